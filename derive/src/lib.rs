@@ -0,0 +1,119 @@
+//! Derive macro of `mm_errors::ErrorCode`.
+//!
+//! Use it through the `derive` feature of `mm-errors`.
+
+extern crate proc_macro;
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{parse_macro_input, Data, DeriveInput, Fields, LitStr};
+
+/// Derives `ErrorCode` and `From<Self> for Error` for an enum.
+///
+/// Each variant takes an optional `#[error_code(...)]` attribute with:
+///
+/// * code - Error code. Defaults to the name of the variant.
+/// * severity - One of `info`, `warning`, `error` and `critical`. Defaults to `error`.
+/// * message - Message of the created errors. Defaults to the registered title of the code.
+///
+#[proc_macro_derive(ErrorCode, attributes(error_code))]
+pub fn derive_error_code(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match expand(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+fn expand(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let data = match input.data {
+        Data::Enum(ref data) => data,
+        _ => return Err(syn::Error::new_spanned(input, "`ErrorCode` can only be derived for enums")),
+    };
+
+    let mut codes = Vec::new();
+    let mut severities = Vec::new();
+    let mut messages = Vec::new();
+    for variant in &data.variants {
+        let name = &variant.ident;
+        let pattern = match variant.fields {
+            Fields::Named(_) => quote!(Self::#name { .. }),
+            Fields::Unnamed(_) => quote!(Self::#name(..)),
+            Fields::Unit => quote!(Self::#name),
+        };
+
+        let mut code = LitStr::new(&name.to_string(), name.span());
+        let mut severity = quote!(Error);
+        let mut message = None;
+        for attr in variant.attrs.iter().filter(|a| a.path().is_ident("error_code")) {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("code") {
+                    code = meta.value()?.parse()?;
+                } else if meta.path.is_ident("severity") {
+                    let s: LitStr = meta.value()?.parse()?;
+                    severity = match s.value().as_str() {
+                        "info" => quote!(Info),
+                        "warning" => quote!(Warning),
+                        "error" => quote!(Error),
+                        "critical" => quote!(Critical),
+                        _ => return Err(meta.error("expected `info`, `warning`, `error` or `critical`")),
+                    };
+                } else if meta.path.is_ident("message") {
+                    let s: LitStr = meta.value()?.parse()?;
+                    message = Some(s);
+                } else {
+                    return Err(meta.error("expected `code`, `severity` or `message`"));
+                }
+                Ok(())
+            })?;
+        }
+
+        codes.push(quote!(#pattern => #code,));
+        severities.push(quote!(#pattern => ::mm_errors::Severity::#severity,));
+        if let Some(message) = message {
+            messages.push(quote!(#pattern => ::std::borrow::Cow::Borrowed(#message),));
+        }
+    }
+
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let message = if messages.is_empty() {
+        quote!()
+    } else {
+        quote! {
+            fn message(&self) -> ::std::borrow::Cow<'static, str> {
+                #[allow(unreachable_patterns)]
+                match *self {
+                    #(#messages)*
+                    _ => ::mm_errors::catalog::default_message(self.code()),
+                }
+            }
+        }
+    };
+
+    Ok(quote! {
+        impl #impl_generics ::mm_errors::ErrorCode for #name #ty_generics #where_clause {
+            fn code(&self) -> &'static str {
+                match *self {
+                    #(#codes)*
+                }
+            }
+
+            fn severity(&self) -> ::mm_errors::Severity {
+                match *self {
+                    #(#severities)*
+                }
+            }
+
+            #message
+        }
+
+        impl #impl_generics ::std::convert::From<#name #ty_generics> for ::mm_errors::Error #where_clause {
+            #[track_caller]
+            fn from(code: #name #ty_generics) -> ::mm_errors::Error {
+                ::mm_errors::Error::from_error_code(&code)
+            }
+        }
+    })
+}
@@ -0,0 +1,91 @@
+//! Errors of command line parsing with clap.
+
+use std::panic;
+
+use clap::error::{ContextKind, ContextValue};
+
+use super::{metadata, Error};
+
+/// Converts a `clap::Error` into an `Error`, created where the conversion is.
+///
+/// The message is the message of clap on a single line, without its `error`
+/// prefix, and the context is kept as metadata:
+///
+/// | Key | Value |
+/// |-----|-------|
+/// | `metadata::ARGUMENT` | Invalid argument or subcommand, if any |
+/// | `metadata::SUGGESTION` | Similar argument, subcommand or value, if any |
+/// | `metadata::HELP` | Tips of clap and the usage, each on a line, as by `Error::with_help` |
+///
+/// so `cli::render` writes the tips and usage as `help` lines. Requests for
+/// `--help` or `--version` are errors of clap too, so pass errors whose
+/// `use_stderr` is `false` to `clap::Error::exit` instead.
+///
+/// This is available with the `clap` feature.
+///
+/// # Examples
+///
+/// ```
+/// use clap::{Arg, Command};
+///
+/// use mm_errors::{cli, metadata, Error};
+///
+/// let command = Command::new("app").arg(Arg::new("color").long("color"));
+/// let e = Error::from(command.try_get_matches_from(["app", "--colour"]).unwrap_err());
+///
+/// assert_eq!(e.metadata.rendered(metadata::ARGUMENT), Some("\"--colour\""));
+/// assert_eq!(e.metadata.rendered(metadata::SUGGESTION), Some("\"--color\""));
+/// assert_eq!(
+///     cli::render_at(&e, false, 1),
+///     "error: unexpected argument '--colour' found\n\
+///      help: a similar argument exists: '--color'\n\
+///      help: usage: app --color <color>");
+/// ```
+///
+impl From<clap::Error> for Error {
+    #[track_caller]
+    fn from(e: clap::Error) -> Error {
+        let location = panic::Location::caller();
+        let rendered = e.render().to_string();
+        let mut paragraphs = rendered.split("\n\n").map(str::trim).filter(|p| !p.is_empty());
+        let message = paragraphs.next().unwrap_or_default();
+        let message = message.strip_prefix("error:").unwrap_or(message);
+        let message = message.split_whitespace().collect::<Vec<_>>().join(" ");
+        let mut help = Vec::new();
+        for line in paragraphs.flat_map(str::lines).map(str::trim) {
+            if let Some(tip) = line.strip_prefix("tip:") {
+                help.push(tip.trim().to_string());
+            }
+        }
+        if let Some(usage) = e.get(ContextKind::Usage) {
+            let usage = usage.to_string();
+            let usage = usage.trim();
+            help.push(format!("usage: {}", usage.strip_prefix("Usage:").unwrap_or(usage).trim()));
+        }
+        let mut error = Error::new(&message, location.file(), location.line());
+        let argument = e.get(ContextKind::InvalidArg).or_else(|| e.get(ContextKind::InvalidSubcommand));
+        if let Some(argument) = argument.and_then(first) {
+            error = error.with_meta_keyed(metadata::ARGUMENT, argument);
+        }
+        let suggestion = [ContextKind::SuggestedArg, ContextKind::SuggestedSubcommand, ContextKind::SuggestedValue]
+            .iter()
+            .filter_map(|&kind| e.get(kind))
+            .find_map(first);
+        if let Some(suggestion) = suggestion {
+            error = error.with_meta_keyed(metadata::SUGGESTION, suggestion);
+        }
+        if !help.is_empty() {
+            error = error.with_help(help.join("\n"));
+        }
+        error
+    }
+}
+
+/// Returns the first string of `value`, if any.
+fn first(value: &ContextValue) -> Option<String> {
+    match value {
+        ContextValue::String(s) => Some(s.clone()),
+        ContextValue::Strings(v) => v.first().cloned(),
+        _ => None,
+    }
+}
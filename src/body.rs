@@ -0,0 +1,156 @@
+//! Bodies of error responses of REST APIs.
+
+use std::fmt;
+use std::fmt::Write;
+
+use super::{json, Error};
+
+type Sanitizer<'a> = Box<dyn Fn(&str) -> Option<String> + 'a>;
+
+/// Builder of the common error body of REST APIs, returned by `Error::error_body`.
+///
+/// The body has the shape
+///
+/// ```json
+/// {"error":{"code":"ORD404","message":"no order 7","details":[],"request_id":"r-42"}}
+/// ```
+///
+/// where `code` is the code of the outermost error having one, `message` the
+/// first message in the chain, and `request_id` is `Error::request_id`, each
+/// `null` if missing. `details` is empty unless enabled with `details`.
+///
+/// Messages often hold internal details, so pass them through `sanitize`
+/// before returning bodies to untrusted clients.
+///
+/// # Examples
+///
+/// ```
+/// use mm_errors::Error;
+///
+/// let e = Error::context(Error::here("connection refused by 10.0.0.7"), "loading order 7", "api.rs", 3)
+///     .with_code("ORD503")
+///     .with_request_id("r-42");
+///
+/// assert_eq!(
+///     e.error_body().to_json(),
+///     "{\"error\":{\"code\":\"ORD503\",\"message\":\"loading order 7\",\"details\":[],\
+///      \"request_id\":\"r-42\"}}");
+///
+/// let body = e.error_body()
+///     .details(true)
+///     .sanitize(|message| if message.contains("10.0.") { None } else { Some(message.to_string()) })
+///     .to_json();
+/// assert_eq!(
+///     body,
+///     "{\"error\":{\"code\":\"ORD503\",\"message\":\"loading order 7\",\
+///      \"details\":[{\"message\":\"internal error\"}],\"request_id\":\"r-42\"}}");
+/// ```
+///
+pub struct ErrorBody<'a> {
+    error: &'a Error,
+    details: bool,
+    sanitizer: Option<Sanitizer<'a>>,
+}
+
+impl<'a> ErrorBody<'a> {
+    /// Returns `self` writing the messages of the inner errors in `details`.
+    ///
+    /// Each detail is an object with the `message` of an inner error, and its
+    /// `code` if it has one. Levels without a message of their own are skipped.
+    ///
+    pub fn details(mut self, enabled: bool) -> ErrorBody<'a> {
+        self.details = enabled;
+        self
+    }
+
+    /// Returns `self` passing every message through `f` before writing it.
+    ///
+    /// `f` returns the message to write, or `None` to write `internal error`
+    /// instead.
+    ///
+    /// # Arguments
+    ///
+    /// * f - Function sanitizing the messages.
+    ///
+    pub fn sanitize<F>(mut self, f: F) -> ErrorBody<'a>
+        where F: Fn(&str) -> Option<String> + 'a {
+        self.sanitizer = Some(Box::new(f));
+        self
+    }
+
+    /// Returns the body as JSON.
+    pub fn to_json(&self) -> String {
+        self.error.mark_reported();
+        let mut out = String::new();
+        self.write(&mut out).expect("writing to a `String` never fails");
+        out
+    }
+
+    fn write(&self, w: &mut dyn Write) -> fmt::Result {
+        let mut levels = self.error.chain().filter_map(|e| match e.downcast_ref::<Error>() {
+            Some(e) => e.message().map(|message| (message.to_string(), e.code.as_deref())),
+            None => Some((e.to_string(), None)),
+        });
+        let code = self.error.chain()
+            .filter_map(|e| e.downcast_ref::<Error>())
+            .find_map(|e| e.code.as_deref());
+        w.write_str("{\"error\":{\"code\":")?;
+        write_optional(w, code)?;
+        w.write_str(",\"message\":")?;
+        let message = levels.next().map(|(message, _)| message).unwrap_or_default();
+        json::write_string(w, &self.sanitized(&message))?;
+        w.write_str(",\"details\":[")?;
+        if self.details {
+            for (i, (message, code)) in levels.enumerate() {
+                if i > 0 {
+                    w.write_char(',')?;
+                }
+                w.write_str("{\"message\":")?;
+                json::write_string(w, &self.sanitized(&message))?;
+                if let Some(code) = code {
+                    w.write_str(",\"code\":")?;
+                    json::write_string(w, code)?;
+                }
+                w.write_char('}')?;
+            }
+        }
+        w.write_str("],\"request_id\":")?;
+        write_optional(w, self.error.request_id())?;
+        w.write_str("}}")
+    }
+
+    fn sanitized(&self, message: &str) -> String {
+        match self.sanitizer {
+            Some(ref f) => f(message).unwrap_or_else(|| "internal error".to_string()),
+            None => message.to_string(),
+        }
+    }
+}
+
+impl<'a> fmt::Debug for ErrorBody<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ErrorBody")
+            .field("error", self.error)
+            .field("details", &self.details)
+            .field("sanitized", &self.sanitizer.is_some())
+            .finish()
+    }
+}
+
+/// Writes `s` as a JSON string, or `null` if it is `None`.
+fn write_optional(w: &mut dyn Write, s: Option<&str>) -> fmt::Result {
+    match s {
+        Some(s) => json::write_string(w, s),
+        None => w.write_str("null"),
+    }
+}
+
+impl Error {
+    /// Returns a builder of the REST error body of `self`.
+    ///
+    /// See `ErrorBody` for the format.
+    ///
+    pub fn error_body(&self) -> ErrorBody<'_> {
+        ErrorBody { error: self, details: false, sanitizer: None }
+    }
+}
@@ -0,0 +1,190 @@
+//! Classifying failures for circuit breakers.
+//!
+//! A circuit breaker counts failures of calls to a dependency and stops calling
+//! it when too many fail. Not every error should count: a cancelled call or a
+//! rejected request says nothing about the health of the dependency. This module
+//! classifies errors for such resilience layers, and counts the outcomes.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use super::retry::RetryClass;
+use super::{Error, Result, Severity};
+
+/// How a failed call counts towards a circuit breaker.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FailureClass {
+    /// The dependency failed, and the failure counts towards opening the circuit.
+    Failure,
+
+    /// The call succeeded with a warning, e.g. a degraded response.
+    Warning,
+
+    /// The failure says nothing about the dependency, e.g. a cancelled call.
+    Ignore,
+}
+
+/// Classifies errors into `FailureClass`es.
+///
+/// Any `Fn(&Error) -> FailureClass` is a `Classify`.
+///
+/// # Examples
+///
+/// ```
+/// use mm_errors::Error;
+/// use mm_errors::breaker::{Classify, FailureClass};
+///
+/// let classifier = |e: &Error| if e.has_code("DB001") { FailureClass::Failure } else { FailureClass::Ignore };
+///
+/// assert_eq!(classifier.classify(&Error::here("down").with_code("DB001")), FailureClass::Failure);
+/// assert_eq!(classifier.classify(&Error::here("bad query")), FailureClass::Ignore);
+/// ```
+///
+pub trait Classify {
+    /// Returns the class of `e`.
+    ///
+    /// # Arguments
+    ///
+    /// * e - Error returned by the call.
+    ///
+    fn classify(&self, e: &Error) -> FailureClass;
+}
+
+impl<F> Classify for F
+    where F: Fn(&Error) -> FailureClass {
+    fn classify(&self, e: &Error) -> FailureClass {
+        self(e)
+    }
+}
+
+/// Classifier using what the error itself records.
+///
+/// Errors are classified as follows, in this order:
+///
+/// * Cancelled errors are `Ignore`.
+/// * Errors with the retry class `RetryClass::Permanent` are `Ignore`, since
+///   they are caused by the request rather than the dependency.
+/// * Errors with the severity `Severity::Info` or `Severity::Warning` are `Warning`.
+/// * Other errors are `Failure`.
+///
+/// # Examples
+///
+/// ```
+/// use mm_errors::{Error, Severity};
+/// use mm_errors::breaker::{Classify, DefaultClassifier, FailureClass};
+/// use mm_errors::retry::RetryClass;
+///
+/// assert_eq!(DefaultClassifier.classify(&Error::here("timed out")), FailureClass::Failure);
+///
+/// let e = Error::here("invalid id").with_retry_class(RetryClass::Permanent);
+/// assert_eq!(DefaultClassifier.classify(&e), FailureClass::Ignore);
+///
+/// let e = Error::here("served from cache").with_severity(Severity::Warning);
+/// assert_eq!(DefaultClassifier.classify(&e), FailureClass::Warning);
+/// ```
+///
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DefaultClassifier;
+
+impl Classify for DefaultClassifier {
+    fn classify(&self, e: &Error) -> FailureClass {
+        if e.is_cancelled() || e.retry_class() == Some(RetryClass::Permanent) {
+            return FailureClass::Ignore;
+        }
+        match e.severity() {
+            Severity::Info | Severity::Warning => FailureClass::Warning,
+            Severity::Error | Severity::Critical => FailureClass::Failure,
+        }
+    }
+}
+
+/// Counts the outcomes of calls per class.
+///
+/// The counters can be shared between threads, e.g. in an `Arc`.
+///
+/// # Examples
+///
+/// ```
+/// use mm_errors::Error;
+/// use mm_errors::breaker::{Counters, DefaultClassifier, FailureClass};
+///
+/// let counters = Counters::new();
+/// counters.record(&DefaultClassifier, &Ok::<u32, Error>(1));
+/// counters.record(&DefaultClassifier, &Err::<u32, Error>(Error::here("timed out")));
+/// counters.record(&DefaultClassifier, &Err::<u32, Error>(Error::cancelled("shutdown", file!(), line!())));
+///
+/// assert_eq!(counters.successes(), 1);
+/// assert_eq!(counters.count(FailureClass::Failure), 1);
+/// assert_eq!(counters.count(FailureClass::Ignore), 1);
+/// ```
+///
+#[derive(Debug, Default)]
+pub struct Counters {
+    successes: AtomicU64,
+    failures: AtomicU64,
+    warnings: AtomicU64,
+    ignored: AtomicU64,
+}
+
+impl Counters {
+    /// Returns a new instance of `Counters` with every count zero.
+    pub fn new() -> Counters {
+        Counters::default()
+    }
+
+    /// Counts the outcome `r` of a call, and returns the class of its error.
+    ///
+    /// # Arguments
+    ///
+    /// * classifier - Classifier of the error.
+    /// * r - Outcome of the call.
+    ///
+    pub fn record<C, T>(&self, classifier: &C, r: &Result<T>) -> Option<FailureClass>
+        where C: Classify + ?Sized {
+        match *r {
+            Ok(_) => {
+                self.record_success();
+                None
+            }
+            Err(ref e) => {
+                let class = classifier.classify(e);
+                self.record_class(class);
+                Some(class)
+            }
+        }
+    }
+
+    /// Counts a successful call.
+    pub fn record_success(&self) {
+        self.successes.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Counts a failed call of class `class`.
+    pub fn record_class(&self, class: FailureClass) {
+        self.counter(class).fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Returns the number of successful calls.
+    pub fn successes(&self) -> u64 {
+        self.successes.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of failed calls of class `class`.
+    pub fn count(&self, class: FailureClass) -> u64 {
+        self.counter(class).load(Ordering::Relaxed)
+    }
+
+    /// Sets every count to zero, e.g. when the circuit closes again.
+    pub fn reset(&self) {
+        for counter in &[&self.successes, &self.failures, &self.warnings, &self.ignored] {
+            counter.store(0, Ordering::Relaxed);
+        }
+    }
+
+    fn counter(&self, class: FailureClass) -> &AtomicU64 {
+        match class {
+            FailureClass::Failure => &self.failures,
+            FailureClass::Warning => &self.warnings,
+            FailureClass::Ignore => &self.ignored,
+        }
+    }
+}
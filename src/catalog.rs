@@ -0,0 +1,979 @@
+//! Registry of error codes.
+//!
+//! Applications register each of their error codes once, with a title, a
+//! message template, a severity and a documentation URL. `Error::from_code`
+//! then creates consistent errors from the codes, and `lookup` lets tooling
+//! describe any code.
+
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::fmt::Write;
+use std::fs;
+use std::panic;
+use std::path::Path;
+use std::sync::RwLock;
+
+use super::retry::RetryClass;
+use super::{hooks, json, metadata, Error, ErrorKind, Result};
+
+/// How serious a failure is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub enum Severity {
+    /// Expected condition worth noting, e.g. a validation failure.
+    Info,
+
+    /// Degraded operation which recovered or can be ignored.
+    Warning,
+
+    /// Failed operation.
+    #[default]
+    Error,
+
+    /// Failure requiring immediate attention, e.g. data loss.
+    Critical,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Severity::Info => "info",
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+            Severity::Critical => "critical",
+        })
+    }
+}
+
+/// Description of a registered error code.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CodeInfo {
+    /// Error code, e.g. `"CFG001"`.
+    pub code: Cow<'static, str>,
+
+    /// Short description of the failure.
+    pub title: Cow<'static, str>,
+
+    /// Template of the message of errors created by `Error::from_code`.
+    ///
+    /// `{name}` is replaced with the argument `name`. The title is used if this is `None`.
+    pub message: Option<Cow<'static, str>>,
+
+    /// Severity of the failure.
+    pub severity: Severity,
+
+    /// URL of the documentation of the code.
+    pub docs_url: Option<Cow<'static, str>>,
+
+    /// Stable number of the code, for FFI, exit codes and binary protocols.
+    ///
+    /// Each number belongs to one code only.
+    pub number: Option<u32>,
+
+    /// Code replacing this deprecated code.
+    pub replaced_by: Option<Cow<'static, str>>,
+
+    /// HTTP status code of responses for the failure, e.g. 404.
+    pub http_status: Option<u16>,
+
+    /// gRPC status code of responses for the failure, e.g. 5 for `NOT_FOUND`.
+    pub grpc_code: Option<u32>,
+
+    /// Whether operations failing with the code may be retried.
+    pub retry_class: Option<RetryClass>,
+}
+
+impl CodeInfo {
+    /// Returns a new instance of `CodeInfo` with the severity `Severity::Error`.
+    ///
+    /// # Arguments
+    ///
+    /// * code - Error code.
+    /// * title - Short description of the failure.
+    ///
+    pub fn new<C, T>(code: C, title: T) -> CodeInfo
+        where C: Into<Cow<'static, str>>,
+              T: Into<Cow<'static, str>> {
+        CodeInfo {
+            code: code.into(),
+            title: title.into(),
+            message: None,
+            severity: Severity::default(),
+            docs_url: None,
+            number: None,
+            replaced_by: None,
+            http_status: None,
+            grpc_code: None,
+            retry_class: None,
+        }
+    }
+
+    /// Returns `self` with the message template set.
+    pub fn message<M: Into<Cow<'static, str>>>(mut self, template: M) -> CodeInfo {
+        self.message = Some(template.into());
+        self
+    }
+
+    /// Returns `self` with the severity set.
+    pub fn severity(mut self, severity: Severity) -> CodeInfo {
+        self.severity = severity;
+        self
+    }
+
+    /// Returns `self` with the documentation URL set.
+    pub fn docs_url<U: Into<Cow<'static, str>>>(mut self, url: U) -> CodeInfo {
+        self.docs_url = Some(url.into());
+        self
+    }
+
+    /// Returns `self` with the number set.
+    pub fn number(mut self, number: u32) -> CodeInfo {
+        self.number = Some(number);
+        self
+    }
+
+    /// Returns `self` with the HTTP status code set.
+    pub fn http_status(mut self, status: u16) -> CodeInfo {
+        self.http_status = Some(status);
+        self
+    }
+
+    /// Returns `self` with the gRPC status code set.
+    pub fn grpc_code(mut self, code: u32) -> CodeInfo {
+        self.grpc_code = Some(code);
+        self
+    }
+
+    /// Returns `self` with the retry class set.
+    pub fn retry_class(mut self, class: RetryClass) -> CodeInfo {
+        self.retry_class = Some(class);
+        self
+    }
+
+    /// Returns `self` marked as deprecated in favor of `replacement`.
+    ///
+    /// Setting a deprecated code on an error emits a warning through
+    /// `hooks::set_warning_hook`, once per code. The error keeps the deprecated
+    /// code, so consumers matching on it keep working; match with
+    /// `Error::has_code` to accept both codes.
+    ///
+    pub fn deprecated<R: Into<Cow<'static, str>>>(mut self, replacement: R) -> CodeInfo {
+        self.replaced_by = Some(replacement.into());
+        self
+    }
+
+    /// Returns the message template with `{name}` replaced by the argument `name`.
+    ///
+    /// Placeholders without an argument are left as they are. The template is
+    /// scanned once, so placeholders in the arguments are not replaced.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mm_errors::catalog::CodeInfo;
+    ///
+    /// let info = CodeInfo::new("USR001", "Invalid user").message("user `{0}` is not in group `{1}`");
+    ///
+    /// assert_eq!(info.format_message(&[("0", &"{1}"), ("1", &"admin")]),
+    ///            "user `{1}` is not in group `admin`");
+    /// assert_eq!(info.format_message(&[("0", &"alice")]), "user `alice` is not in group `{1}`");
+    /// ```
+    ///
+    pub fn format_message(&self, args: &[(&str, &dyn fmt::Display)]) -> String {
+        let template = self.message.as_ref().unwrap_or(&self.title);
+        fill_template(template, |name| {
+            args.iter().find(|&&(n, _)| n == name).map(|(_, value)| value.to_string())
+        })
+    }
+}
+
+/// Returns `template` with each `{name}` replaced by `value(name)`, scanning it once.
+///
+/// Placeholders for which `value` returns `None` are left as they are.
+///
+fn fill_template<F>(template: &str, value: F) -> String
+    where F: Fn(&str) -> Option<String> {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        match after.find('}') {
+            Some(end) => {
+                match value(&after[..end]) {
+                    Some(value) => out.push_str(&value),
+                    None => out.push_str(&rest[start..start + end + 2]),
+                }
+                rest = &after[end + 1..];
+            }
+            None => {
+                out.push_str(&rest[start..]);
+                rest = "";
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+struct Catalog {
+    codes: BTreeMap<String, CodeInfo>,
+    numbers: BTreeMap<u32, String>,
+
+    /// Deprecated codes which were already warned about.
+    warned: BTreeSet<String>,
+
+    /// Message templates by code and locale.
+    translations: BTreeMap<(String, String), String>,
+
+    /// Labels of human-readable output by locale.
+    labels: BTreeMap<(Label, String), String>,
+}
+
+static CATALOG: RwLock<Catalog> = RwLock::new(Catalog {
+    codes: BTreeMap::new(),
+    numbers: BTreeMap::new(),
+    warned: BTreeSet::new(),
+    translations: BTreeMap::new(),
+    labels: BTreeMap::new(),
+});
+
+static LOCALE: RwLock<Option<String>> = RwLock::new(None);
+
+/// Registers the message template of `code` in `locale`, e.g. `"ja"` or `"pt-BR"`.
+///
+/// The template has the same placeholders as `CodeInfo::message`, filled from
+/// the arguments given to `Error::from_code`. Translated messages are used by
+/// `Error::report` only, so logs written from `Display` keep the canonical message.
+///
+/// # Arguments
+///
+/// * code - Error code.
+/// * locale - Locale of the template.
+/// * template - Translated message template.
+///
+/// # Examples
+///
+/// ```
+/// use mm_errors::Error;
+/// use mm_errors::catalog::{self, CodeInfo};
+///
+/// catalog::register(CodeInfo::new("FS001", "File not found").message("`{path}` not found")).unwrap();
+/// catalog::register_translation("FS001", "ja", "`{path}` が見つかりません");
+///
+/// let e = Error::from_code("FS001", &[("path", &"a.txt")]);
+/// assert_eq!(e.report().to_string(), "error[FS001]: `a.txt` not found");
+///
+/// catalog::set_locale(Some("ja-JP"));
+/// assert_eq!(e.report().to_string(), "error[FS001]: `a.txt` が見つかりません");
+/// assert!(e.to_string().contains("<reason>`a.txt` not found</reason>"));
+/// # catalog::set_locale(None);
+/// ```
+///
+pub fn register_translation(code: &str, locale: &str, template: &str) {
+    let mut catalog = CATALOG.write().unwrap_or_else(|e| e.into_inner());
+    catalog.translations.insert((code.to_string(), locale.to_string()), template.to_string());
+}
+
+/// Sets the locale of translated messages, or `None` for the canonical messages.
+///
+/// A locale with a region, e.g. `pt-BR`, falls back to its language, e.g. `pt`.
+/// The setting is global and applies to all threads.
+///
+pub fn set_locale(locale: Option<&str>) {
+    *LOCALE.write().unwrap_or_else(|e| e.into_inner()) = locale.map(str::to_string);
+}
+
+/// Returns the locale of translated messages.
+pub fn locale() -> Option<String> {
+    LOCALE.read().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+/// Returns the message template of `code` in the current locale.
+fn translation(code: &str) -> Option<String> {
+    let locale = locale()?;
+    let catalog = CATALOG.read().unwrap_or_else(|e| e.into_inner());
+    fallbacks(&locale).into_iter()
+        .find_map(|l| catalog.translations.get(&(code.to_string(), l.to_string())))
+        .cloned()
+}
+
+/// Returns `locale` followed by its language, if it has a region.
+fn fallbacks(locale: &str) -> Vec<&str> {
+    let mut candidates = vec![locale];
+    if let Some(i) = locale.find(['-', '_']) {
+        candidates.push(&locale[..i]);
+    }
+    candidates
+}
+
+/// Boilerplate of human-readable output, translated with `register_label`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Label {
+    /// Prefix of the first line, `error`.
+    Error,
+
+    /// Prefix of the lines of inner errors, `caused by`.
+    CausedBy,
+
+    /// Prefix of hints in the output of `cli::render`, `help`.
+    Help,
+
+    /// Text preceding documentation URLs, `for more information, see`.
+    MoreInformation,
+}
+
+impl Label {
+    /// Returns the English text of `self`.
+    pub fn text(self) -> &'static str {
+        match self {
+            Label::Error => "error",
+            Label::CausedBy => "caused by",
+            Label::Help => "help",
+            Label::MoreInformation => "for more information, see",
+        }
+    }
+}
+
+/// Registers the text of `label` in `locale`, e.g. `"ja"` or `"pt-BR"`.
+///
+/// Labels are translated to the locale set by `set_locale` in `Error::report`
+/// and `cli::render`, as messages are, and fall back to English.
+///
+/// # Arguments
+///
+/// * label - Label to translate.
+/// * locale - Locale of the text.
+/// * text - Translated text.
+///
+/// # Examples
+///
+/// ```
+/// use mm_errors::{cli, Error};
+/// use mm_errors::catalog::{self, Label};
+///
+/// catalog::register_label(Label::Error, "de", "Fehler");
+/// catalog::register_label(Label::CausedBy, "de", "verursacht durch");
+/// catalog::register_label(Label::Help, "de", "Hilfe");
+///
+/// let e = Error::context(Error::here("disk full"), "saving the file", "save.rs", 3)
+///     .with_help("free some space");
+///
+/// catalog::set_locale(Some("de-AT"));
+/// assert_eq!(
+///     cli::render_at(&e, false, 1),
+///     "Fehler: saving the file\n  \
+///      verursacht durch: disk full\n\
+///      Hilfe: free some space");
+/// # catalog::set_locale(None);
+/// ```
+///
+pub fn register_label(label: Label, locale: &str, text: &str) {
+    let mut catalog = CATALOG.write().unwrap_or_else(|e| e.into_inner());
+    catalog.labels.insert((label, locale.to_string()), text.to_string());
+}
+
+/// Returns the text of `label` in the current locale.
+pub(crate) fn label(label: Label) -> Cow<'static, str> {
+    let locale = match locale() {
+        Some(locale) => locale,
+        None => return Cow::Borrowed(label.text()),
+    };
+    let catalog = CATALOG.read().unwrap_or_else(|e| e.into_inner());
+    fallbacks(&locale).into_iter()
+        .find_map(|l| catalog.labels.get(&(label, l.to_string())))
+        .map_or(Cow::Borrowed(label.text()), |text| Cow::Owned(text.clone()))
+}
+
+static DOCS_URL_TEMPLATE: RwLock<Option<String>> = RwLock::new(None);
+
+/// Sets the template of the documentation URL of codes without their own.
+///
+/// `{code}` in the template is replaced by the code. The setting is global
+/// and applies to all threads.
+///
+/// # Arguments
+///
+/// * template - URL template, or `None` for no URL.
+///
+/// # Examples
+///
+/// ```
+/// use mm_errors::{catalog, Error};
+///
+/// catalog::set_docs_url_template(Some("https://errors.example.com/{code}"));
+/// assert_eq!(catalog::docs_url("NET042").as_deref(), Some("https://errors.example.com/NET042"));
+///
+/// let e = Error::here("connection refused").with_code("NET042");
+/// assert_eq!(
+///     e.report().to_string(),
+///     "error[NET042]: connection refused\n\
+///      for more information, see https://errors.example.com/NET042");
+/// # catalog::set_docs_url_template(None);
+/// ```
+///
+pub fn set_docs_url_template(template: Option<&str>) {
+    *DOCS_URL_TEMPLATE.write().unwrap_or_else(|e| e.into_inner()) = template.map(str::to_string);
+}
+
+/// Returns the documentation URL of `code`.
+///
+/// This is the URL registered for `code`, or else the URL from the template set
+/// by `set_docs_url_template`, which applies to unregistered codes as well.
+///
+pub fn docs_url(code: &str) -> Option<String> {
+    if let Some(url) = lookup(code).and_then(|info| info.docs_url) {
+        return Some(url.into_owned());
+    }
+    let template = DOCS_URL_TEMPLATE.read().unwrap_or_else(|e| e.into_inner());
+    template.as_ref().map(|t| t.replace("{code}", code))
+}
+
+/// Registers `info`.
+///
+/// # Errors
+///
+/// Returns an error if the code is already registered, or if its number is
+/// already registered for another code, so codes and numbers map one-to-one.
+///
+/// # Examples
+///
+/// ```
+/// use mm_errors::Error;
+/// use mm_errors::catalog::{self, CodeInfo, Severity};
+///
+/// catalog::register(
+///     CodeInfo::new("CFG001", "Invalid configuration")
+///         .message("invalid value `{value}` for `{key}`")
+///         .severity(Severity::Critical)
+///         .docs_url("https://errors.example.com/CFG001")
+///         .number(1001)).unwrap();
+///
+/// let e = Error::from_code("CFG001", &[("key", &"port"), ("value", &"XXX")]);
+/// assert_eq!(e.code.as_deref(), Some("CFG001"));
+/// assert!(e.to_string().contains("<reason>invalid value `XXX` for `port`</reason>"));
+/// assert_eq!(e.severity(), Severity::Critical);
+/// assert_eq!(e.code_number(), Some(1001));
+///
+/// assert_eq!(catalog::lookup("CFG001").unwrap().title, "Invalid configuration");
+/// assert_eq!(catalog::code_of(1001).as_deref(), Some("CFG001"));
+/// assert!(catalog::register(CodeInfo::new("CFG001", "Duplicate")).is_err());
+/// assert!(catalog::register(CodeInfo::new("CFG002", "Same number").number(1001)).is_err());
+/// ```
+///
+pub fn register(info: CodeInfo) -> Result<()> {
+    let mut catalog = CATALOG.write().unwrap_or_else(|e| e.into_inner());
+    let conflict = if catalog.codes.contains_key(&*info.code) {
+        Some(format!("error code `{}` is already registered", info.code))
+    } else {
+        info.number.and_then(|number| {
+            catalog.numbers.get(&number).map(|other| {
+                format!("error number {} of `{}` is already registered for `{}`", number, info.code, other)
+            })
+        })
+    };
+    if conflict.is_none() {
+        if let Some(number) = info.number {
+            catalog.numbers.insert(number, info.code.to_string());
+        }
+        catalog.codes.insert(info.code.to_string(), info);
+    }
+    // `Error::new` runs the metadata providers, which may look up codes.
+    drop(catalog);
+    match conflict {
+        Some(message) => Err(Error::new(&message, file!(), line!())),
+        None => Ok(()),
+    }
+}
+
+/// Returns the description of `code`, if registered.
+pub fn lookup(code: &str) -> Option<CodeInfo> {
+    CATALOG.read().unwrap_or_else(|e| e.into_inner()).codes.get(code).cloned()
+}
+
+/// Returns the descriptions of every registered code, sorted by code.
+pub fn entries() -> Vec<CodeInfo> {
+    CATALOG.read().unwrap_or_else(|e| e.into_inner()).codes.values().cloned().collect()
+}
+
+/// Format of `export`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// JSON array of objects with the fields of `CodeInfo`, `null` for missing ones.
+    Json,
+
+    /// Markdown with a section for each code.
+    Markdown,
+}
+
+/// Returns every registered code, sorted by code, in `format`.
+///
+/// Use it to generate an error reference which always matches the code, e.g.
+/// from a test or a build script which registers the codes and writes the
+/// result with `write_export`.
+///
+/// # Examples
+///
+/// ```
+/// use mm_errors::catalog::{self, CodeInfo, ExportFormat, Severity};
+///
+/// catalog::register(CodeInfo::new("NET001", "Connection refused").severity(Severity::Warning)).unwrap();
+///
+/// let json = catalog::export(ExportFormat::Json);
+/// assert!(json.contains("{\"code\":\"NET001\",\"title\":\"Connection refused\",\"message\":null,\
+///                        \"severity\":\"warning\",\"docs_url\":null,\"number\":null,\"replaced_by\":null,\
+///                        \"http_status\":null,\"grpc_code\":null,\"retry_class\":null}"));
+///
+/// let markdown = catalog::export(ExportFormat::Markdown);
+/// assert!(markdown.contains("## NET001: Connection refused\n\n* Severity: warning\n"));
+/// ```
+///
+pub fn export(format: ExportFormat) -> String {
+    let mut out = String::new();
+    let entries = entries();
+    match format {
+        ExportFormat::Json => {
+            // Writing to a `String` never fails.
+            let _ = write_json(&mut out, &entries);
+        }
+        ExportFormat::Markdown => {
+            out.push_str("# Error codes\n");
+            for info in &entries {
+                out.push_str(&format!("\n## {}: {}\n\n", info.code, info.title));
+                out.push_str(&format!("* Severity: {}\n", info.severity));
+                if let Some(number) = info.number {
+                    out.push_str(&format!("* Number: {}\n", number));
+                }
+                if let Some(ref message) = info.message {
+                    out.push_str(&format!("* Message: `{}`\n", message));
+                }
+                if let Some(status) = info.http_status {
+                    out.push_str(&format!("* HTTP status: {}\n", status));
+                }
+                if let Some(code) = info.grpc_code {
+                    out.push_str(&format!("* gRPC code: {}\n", code));
+                }
+                if let Some(class) = info.retry_class {
+                    out.push_str(&format!("* Retry: {}\n", class));
+                }
+                if let Some(ref replacement) = info.replaced_by {
+                    out.push_str(&format!("* Deprecated: use {} instead\n", replacement));
+                }
+                if let Some(url) = docs_url(&info.code) {
+                    out.push_str(&format!("* Documentation: <{}>\n", url));
+                }
+            }
+        }
+    }
+    out
+}
+
+fn write_json(out: &mut String, entries: &[CodeInfo]) -> fmt::Result {
+    fn write_opt(out: &mut String, v: Option<&str>) -> fmt::Result {
+        match v {
+            Some(v) => json::write_string(out, v),
+            None => out.write_str("null"),
+        }
+    }
+
+    out.write_str("[")?;
+    for (i, info) in entries.iter().enumerate() {
+        if i > 0 {
+            out.write_str(",")?;
+        }
+        out.write_str("{\"code\":")?;
+        json::write_string(out, &info.code)?;
+        out.write_str(",\"title\":")?;
+        json::write_string(out, &info.title)?;
+        out.write_str(",\"message\":")?;
+        write_opt(out, info.message.as_deref())?;
+        write!(out, ",\"severity\":\"{}\",\"docs_url\":", info.severity)?;
+        write_opt(out, info.docs_url.as_deref())?;
+        match info.number {
+            Some(n) => write!(out, ",\"number\":{}", n)?,
+            None => out.write_str(",\"number\":null")?,
+        }
+        out.write_str(",\"replaced_by\":")?;
+        write_opt(out, info.replaced_by.as_deref())?;
+        match info.http_status {
+            Some(status) => write!(out, ",\"http_status\":{}", status)?,
+            None => out.write_str(",\"http_status\":null")?,
+        }
+        match info.grpc_code {
+            Some(code) => write!(out, ",\"grpc_code\":{}", code)?,
+            None => out.write_str(",\"grpc_code\":null")?,
+        }
+        match info.retry_class {
+            Some(class) => write!(out, ",\"retry_class\":\"{}\"", class)?,
+            None => out.write_str(",\"retry_class\":null")?,
+        }
+        out.write_str("}")?;
+    }
+    out.write_str("]")
+}
+
+/// Writes `export(format)` to `path`, e.g. from a build script.
+///
+/// A build script using this depends on `mm-errors` as a build dependency, and
+/// registers the codes itself, e.g. by calling a registration function shared
+/// with the application through a module included with `include!`.
+///
+/// # Errors
+///
+/// Returns an error if the file can't be written.
+///
+pub fn write_export<P: AsRef<Path>>(path: P, format: ExportFormat) -> Result<()> {
+    let path = path.as_ref();
+    fs::write(path, export(format))
+        .map_err(|e| Error::context(e, &format!("writing {}", path.display()), file!(), line!()))
+}
+
+/// Returns the code replacing `code`, following replacements of deprecated codes,
+/// or `code` itself if it is not deprecated.
+///
+/// # Examples
+///
+/// ```
+/// use mm_errors::{catalog, hooks, Error};
+/// use mm_errors::catalog::CodeInfo;
+///
+/// hooks::set_warning_hook(|_: &str| ());
+/// catalog::register(CodeInfo::new("DB001", "Query failed").deprecated("DB100")).unwrap();
+/// catalog::register(CodeInfo::new("DB100", "Query failed")).unwrap();
+///
+/// assert_eq!(catalog::canonical_code("DB001"), "DB100");
+///
+/// let e = Error::here("query failed").with_code("DB001");
+/// assert_eq!(e.code.as_deref(), Some("DB001"));
+/// assert!(e.has_code("DB001"));
+/// assert!(e.has_code("DB100"));
+/// # hooks::reset_warning_hook();
+/// ```
+///
+pub fn canonical_code(code: &str) -> String {
+    let catalog = CATALOG.read().unwrap_or_else(|e| e.into_inner());
+    let mut code = code.to_string();
+    // Bounded, in case of a cycle of replacements.
+    for _ in 0..catalog.codes.len() {
+        match catalog.codes.get(&code).and_then(|info| info.replaced_by.as_ref()) {
+            Some(replacement) => code = replacement.to_string(),
+            None => break,
+        }
+    }
+    code
+}
+
+/// Warns once if `code` is deprecated.
+pub(crate) fn check_deprecated(code: &str) {
+    let replacement = {
+        let catalog = CATALOG.read().unwrap_or_else(|e| e.into_inner());
+        match catalog.codes.get(code).and_then(|info| info.replaced_by.clone()) {
+            Some(replacement) if !catalog.warned.contains(code) => replacement,
+            _ => return,
+        }
+    };
+    let first = CATALOG.write().unwrap_or_else(|e| e.into_inner()).warned.insert(code.to_string());
+    if first {
+        hooks::warn(&format!("error code `{}` is deprecated; use `{}` instead", code, replacement));
+    }
+}
+
+/// Returns the code registered with `number`.
+pub fn code_of(number: u32) -> Option<String> {
+    CATALOG.read().unwrap_or_else(|e| e.into_inner()).numbers.get(&number).cloned()
+}
+
+/// Returns the number registered for `code`.
+pub fn number_of(code: &str) -> Option<u32> {
+    lookup(code).and_then(|info| info.number)
+}
+
+/// Returns the default message of errors with `code`: its registered title, or else the code itself.
+#[doc(hidden)]
+pub fn default_message(code: &str) -> Cow<'static, str> {
+    match lookup(code) {
+        Some(info) => info.title,
+        None => Cow::Owned(code.to_string()),
+    }
+}
+
+/// Enum of error codes.
+///
+/// With the `derive` feature, `#[derive(ErrorCode)]` implements this trait and
+/// `From<T> for Error`, so an application defines its code space as a plain enum.
+///
+/// # Examples
+///
+/// ```
+/// use std::borrow::Cow;
+///
+/// use mm_errors::{Error, ErrorCode, Severity};
+///
+/// enum StorageError {
+///     DiskFull,
+/// }
+///
+/// impl ErrorCode for StorageError {
+///     fn code(&self) -> &'static str {
+///         "STO001"
+///     }
+///
+///     fn severity(&self) -> Severity {
+///         Severity::Critical
+///     }
+///
+///     fn message(&self) -> Cow<'static, str> {
+///         Cow::Borrowed("disk full")
+///     }
+/// }
+///
+/// let e = Error::from_error_code(&StorageError::DiskFull);
+/// assert_eq!(e.code.as_deref(), Some("STO001"));
+/// assert_eq!(e.severity(), Severity::Critical);
+/// assert_eq!(e.message(), Some("disk full"));
+/// ```
+///
+pub trait ErrorCode {
+    /// Returns the error code.
+    fn code(&self) -> &'static str;
+
+    /// Returns the severity of the failure.
+    fn severity(&self) -> Severity {
+        Severity::Error
+    }
+
+    /// Returns the message of errors created from `self`.
+    ///
+    /// The default is the title registered in the catalog, or else the code.
+    fn message(&self) -> Cow<'static, str> {
+        default_message(self.code())
+    }
+}
+
+impl Error {
+    /// Returns a new instance of `Error` for `code`, located at the caller.
+    ///
+    /// The error has the code, message and severity of `code`.
+    ///
+    /// # Arguments
+    ///
+    /// * code - Error code.
+    ///
+    #[track_caller]
+    pub fn from_error_code<C: ErrorCode + ?Sized>(code: &C) -> Error {
+        let location = panic::Location::caller();
+        Error::new(&code.message(), location.file(), location.line())
+            .with_code(code.code())
+            .with_severity(code.severity())
+    }
+
+    /// Returns `self` with its severity set, overriding the severity of its code.
+    ///
+    /// The severity is attached under `metadata::SEVERITY`.
+    ///
+    pub fn with_severity(self, severity: Severity) -> Error {
+        self.with_meta_keyed(metadata::SEVERITY, severity)
+    }
+
+    /// Returns a new instance of `Error` for the registered `code`, located at the caller.
+    ///
+    /// The message is the message template of the code with `args` substituted,
+    /// and every argument is also attached as a `String` under its name. If
+    /// `code` is not registered, the message is the code itself.
+    ///
+    /// # Arguments
+    ///
+    /// * code - Registered error code.
+    /// * args - Names and values of the arguments of the message template.
+    ///
+    #[track_caller]
+    pub fn from_code(code: &'static str, args: &[(&str, &dyn fmt::Display)]) -> Error {
+        let location = panic::Location::caller();
+        let message = match lookup(code) {
+            Some(info) => info.format_message(args),
+            None => code.to_string(),
+        };
+        let mut e = Error::new(&message, location.file(), location.line()).with_code(code);
+        for (name, value) in args {
+            e.metadata.insert_keyed(name.to_string(), value.to_string());
+        }
+        e
+    }
+
+    /// Returns `true` if the code of `self` is `code`, or replaces or is replaced by `code`.
+    ///
+    /// See `canonical_code`.
+    ///
+    pub fn has_code(&self, code: &str) -> bool {
+        match self.code {
+            Some(ref own) => own == code || canonical_code(own) == canonical_code(code),
+            None => false,
+        }
+    }
+
+    /// Returns the message of `self` translated to the locale set by `set_locale`.
+    ///
+    /// Placeholders of the translated template are filled from the `String`
+    /// values attached under their names, as attached by `from_code`. Returns
+    /// `None` if no locale is set, `self` has no code, or the code has no
+    /// template in the locale.
+    ///
+    pub fn localized_message(&self) -> Option<String> {
+        let template = translation(self.code.as_ref()?)?;
+        Some(fill_template(&template, |name| self.metadata.get_keyed::<String>(name).cloned()))
+    }
+
+    /// Returns the HTTP status code registered for the outermost code in the chain having one.
+    ///
+    /// Integrations building responses from errors use this, so the mapping of
+    /// codes to statuses lives in the catalog only.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mm_errors::Error;
+    /// use mm_errors::catalog::{self, CodeInfo};
+    ///
+    /// catalog::register(CodeInfo::new("ORD404", "Order not found").http_status(404).grpc_code(5)).unwrap();
+    ///
+    /// let e = Error::wrap(Error::here("no such order").with_code("ORD404"), "api.rs", 1);
+    /// assert_eq!(e.http_status(), Some(404));
+    /// assert_eq!(e.grpc_code(), Some(5));
+    /// ```
+    ///
+    pub fn http_status(&self) -> Option<u16> {
+        self.find_code_info(|info| info.http_status)
+    }
+
+    /// Returns the gRPC status code registered for the outermost code in the chain having one.
+    pub fn grpc_code(&self) -> Option<u32> {
+        self.find_code_info(|info| info.grpc_code)
+    }
+
+    fn find_code_info<T, F>(&self, f: F) -> Option<T>
+        where F: Fn(&CodeInfo) -> Option<T> {
+        self.chain()
+            .filter_map(|e| e.downcast_ref::<Error>())
+            .filter_map(|e| e.code.as_ref())
+            .find_map(|code| lookup(code).and_then(|info| f(&info)))
+    }
+
+    /// Returns the number of the code of `self` in the catalog.
+    pub fn code_number(&self) -> Option<u32> {
+        self.code.as_ref().and_then(|code| number_of(code))
+    }
+
+    /// Returns the severity of `self`.
+    ///
+    /// This is the severity set by `with_severity`, or else the severity of the
+    /// code of `self` in the catalog, or else `Severity::Error`.
+    ///
+    pub fn severity(&self) -> Severity {
+        if let Some(severity) = self.metadata.get_keyed::<Severity>(metadata::SEVERITY) {
+            return *severity;
+        }
+        self.code.as_ref().and_then(|code| lookup(code)).map(|info| info.severity).unwrap_or_default()
+    }
+
+    /// Returns a copy of `self` safe to return to untrusted callers.
+    ///
+    /// The copy holds the code of the outermost error having one, the request
+    /// ID of `Error::request_id`, and the title of the code in the catalog as
+    /// its message, or `internal error` if the code is not registered. Files,
+    /// lines, inner errors, other metadata and the messages of `self` are
+    /// dropped, and metadata providers are not run, so `self` can still be
+    /// logged in full.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mm_errors::Error;
+    /// use mm_errors::catalog::{self, CodeInfo};
+    ///
+    /// catalog::register(CodeInfo::new("SAN503", "Service unavailable")).unwrap();
+    ///
+    /// let e = Error::context(Error::here("connection refused by 10.0.0.7").with_code("SAN503"),
+    ///                        "loading order 7", "api.rs", 3)
+    ///     .with_request_id("r-42");
+    /// let safe = e.sanitize_for_client();
+    ///
+    /// assert_eq!(safe.message(), Some("Service unavailable"));
+    /// assert_eq!(safe.code.as_deref(), Some("SAN503"));
+    /// assert_eq!(safe.request_id(), Some("r-42"));
+    /// assert_eq!((&*safe.file, safe.line), ("", 0));
+    /// assert_eq!(safe.chain().count(), 1);
+    ///
+    /// assert_eq!(Error::here("secret path").sanitize_for_client().message(), Some("internal error"));
+    /// ```
+    ///
+    pub fn sanitize_for_client(&self) -> Error {
+        let code = self.chain()
+            .filter_map(|e| e.downcast_ref::<Error>())
+            .find_map(|e| e.code.clone());
+        let message = code.as_ref()
+            .and_then(|code| lookup(code))
+            .map_or_else(|| "internal error".to_string(), |info| info.title.into_owned());
+        let mut metadata = metadata::Metadata::new();
+        if let Some(id) = self.request_id() {
+            metadata.insert_keyed(metadata::REQUEST_ID, id.to_string());
+        }
+        Error { file: Cow::Borrowed(""), line: 0, kind: ErrorKind::String(message), code, metadata }
+    }
+}
+
+/// Returns the namespace of the hierarchical `code`, e.g. `myapp::storage` for
+/// `myapp::storage::E012`, or `None` if `code` has no namespace.
+///
+/// # Examples
+///
+/// ```
+/// use mm_errors::catalog;
+///
+/// assert_eq!(catalog::namespace("myapp::storage::E012"), Some("myapp::storage"));
+/// assert_eq!(catalog::local_code("myapp::storage::E012"), "E012");
+/// assert_eq!(catalog::namespace("E012"), None);
+/// assert_eq!(catalog::local_code("E012"), "E012");
+/// ```
+///
+pub fn namespace(code: &str) -> Option<&str> {
+    code.rfind("::").map(|i| &code[..i])
+}
+
+/// Returns `code` without its namespace.
+pub fn local_code(code: &str) -> &str {
+    match code.rfind("::") {
+        Some(i) => &code[i + 2..],
+        None => code,
+    }
+}
+
+/// Returns the error code `$code` namespaced by the current module, e.g.
+/// `myapp::storage::E012` for `error_code!("E012")` in `myapp::storage`.
+///
+/// The result is a `&'static str` usable with `Error::from_code`,
+/// `Error::with_code` and `catalog::CodeInfo::new`, so crates of a workspace
+/// can use the same local codes without collisions.
+///
+/// # Examples
+///
+/// ```
+/// # #[macro_use] extern crate mm_errors;
+///
+/// use mm_errors::catalog;
+///
+/// mod storage {
+///     pub const DISK_FULL: &str = error_code!("E012");
+/// }
+///
+/// # fn main() {
+///     assert!(storage::DISK_FULL.ends_with("::storage::E012"));
+///     assert_eq!(catalog::local_code(storage::DISK_FULL), "E012");
+/// # }
+/// ```
+///
+#[macro_export]
+macro_rules! error_code {
+    ($code:expr) => {
+        concat!(module_path!(), "::", $code)
+    }
+}
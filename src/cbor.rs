@@ -0,0 +1,460 @@
+//! CBOR encoding of errors.
+//!
+//! Errors are encoded as CBOR maps with small integer keys, so the encoding is
+//! compact enough for constrained links, e.g. of gateways forwarding errors of
+//! devices. The keys are the field numbers of `proto/mm_errors.proto`:
+//!
+//! | Key | Value |
+//! |-----|-------|
+//! | 1 | Schema version, only in the outermost map |
+//! | 2 | File (text) |
+//! | 3 | Line (unsigned) |
+//! | 4 | Reason as text |
+//! | 5 | Reason as an inner error (map) |
+//! | 6 | Reason as inner errors of a `MultiError` (array of maps) |
+//! | 7 | Context (text) |
+//! | 8 | `true` if cancelled |
+//! | 9 | Code (text) |
+//! | 10 | Metadata (array of `[key, value]` text pairs) |
+//! | 11 | IO context (map of 1: operation, 2: path, 3: bytes) |
+//! | 12 | Crate version (text), only in the outermost map |
+//! | 13 | Reason as a `RemoteError` (map of 1: host, 2: service, 3: error) |
+//!
+//! Exactly one of the keys 4, 5, 6 and 13 is present. Only definite lengths are
+//! written and read.
+//!
+//! This module is available with the `cbor` feature.
+
+use std::borrow::Cow;
+use std::convert::TryFrom;
+use std::error;
+use std::marker;
+use std::sync::Arc;
+
+use super::io::IoContext;
+use super::{metadata, render, xml};
+use super::{Error, ErrorKind, LocalError, Metadata, MultiError, RemoteError, Result, SCHEMA_VERSION};
+
+const SCHEMA: u64 = 1;
+const FILE: u64 = 2;
+const LINE: u64 = 3;
+const TEXT: u64 = 4;
+const INNER: u64 = 5;
+const ERRORS: u64 = 6;
+const CONTEXT: u64 = 7;
+const CANCELLED: u64 = 8;
+const CODE: u64 = 9;
+const META: u64 = 10;
+const IO: u64 = 11;
+const VERSION: u64 = 12;
+const REMOTE: u64 = 13;
+
+/// Maximum nesting depth of values, so malicious input can't overflow the stack.
+const MAX_DEPTH: usize = 512;
+
+impl Error {
+    /// Returns `self` encoded as CBOR, as described in the module `cbor`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mm_errors::Error;
+    ///
+    /// let sent = Error::context(Error::new("disk full", "storage.rs", 7), "saving", "service.rs", 21);
+    /// let bytes = sent.to_cbor();
+    /// assert!(bytes.len() < sent.to_json().len());
+    ///
+    /// let received = Error::from_cbor(&bytes).unwrap();
+    /// assert_eq!(received.to_string(), sent.to_string());
+    /// ```
+    ///
+    pub fn to_cbor(&self) -> Vec<u8> {
+        self.mark_reported();
+        let mut out = Vec::new();
+        write_error(&mut out, self, true);
+        out
+    }
+
+    /// Decodes CBOR written by `to_cbor` into an `Error`.
+    ///
+    /// As with `from_json`, every level of the chain becomes an `Error` holding
+    /// the original file and line.
+    ///
+    /// # Arguments
+    ///
+    /// * bytes - CBOR written by `to_cbor`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `bytes` is not valid CBOR in that format, or its
+    /// schema version is not supported.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mm_errors::{Error, RemoteError};
+    ///
+    /// let remote = RemoteError::new("db-1", "storage", Error::new("disk full", "storage.rs", 7));
+    /// let sent = Error::context(remote, "saving the order", "orders.rs", 12);
+    ///
+    /// let received = Error::from_cbor(&sent.to_cbor()).unwrap();
+    /// assert_eq!(received.remote().unwrap().host, "db-1");
+    /// assert_eq!(received.to_string(), sent.to_string());
+    /// ```
+    ///
+    pub fn from_cbor(bytes: &[u8]) -> Result<Error> {
+        let mut decoder = Decoder { bytes, pos: 0 };
+        let value = decoder.value(0)?;
+        if decoder.pos != bytes.len() {
+            return Err(invalid("unexpected bytes after the error"));
+        }
+        to_error(value, true)
+    }
+}
+
+fn write_head(out: &mut Vec<u8>, major: u8, n: u64) {
+    let major = major << 5;
+    if n < 24 {
+        out.push(major | n as u8);
+    } else if n <= u64::from(u8::MAX) {
+        out.extend_from_slice(&[major | 24, n as u8]);
+    } else if n <= u64::from(u16::MAX) {
+        out.push(major | 25);
+        out.extend_from_slice(&(n as u16).to_be_bytes());
+    } else if n <= u64::from(u32::MAX) {
+        out.push(major | 26);
+        out.extend_from_slice(&(n as u32).to_be_bytes());
+    } else {
+        out.push(major | 27);
+        out.extend_from_slice(&n.to_be_bytes());
+    }
+}
+
+fn write_uint(out: &mut Vec<u8>, n: u64) {
+    write_head(out, 0, n);
+}
+
+fn write_text(out: &mut Vec<u8>, s: &str) {
+    write_head(out, 3, s.len() as u64);
+    out.extend_from_slice(s.as_bytes());
+}
+
+fn write_error(out: &mut Vec<u8>, e: &Error, root: bool) {
+    let meta: Vec<_> = e.metadata.iter_rendered(render::is_unredacted())
+        .filter(|&(key, _)| key != metadata::IO)
+        .collect();
+    let io = e.metadata.get_keyed::<IoContext>(metadata::IO);
+    let context = match e.kind {
+        ErrorKind::Context(ref context, _) => Some(context),
+        _ => None,
+    };
+    let cancelled = matches!(e.kind, ErrorKind::Cancelled(..));
+
+    let len = 3 + 2 * root as u64 + context.is_some() as u64 + cancelled as u64 + e.code.is_some() as u64
+        + !meta.is_empty() as u64 + io.is_some() as u64;
+    write_head(out, 5, len);
+    write_location(out, &e.file, e.line, root);
+    match e.kind {
+        ErrorKind::String(ref s) | ErrorKind::Cancelled(ref s) => {
+            write_uint(out, TEXT);
+            write_text(out, s);
+        }
+        ErrorKind::Wrapped(ref inner) | ErrorKind::Context(_, ref inner) => write_reason(out, &**inner),
+    }
+    if let Some(context) = context {
+        write_uint(out, CONTEXT);
+        write_text(out, context);
+    }
+    if cancelled {
+        write_uint(out, CANCELLED);
+        out.push(0xf5);
+    }
+    if let Some(ref code) = e.code {
+        write_uint(out, CODE);
+        write_text(out, code);
+    }
+    if !meta.is_empty() {
+        write_uint(out, META);
+        write_head(out, 4, meta.len() as u64);
+        for (key, value) in meta {
+            write_head(out, 4, 2);
+            write_text(out, key);
+            write_text(out, value);
+        }
+    }
+    if let Some(io) = io {
+        write_uint(out, IO);
+        write_head(out, 5, 2 + io.bytes.is_some() as u64);
+        write_uint(out, 1);
+        write_text(out, &io.operation);
+        write_uint(out, 2);
+        write_text(out, &io.path.to_string_lossy());
+        if let Some(bytes) = io.bytes {
+            write_uint(out, 3);
+            write_uint(out, bytes);
+        }
+    }
+}
+
+/// Writes the schema and crate versions if `root`, the file and the line.
+fn write_location(out: &mut Vec<u8>, file: &str, line: u32, root: bool) {
+    let (file, line) = render::location(file, line);
+    if root {
+        write_uint(out, SCHEMA);
+        write_uint(out, u64::from(SCHEMA_VERSION));
+        write_uint(out, VERSION);
+        write_text(out, render::crate_version());
+    }
+    write_uint(out, FILE);
+    write_text(out, &file);
+    write_uint(out, LINE);
+    write_uint(out, u64::from(line));
+}
+
+/// Writes an inner error as the key and value of the reason.
+fn write_reason(out: &mut Vec<u8>, e: &(dyn error::Error + 'static)) {
+    if let Some(inner) = e.downcast_ref::<Error>() {
+        write_uint(out, INNER);
+        write_error(out, inner, false);
+    } else if let Some(inner) = e.downcast_ref::<LocalError>() {
+        write_uint(out, INNER);
+        write_head(out, 5, 3);
+        write_location(out, inner.file, inner.line, false);
+        write_reason(out, &*inner.inner);
+    } else if let Some(inner) = e.downcast_ref::<RemoteError>() {
+        write_uint(out, REMOTE);
+        write_head(out, 5, 3);
+        write_uint(out, 1);
+        write_text(out, &inner.host);
+        write_uint(out, 2);
+        write_text(out, &inner.service);
+        write_uint(out, 3);
+        write_error(out, &inner.error, false);
+    } else if let Some(inner) = e.downcast_ref::<MultiError>() {
+        write_uint(out, ERRORS);
+        write_head(out, 4, inner.len() as u64);
+        for e in inner {
+            write_error(out, e, false);
+        }
+    } else {
+        write_uint(out, TEXT);
+        write_text(out, &e.to_string());
+    }
+}
+
+/// Decoded CBOR value, limited to the types written by `to_cbor`.
+enum Value {
+    Uint(u64),
+    Text(String),
+    Bool(bool),
+    Array(Vec<Value>),
+    Map(Vec<(Value, Value)>),
+}
+
+struct Decoder<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Decoder<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8]> {
+        if self.bytes.len() - self.pos < n {
+            return Err(invalid("unexpected end of input"));
+        }
+        let taken = &self.bytes[self.pos..self.pos + n];
+        self.pos += n;
+        Ok(taken)
+    }
+
+    /// Reads the head of a value, returning its major type and argument.
+    fn head(&mut self) -> Result<(u8, u64)> {
+        let initial = self.take(1)?[0];
+        let (major, info) = (initial >> 5, initial & 0x1f);
+        let n = match info {
+            0..=23 => u64::from(info),
+            24 => u64::from(self.take(1)?[0]),
+            25 => u64::from(u16::from_be_bytes([self.take(1)?[0], self.take(1)?[0]])),
+            26 => {
+                let b = self.take(4)?;
+                u64::from(u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+            }
+            27 => {
+                let b = self.take(8)?;
+                u64::from_be_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]])
+            }
+            _ => return Err(invalid("unsupported length encoding")),
+        };
+        Ok((major, n))
+    }
+
+    fn value(&mut self, depth: usize) -> Result<Value> {
+        if depth > MAX_DEPTH {
+            return Err(invalid("too deeply nested"));
+        }
+        match self.head()? {
+            (0, n) => Ok(Value::Uint(n)),
+            (3, len) => {
+                let len = usize::try_from(len).map_err(|_| invalid("text too long"))?;
+                let text = self.take(len)?;
+                String::from_utf8(text.to_vec())
+                    .map(Value::Text)
+                    .map_err(|_| invalid("invalid UTF-8 in text"))
+            }
+            (4, len) => {
+                let mut values = Vec::new();
+                for _ in 0..len {
+                    values.push(self.value(depth + 1)?);
+                }
+                Ok(Value::Array(values))
+            }
+            (5, len) => {
+                let mut entries = Vec::new();
+                for _ in 0..len {
+                    let key = self.value(depth + 1)?;
+                    entries.push((key, self.value(depth + 1)?));
+                }
+                Ok(Value::Map(entries))
+            }
+            (7, 20) => Ok(Value::Bool(false)),
+            (7, 21) => Ok(Value::Bool(true)),
+            (major, _) => Err(invalid(&format!("unsupported major type {}", major))),
+        }
+    }
+}
+
+fn invalid(message: &str) -> Error {
+    Error::new(&format!("invalid error CBOR: {}", message), file!(), line!())
+}
+
+fn to_text(value: Value) -> Result<String> {
+    match value {
+        Value::Text(s) => Ok(s),
+        _ => Err(invalid("expected text")),
+    }
+}
+
+fn to_uint(value: Value) -> Result<u64> {
+    match value {
+        Value::Uint(n) => Ok(n),
+        _ => Err(invalid("expected an unsigned integer")),
+    }
+}
+
+/// Converts the decoded `value` into an `Error`.
+fn to_error(value: Value, root: bool) -> Result<Error> {
+    let entries = match value {
+        Value::Map(entries) => entries,
+        _ => return Err(invalid("expected an error map")),
+    };
+    let (mut file, mut line, mut reason, mut context, mut code) = (None, None, None, None, None);
+    let mut cancelled = false;
+    let mut metadata = Metadata::new();
+    for (key, value) in entries {
+        match to_uint(key)? {
+            SCHEMA if root => {
+                let version = to_uint(value)?;
+                if !u32::try_from(version).is_ok_and(super::is_supported_schema) {
+                    return Err(invalid(&format!("unsupported schema version `{}`", version)));
+                }
+            }
+            VERSION if root => {
+                to_text(value)?;
+            }
+            FILE => file = Some(to_text(value)?),
+            LINE => {
+                line = Some(u32::try_from(to_uint(value)?).map_err(|_| invalid("invalid line number"))?);
+            }
+            key @ TEXT..=ERRORS | key @ REMOTE => reason = Some((key, value)),
+            CONTEXT => context = Some(to_text(value)?),
+            CANCELLED => cancelled = matches!(value, Value::Bool(true)),
+            CODE => code = Some(Cow::Owned(to_text(value)?)),
+            META => {
+                let entries = match value {
+                    Value::Array(entries) => entries,
+                    _ => return Err(invalid("expected an array of metadata")),
+                };
+                for entry in entries {
+                    let mut pair = match entry {
+                        Value::Array(pair) if pair.len() == 2 => pair.into_iter(),
+                        _ => return Err(invalid("invalid metadata entry")),
+                    };
+                    let key = to_text(pair.next().unwrap())?;
+                    let value = to_text(pair.next().unwrap())?;
+                    let parsed = xml::unquote(&value).unwrap_or_else(|| value.clone());
+                    metadata.insert_entry(Cow::Owned(key), Arc::new(parsed), value, None);
+                }
+            }
+            IO => metadata.insert_keyed(metadata::IO, to_io(value)?),
+            key => return Err(invalid(&format!("unexpected key {}", key))),
+        }
+    }
+
+    let file = file.ok_or_else(|| invalid("missing file"))?;
+    let line = line.ok_or_else(|| invalid("missing line"))?;
+    let reason: Box<dyn error::Error + marker::Send + marker::Sync> = match reason {
+        Some((TEXT, value)) => {
+            let s = to_text(value)?;
+            if context.is_none() {
+                let kind = if cancelled { ErrorKind::Cancelled(s) } else { ErrorKind::String(s) };
+                return Ok(Error { file: Cow::Owned(file), line, kind, code, metadata });
+            }
+            s.into()
+        }
+        Some((INNER, value)) => Box::new(to_error(value, false)?),
+        Some((REMOTE, value)) => Box::new(to_remote(value)?),
+        Some((_, Value::Array(errors))) => {
+            let errors = errors.into_iter().map(|e| to_error(e, false)).collect::<Result<Vec<_>>>()?;
+            Box::new(MultiError::from(errors))
+        }
+        _ => return Err(invalid("missing or invalid reason")),
+    };
+    let kind = match context {
+        Some(context) => ErrorKind::Context(context, reason),
+        None => ErrorKind::Wrapped(reason),
+    };
+    Ok(Error { file: Cow::Owned(file), line, kind, code, metadata })
+}
+
+fn to_remote(value: Value) -> Result<RemoteError> {
+    let entries = match value {
+        Value::Map(entries) => entries,
+        _ => return Err(invalid("expected a remote error map")),
+    };
+    let (mut host, mut service, mut error) = (None, None, None);
+    for (key, value) in entries {
+        match to_uint(key)? {
+            1 => host = Some(to_text(value)?),
+            2 => service = Some(to_text(value)?),
+            3 => error = Some(to_error(value, false)?),
+            key => return Err(invalid(&format!("unexpected remote error key {}", key))),
+        }
+    }
+    match (host, service, error) {
+        (Some(host), Some(service), Some(error)) => Ok(RemoteError::new(host, service, error)),
+        _ => Err(invalid("incomplete remote error")),
+    }
+}
+
+fn to_io(value: Value) -> Result<IoContext> {
+    let entries = match value {
+        Value::Map(entries) => entries,
+        _ => return Err(invalid("expected an IO context map")),
+    };
+    let (mut operation, mut path, mut bytes) = (None, None, None);
+    for (key, value) in entries {
+        match to_uint(key)? {
+            1 => operation = Some(to_text(value)?),
+            2 => path = Some(to_text(value)?),
+            3 => bytes = Some(to_uint(value)?),
+            key => return Err(invalid(&format!("unexpected IO context key {}", key))),
+        }
+    }
+    match (operation, path) {
+        (Some(operation), Some(path)) => {
+            let mut io = IoContext::new(operation, path);
+            io.bytes = bytes;
+            Ok(io)
+        }
+        _ => Err(invalid("incomplete IO context")),
+    }
+}
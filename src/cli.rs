@@ -0,0 +1,869 @@
+//! Helpers for command line tools.
+
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::io::{self, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitCode, Stdio, Termination};
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+
+use super::catalog::{self, Severity};
+use super::metadata::{self, REDACTED};
+use super::report::CliReport;
+use super::{Error, Result};
+
+type Redactor = Arc<dyn Fn(&[String]) -> Vec<String> + Send + Sync>;
+
+type SuspendHook = Arc<dyn Fn(&mut dyn FnMut()) + Send + Sync>;
+
+static REDACTOR: RwLock<Option<Redactor>> = RwLock::new(None);
+
+static SUSPEND_HOOK: RwLock<Option<SuspendHook>> = RwLock::new(None);
+
+static EXIT_CODES: RwLock<Option<Arc<ExitCodeMap>>> = RwLock::new(None);
+
+static BACKTRACE_HINT: AtomicBool = AtomicBool::new(true);
+
+static PAGER: AtomicBool = AtomicBool::new(false);
+
+static VERBOSITY: AtomicU8 = AtomicU8::new(1);
+
+static COLOR: RwLock<ColorChoice> = RwLock::new(ColorChoice::Auto);
+
+static BUG_REPORT: RwLock<Option<Arc<BugReport>>> = RwLock::new(None);
+
+static SIDECAR: Mutex<Option<Box<dyn Write + Send>>> = Mutex::new(None);
+
+/// Flags whose values are redacted by `redact_secret_flags`.
+const SECRET_FLAGS: &[&str] = &["password", "passwd", "secret", "token", "api-key", "apikey", "key", "auth"];
+
+/// Sets the function redacting the arguments captured by `Error::with_args`.
+///
+/// The function receives the arguments, including the program name, and
+/// returns the arguments to attach. The default is `redact_secret_flags`.
+/// The setting is global and applies to all threads.
+///
+/// # Arguments
+///
+/// * f - Function redacting the arguments.
+///
+/// # Examples
+///
+/// ```
+/// use mm_errors::cli;
+///
+/// // Attach the program name only.
+/// cli::set_args_redactor(|args: &[String]| args.iter().take(1).cloned().collect());
+/// # cli::reset_args_redactor();
+/// ```
+///
+pub fn set_args_redactor<F>(f: F)
+    where F: Fn(&[String]) -> Vec<String> + Send + Sync + 'static {
+    *REDACTOR.write().unwrap_or_else(|e| e.into_inner()) = Some(Arc::new(f));
+}
+
+/// Restores `redact_secret_flags` as the function redacting captured arguments.
+pub fn reset_args_redactor() {
+    *REDACTOR.write().unwrap_or_else(|e| e.into_inner()) = None;
+}
+
+/// Redacts the values of flags which look like secrets, such as `--password` or `--api-key`.
+///
+/// Both `--token VALUE` and `--token=VALUE` forms are redacted, as are flags
+/// ending with one of the secret names, such as `--github-token`.
+///
+/// # Examples
+///
+/// ```
+/// use mm_errors::cli;
+///
+/// let args: Vec<String> = ["app", "--user", "bob", "--password", "hunter2", "--api-key=abc"]
+///     .iter().map(|s| s.to_string()).collect();
+/// assert_eq!(
+///     cli::redact_secret_flags(&args),
+///     ["app", "--user", "bob", "--password", "***", "--api-key=***"]);
+/// ```
+///
+pub fn redact_secret_flags(args: &[String]) -> Vec<String> {
+    let mut out = Vec::with_capacity(args.len());
+    let mut redact_next = false;
+    for arg in args {
+        if redact_next {
+            out.push(REDACTED.to_string());
+            redact_next = false;
+            continue;
+        }
+        let flag = arg.trim_start_matches('-');
+        if flag.len() == arg.len() {
+            out.push(arg.clone());
+            continue;
+        }
+        match flag.find('=') {
+            Some(i) if is_secret_flag(&flag[..i]) => {
+                out.push(format!("{}={}", &arg[..arg.len() - flag.len() + i], REDACTED));
+            }
+            Some(_) => out.push(arg.clone()),
+            None => {
+                redact_next = is_secret_flag(flag);
+                out.push(arg.clone());
+            }
+        }
+    }
+    out
+}
+
+fn is_secret_flag(flag: &str) -> bool {
+    let flag = flag.to_ascii_lowercase().replace('_', "-");
+    SECRET_FLAGS.iter().any(|s| flag == *s || flag.ends_with(&format!("-{}", s)))
+}
+
+/// Returns the arguments of the process, redacted by the configured function.
+pub(crate) fn redacted_args() -> Vec<String> {
+    let args: Vec<String> = env::args_os().map(|a| a.to_string_lossy().into_owned()).collect();
+    let redactor = REDACTOR.read().unwrap_or_else(|e| e.into_inner()).clone();
+    match redactor {
+        Some(f) => f(&args),
+        None => redact_secret_flags(&args),
+    }
+}
+
+impl Error {
+    /// Returns `self` with the command line arguments of the process attached.
+    ///
+    /// The arguments, including the program name, are redacted by the function
+    /// set with `cli::set_args_redactor`, and attached as a `Vec<String>` under
+    /// `metadata::ARGS`. Attach them to top-level errors only, e.g. in `main`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mm_errors::{metadata, Error};
+    ///
+    /// let e = Error::here("fatal").with_args();
+    /// let args = e.metadata.get_keyed::<Vec<String>>(metadata::ARGS).unwrap();
+    /// assert!(!args.is_empty());
+    /// ```
+    ///
+    pub fn with_args(self) -> Error {
+        self.with_meta_keyed(metadata::ARGS, redacted_args())
+    }
+}
+
+/// Exit codes of BSD `sysexits.h`, used by `ExitCodeMap::sysexits`.
+pub mod sysexits {
+    /// The command was used incorrectly, e.g. with wrong arguments.
+    pub const EX_USAGE: u8 = 64;
+    /// The input data was incorrect.
+    pub const EX_DATAERR: u8 = 65;
+    /// An input file did not exist or was not readable.
+    pub const EX_NOINPUT: u8 = 66;
+    /// A service is unavailable.
+    pub const EX_UNAVAILABLE: u8 = 69;
+    /// An internal software error was detected.
+    pub const EX_SOFTWARE: u8 = 70;
+    /// An error occurred while doing I/O on some file.
+    pub const EX_IOERR: u8 = 74;
+    /// A temporary failure, indicating something that is not really an error.
+    pub const EX_TEMPFAIL: u8 = 75;
+    /// Insufficient permission to perform the operation.
+    pub const EX_NOPERM: u8 = 77;
+    /// Something was found in an unconfigured or misconfigured state.
+    pub const EX_CONFIG: u8 = 78;
+}
+
+/// Table of the exit codes of errors, consulted by `Error::exit_code`.
+///
+/// The outermost error in the chain having a code decides: its code if it
+/// is in the table, or else the longest namespace of the code in the table,
+/// as returned by `catalog::namespace`. Otherwise, the `sysexits` preset
+/// maps retryable errors to `EX_TEMPFAIL`, and I/O errors in the chain to
+/// `EX_NOINPUT`, `EX_NOPERM` or `EX_IOERR` by kind. Any other error exits
+/// with the default code.
+///
+/// # Examples
+///
+/// ```
+/// use std::io;
+///
+/// use mm_errors::Error;
+/// use mm_errors::cli::{sysexits, ExitCodeMap};
+///
+/// let map = ExitCodeMap::sysexits()
+///     .code("CFG001", sysexits::EX_CONFIG)
+///     .namespace("myapp::net", sysexits::EX_UNAVAILABLE);
+///
+/// assert_eq!(map.exit_code(&Error::here("bad config").with_code("CFG001")), 78);
+/// assert_eq!(map.exit_code(&Error::here("refused").with_code("myapp::net::http::E001")), 69);
+/// assert_eq!(map.exit_code(&Error::wrap(io::Error::from(io::ErrorKind::NotFound), "main.rs", 1)), 66);
+/// assert_eq!(map.exit_code(&Error::here("bug")), 70);
+///
+/// assert_eq!(ExitCodeMap::new().exit_code(&Error::here("bug")), 1);
+/// ```
+///
+#[derive(Debug, Clone)]
+pub struct ExitCodeMap {
+    codes: HashMap<String, u8>,
+    namespaces: HashMap<String, u8>,
+    sysexits: bool,
+    default: u8,
+}
+
+impl ExitCodeMap {
+    /// Returns a new empty instance of `ExitCodeMap`, exiting with 1 by default.
+    pub fn new() -> ExitCodeMap {
+        ExitCodeMap {
+            codes: HashMap::new(),
+            namespaces: HashMap::new(),
+            sysexits: false,
+            default: 1,
+        }
+    }
+
+    /// Returns a new instance of `ExitCodeMap` with the BSD sysexits preset,
+    /// exiting with `EX_SOFTWARE` by default.
+    pub fn sysexits() -> ExitCodeMap {
+        ExitCodeMap {
+            sysexits: true,
+            default: sysexits::EX_SOFTWARE,
+            ..ExitCodeMap::new()
+        }
+    }
+
+    /// Returns `self` with errors with `code` exiting with `exit_code`.
+    pub fn code<C: Into<String>>(mut self, code: C, exit_code: u8) -> ExitCodeMap {
+        self.codes.insert(code.into(), exit_code);
+        self
+    }
+
+    /// Returns `self` with errors with codes in `namespace` exiting with `exit_code`.
+    pub fn namespace<N: Into<String>>(mut self, namespace: N, exit_code: u8) -> ExitCodeMap {
+        self.namespaces.insert(namespace.into(), exit_code);
+        self
+    }
+
+    /// Returns `self` with errors not in the table exiting with `exit_code`.
+    pub fn default_code(mut self, exit_code: u8) -> ExitCodeMap {
+        self.default = exit_code;
+        self
+    }
+
+    /// Returns the exit code of `e`.
+    pub fn exit_code(&self, e: &Error) -> u8 {
+        let code = e.chain()
+            .filter_map(|e| e.downcast_ref::<Error>())
+            .find_map(|e| e.code.as_deref());
+        if let Some(code) = code {
+            if let Some(&exit_code) = self.codes.get(code) {
+                return exit_code;
+            }
+            let mut namespace = catalog::namespace(code);
+            while let Some(ns) = namespace {
+                if let Some(&exit_code) = self.namespaces.get(ns) {
+                    return exit_code;
+                }
+                namespace = catalog::namespace(ns);
+            }
+        }
+        if self.sysexits {
+            if e.is_retryable() {
+                return sysexits::EX_TEMPFAIL;
+            }
+            if let Some(io) = e.chain().find_map(|e| e.downcast_ref::<io::Error>()) {
+                return match io.kind() {
+                    io::ErrorKind::NotFound => sysexits::EX_NOINPUT,
+                    io::ErrorKind::PermissionDenied => sysexits::EX_NOPERM,
+                    _ => sysexits::EX_IOERR,
+                };
+            }
+        }
+        self.default
+    }
+}
+
+impl Default for ExitCodeMap {
+    fn default() -> ExitCodeMap {
+        ExitCodeMap::new()
+    }
+}
+
+/// Sets the table of exit codes consulted by `Error::exit_code` and `Exit`.
+///
+/// By default, every error exits with 1. The setting is global and applies
+/// to all threads.
+///
+/// # Arguments
+///
+/// * map - Table of exit codes.
+///
+pub fn set_exit_codes(map: ExitCodeMap) {
+    *EXIT_CODES.write().unwrap_or_else(|e| e.into_inner()) = Some(Arc::new(map));
+}
+
+impl Error {
+    /// Returns the exit code of `self` in the table set by `cli::set_exit_codes`.
+    pub fn exit_code(&self) -> u8 {
+        let map = EXIT_CODES.read().unwrap_or_else(|e| e.into_inner()).clone();
+        match map {
+            Some(map) => map.exit_code(self),
+            None => ExitCodeMap::new().exit_code(self),
+        }
+    }
+}
+
+/// Result of `main`, exiting with the exit code of the error.
+///
+/// Unlike `Result<(), Error>`, which always exits with 1 and writes `Debug`,
+/// this writes the error with `cli::report_fatal`, and exits with `Error::exit_code`.
+///
+/// # Examples
+///
+/// ```no_run
+/// use mm_errors::cli::{self, sysexits, Exit, ExitCodeMap};
+///
+/// fn run() -> mm_errors::Result<()> {
+///     Err(mm_errors::Error::here("bad config").with_code("CFG001"))
+/// }
+///
+/// fn main() -> Exit {
+///     cli::set_exit_codes(ExitCodeMap::sysexits().code("CFG001", sysexits::EX_CONFIG));
+///     run().into()
+/// }
+/// ```
+///
+#[derive(Debug)]
+pub struct Exit(pub Result<()>);
+
+impl From<Result<()>> for Exit {
+    fn from(result: Result<()>) -> Exit {
+        Exit(result)
+    }
+}
+
+impl Termination for Exit {
+    fn report(self) -> ExitCode {
+        match self.0 {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(e) => {
+                report_fatal(&e);
+                ExitCode::from(e.exit_code())
+            }
+        }
+    }
+}
+
+/// Writes `e` to the standard error in the style of cargo.
+///
+/// The output is `render` of `e`, in color if `color_enabled`, or only
+/// `render_summary` in quiet mode, enabled by `set_quiet`. This is the default
+/// rendering for terminal programs, used by `Exit`.
+///
+pub fn report(e: &Error) {
+    if write_sidecar(e) {
+        write_stderr(&render_summary(e, color_enabled()));
+    } else {
+        write_stderr(&render(e, color_enabled()));
+    }
+}
+
+/// Writes `e` as `report` does, followed by the footer of `set_bug_report`, if any.
+///
+/// This is the rendering of errors ending the program, used by `Exit`. There
+/// is no footer in quiet mode.
+///
+pub fn report_fatal(e: &Error) {
+    if write_sidecar(e) {
+        write_stderr(&render_summary(e, color_enabled()));
+        return;
+    }
+    let bug_report = BUG_REPORT.read().unwrap_or_else(|e| e.into_inner()).clone();
+    let rendered = render(e, color_enabled());
+    match bug_report.and_then(|bug_report| bug_report.footer(e)) {
+        Some(footer) => write_stderr(&format!("{}\n{}", rendered, footer)),
+        None => write_stderr(&rendered),
+    }
+}
+
+/// Enables quiet mode, where the full reports are written to `sidecar`.
+///
+/// In quiet mode, `report` and `report_fatal` write the first line of the
+/// error only, as `render_summary`, for a terse terminal, and
+/// the error as by `Error::to_json` on a line of `sidecar`, for the tools
+/// running the program. The terminal gets the full report if writing to
+/// `sidecar` fails. The setting is global and applies to all threads.
+///
+/// # Arguments
+///
+/// * sidecar - Destination of the JSON reports, e.g. a file, or the file
+///   descriptor of an `--error-json-fd` flag opened with `FromRawFd`.
+///
+/// # Examples
+///
+/// ```
+/// use std::io::{self, Write};
+/// use std::sync::{Arc, Mutex};
+///
+/// use mm_errors::{cli, Error};
+///
+/// #[derive(Clone, Default)]
+/// struct Buffer(Arc<Mutex<Vec<u8>>>);
+///
+/// impl Write for Buffer {
+///     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+///         self.0.lock().unwrap().write(buf)
+///     }
+///
+///     fn flush(&mut self) -> io::Result<()> {
+///         Ok(())
+///     }
+/// }
+///
+/// let sidecar = Buffer::default();
+/// cli::set_quiet(sidecar.clone());
+///
+/// let e = Error::context(Error::here("invalid digit"), "reading the port number", "config.rs", 3)
+///     .with_help("use a number between 1 and 65535");
+/// cli::report(&e);
+/// assert_eq!(String::from_utf8(sidecar.0.lock().unwrap().clone()).unwrap(), e.to_json() + "\n");
+///
+/// // The terminal gets this line and no `help` lines.
+/// assert_eq!(cli::render_summary(&e, false), "error: reading the port number");
+/// # cli::reset_quiet();
+/// ```
+///
+pub fn set_quiet<W>(sidecar: W)
+    where W: Write + Send + 'static {
+    *SIDECAR.lock().unwrap_or_else(|e| e.into_inner()) = Some(Box::new(sidecar));
+}
+
+/// Disables quiet mode, closing the sidecar.
+pub fn reset_quiet() {
+    *SIDECAR.lock().unwrap_or_else(|e| e.into_inner()) = None;
+}
+
+/// Writes `e` to the sidecar of `set_quiet`, returning `false` if there is none or it fails.
+fn write_sidecar(e: &Error) -> bool {
+    let mut sidecar = SIDECAR.lock().unwrap_or_else(|e| e.into_inner());
+    match *sidecar {
+        Some(ref mut w) => writeln!(w, "{}", e.to_json()).and_then(|()| w.flush()).is_ok(),
+        None => false,
+    }
+}
+
+/// Sets the function suspending other output to the terminal while errors are written.
+///
+/// `report` and `report_fatal` write through `f`, which receives the write
+/// and must run it once, e.g. with progress bars hidden, so they don't mix
+/// with the error on the terminal. By default, errors are written directly.
+/// The setting is global and applies to all threads.
+///
+/// # Arguments
+///
+/// * f - Function running the write with other output suspended.
+///
+/// # Examples
+///
+/// ```
+/// use std::sync::{Arc, Mutex};
+///
+/// use mm_errors::{cli, Error};
+///
+/// // With indicatif, this is `cli::set_suspend_hook(move |write| bars.suspend(write))`.
+/// let events = Arc::new(Mutex::new(Vec::new()));
+/// let log = events.clone();
+/// cli::set_suspend_hook(move |write: &mut dyn FnMut()| {
+///     log.lock().unwrap().push("hide");
+///     write();
+///     log.lock().unwrap().push("show");
+/// });
+///
+/// cli::report(&Error::here("download failed"));
+/// assert_eq!(*events.lock().unwrap(), ["hide", "show"]);
+/// # cli::reset_suspend_hook();
+/// ```
+///
+pub fn set_suspend_hook<F>(f: F)
+    where F: Fn(&mut dyn FnMut()) + Send + Sync + 'static {
+    *SUSPEND_HOOK.write().unwrap_or_else(|e| e.into_inner()) = Some(Arc::new(f));
+}
+
+/// Restores writing errors to the terminal directly.
+pub fn reset_suspend_hook() {
+    *SUSPEND_HOOK.write().unwrap_or_else(|e| e.into_inner()) = None;
+}
+
+/// Sets whether `report` and `report_fatal` page reports taller than the terminal.
+///
+/// If enabled, and the standard error is a terminal, reports with more lines
+/// than the terminal are written to the standard input of `$PAGER`, or else
+/// of `less`, so long reports such as many validation errors can be scrolled.
+/// `LESS` defaults to `FRX` for the pager, so colors are kept and short
+/// reports are not paged by `less` either. The pager writes to the standard
+/// error too, so reports stay on the terminal if the standard output is
+/// redirected. Reports are written to the
+/// standard error if the pager can't be run.
+///
+/// The height of the terminal is taken from `LINES`, or else from `stty` on
+/// Unix. Paging is disabled by default. The setting is global and applies to
+/// all threads.
+///
+/// # Examples
+///
+/// ```no_run
+/// use mm_errors::{cli, Error};
+///
+/// cli::set_pager(true);
+/// cli::report(&Error::here("validation failed"));
+/// ```
+///
+pub fn set_pager(enabled: bool) {
+    PAGER.store(enabled, Ordering::Relaxed);
+}
+
+/// Returns the number of lines of the terminal, if known.
+fn terminal_height() -> Option<usize> {
+    if let Some(lines) = env::var("LINES").ok().and_then(|lines| lines.trim().parse().ok()) {
+        return Some(lines);
+    }
+    if cfg!(unix) {
+        let tty = fs::File::open("/dev/tty").ok()?;
+        let output = Command::new("stty").arg("size").stdin(tty).stderr(Stdio::null()).output().ok()?;
+        let size = String::from_utf8(output.stdout).ok()?;
+        return size.split_whitespace().next()?.parse().ok();
+    }
+    None
+}
+
+/// Writes `s` to the pager, returning `false` if it can't be run.
+fn page(s: &str) -> bool {
+    let pager = env::var("PAGER").ok().filter(|pager| !pager.trim().is_empty());
+    let pager = pager.as_deref().unwrap_or("less");
+    let mut words = pager.split_whitespace();
+    let program = match words.next() {
+        Some(program) => program,
+        None => return false,
+    };
+    let stderr = match stderr_stdio() {
+        Ok(stderr) => stderr,
+        Err(_) => return false,
+    };
+    let mut command = Command::new(program);
+    // The report belongs on the terminal of the standard error, even if the standard output is redirected.
+    command.args(words).stdin(Stdio::piped()).stdout(stderr);
+    if env::var_os("LESS").is_none() {
+        command.env("LESS", "FRX");
+    }
+    let mut child = match command.spawn() {
+        Ok(child) => child,
+        Err(_) => return false,
+    };
+    if let Some(mut stdin) = child.stdin.take() {
+        // The user quitting the pager early closes the pipe, which is not a failure.
+        let _ = writeln!(stdin, "{}", s);
+    }
+    child.wait().is_ok()
+}
+
+/// Returns a handle to the standard error of the process, for the standard output of the pager.
+#[cfg(unix)]
+fn stderr_stdio() -> io::Result<Stdio> {
+    use std::os::fd::AsFd;
+
+    Ok(Stdio::from(io::stderr().as_fd().try_clone_to_owned()?))
+}
+
+#[cfg(windows)]
+fn stderr_stdio() -> io::Result<Stdio> {
+    use std::os::windows::io::AsHandle;
+
+    Ok(Stdio::from(io::stderr().as_handle().try_clone_to_owned()?))
+}
+
+#[cfg(not(any(unix, windows)))]
+fn stderr_stdio() -> io::Result<Stdio> {
+    Err(io::Error::from(io::ErrorKind::Unsupported))
+}
+
+/// Writes `s` on a line of the standard error, through the hook of `set_suspend_hook`.
+///
+/// `s` goes to the pager instead if enabled by `set_pager` and taller than the terminal.
+///
+fn write_stderr(s: &str) {
+    let paged = PAGER.load(Ordering::Relaxed)
+        && io::stderr().is_terminal()
+        && terminal_height().is_some_and(|height| s.lines().count() >= height);
+    // There is nowhere left to report a failure to write to the standard error.
+    let mut write = || {
+        if !(paged && page(s)) {
+            let _ = writeln!(io::stderr().lock(), "{}", s);
+        }
+    };
+    let hook = SUSPEND_HOOK.read().unwrap_or_else(|e| e.into_inner()).clone();
+    match hook {
+        Some(f) => f(&mut write),
+        None => write(),
+    }
+}
+
+/// Footer of fatal errors asking users to report them, set by `set_bug_report`.
+///
+/// The footer points to the issue tracker, and gives the report ID of the
+/// error, the hexadecimal `Error::fingerprint`, so reports of the same
+/// failure can be matched. If a crash report directory is set, the error is
+/// also written there as by `Error::to_json`, and the footer gives the path.
+///
+/// # Examples
+///
+/// ```
+/// use mm_errors::{Error, Severity};
+/// use mm_errors::cli::BugReport;
+///
+/// let bug_report = BugReport::new("https://github.com/example/app/issues/new");
+/// let e = Error::new("index out of range", "table.rs", 7).with_severity(Severity::Critical);
+///
+/// assert_eq!(
+///     bug_report.footer(&e).unwrap(),
+///     format!("note: this is a bug, please report it at https://github.com/example/app/issues/new\n\
+///              note: include the report ID {:016x}", e.fingerprint()));
+///
+/// // Errors below the severity are not bugs.
+/// assert!(bug_report.footer(&Error::new("file not found", "main.rs", 3)).is_none());
+///
+/// # let dir = std::env::temp_dir().join(format!("mm-errors-bug-report-{}", std::process::id()));
+/// let footer = bug_report.crash_dir(&dir).footer(&e).unwrap();
+/// let path = footer.rsplit("written to ").next().unwrap();
+/// assert_eq!(std::fs::read_to_string(path).unwrap(), e.to_json());
+/// # std::fs::remove_dir_all(&dir).unwrap();
+/// ```
+///
+#[derive(Debug, Clone)]
+pub struct BugReport {
+    url: String,
+    crash_dir: Option<PathBuf>,
+    severity: Severity,
+}
+
+impl BugReport {
+    /// Returns a new instance of `BugReport` for critical errors.
+    ///
+    /// # Arguments
+    ///
+    /// * url - URL of the issue tracker.
+    ///
+    pub fn new<U: Into<String>>(url: U) -> BugReport {
+        BugReport {
+            url: url.into(),
+            crash_dir: None,
+            severity: Severity::Critical,
+        }
+    }
+
+    /// Returns `self` writing crash reports to `dir`, which is created if needed.
+    pub fn crash_dir<P: AsRef<Path>>(mut self, dir: P) -> BugReport {
+        self.crash_dir = Some(dir.as_ref().to_path_buf());
+        self
+    }
+
+    /// Returns `self` with the footer for errors of `severity` or higher.
+    pub fn severity(mut self, severity: Severity) -> BugReport {
+        self.severity = severity;
+        self
+    }
+
+    /// Returns the footer of `e`, or `None` if `e` is below the severity.
+    ///
+    /// This writes the crash report if a crash report directory is set. The
+    /// footer has no path if it can't be written.
+    ///
+    pub fn footer(&self, e: &Error) -> Option<String> {
+        if e.severity() < self.severity {
+            return None;
+        }
+        let id = format!("{:016x}", e.fingerprint());
+        let mut footer = format!("note: this is a bug, please report it at {}\nnote: include the report ID {}",
+                                 self.url, id);
+        if let Some(ref dir) = self.crash_dir {
+            let path = dir.join(format!("crash-{}.json", id));
+            if fs::create_dir_all(dir).and_then(|()| fs::write(&path, e.to_json())).is_ok() {
+                footer.push_str(&format!(" and the crash report written to {}", path.display()));
+            }
+        }
+        Some(footer)
+    }
+}
+
+/// Sets the footer of fatal errors written by `report_fatal`.
+///
+/// There is no footer by default. The setting is global and applies to all
+/// threads.
+///
+/// # Arguments
+///
+/// * bug_report - Footer, or `None` to disable it.
+///
+pub fn set_bug_report(bug_report: Option<BugReport>) {
+    *BUG_REPORT.write().unwrap_or_else(|e| e.into_inner()) = bug_report.map(Arc::new);
+}
+
+/// Whether the output to the terminal is colored, set by `set_color`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ColorChoice {
+    /// Colored as decided by the environment, as described in `color_enabled`.
+    #[default]
+    Auto,
+
+    /// Always colored.
+    Always,
+
+    /// Never colored.
+    Never,
+}
+
+/// Sets whether `report` colors its output, e.g. from a `--color` flag.
+///
+/// The setting is global and applies to all threads.
+///
+/// # Arguments
+///
+/// * choice - Whether to color the output.
+///
+/// # Examples
+///
+/// ```
+/// use mm_errors::cli::{self, ColorChoice};
+///
+/// cli::set_color(ColorChoice::Never);
+/// assert!(!cli::color_enabled());
+/// # cli::set_color(ColorChoice::Auto);
+/// ```
+///
+pub fn set_color(choice: ColorChoice) {
+    *COLOR.write().unwrap_or_else(|e| e.into_inner()) = choice;
+}
+
+/// Returns `true` if the output of `report` to the standard error is colored.
+///
+/// Unless set otherwise by `set_color`, this follows the conventions of
+/// terminal programs: `CLICOLOR_FORCE` set to anything but `0` forces
+/// colors, then `NO_COLOR` set to anything but an empty string disables
+/// them, as does `CLICOLOR=0`. Otherwise the output is colored if the
+/// standard error is a terminal.
+///
+pub fn color_enabled() -> bool {
+    match *COLOR.read().unwrap_or_else(|e| e.into_inner()) {
+        ColorChoice::Always => true,
+        ColorChoice::Never => false,
+        ColorChoice::Auto => {
+            let var = |name| env::var_os(name).filter(|value| !value.is_empty());
+            if var("CLICOLOR_FORCE").is_some_and(|value| value != "0") {
+                return true;
+            }
+            if var("NO_COLOR").is_some() || var("CLICOLOR").is_some_and(|value| value == "0") {
+                return false;
+            }
+            io::stderr().is_terminal()
+        }
+    }
+}
+
+/// Returns `e` rendered in the style of cargo, at the verbosity set by `set_verbosity`.
+///
+/// See `render_at` for the format.
+///
+/// # Arguments
+///
+/// * e - Error to render.
+/// * color - Whether to use ANSI colors.
+///
+pub fn render(e: &Error, color: bool) -> String {
+    render_at(e, color, VERBOSITY.load(Ordering::Relaxed))
+}
+
+/// Returns `e` rendered in the style of cargo, down to the depth of `verbosity`.
+///
+/// The first line is the message of the outermost error prefixed with
+/// `error` and its code, in red if `color`, and `Error::help` and the
+/// documentation URL of the code follow as `help` lines. `verbosity` adds,
+/// so `-v` flags can be passed as they are:
+///
+/// | Verbosity | Output |
+/// |-----------|--------|
+/// | 0 | The first line only |
+/// | 1 | Each inner message on a line prefixed with an indented `caused by`, as in `Error::report` |
+/// | 2 | The file, line and metadata of each level, on `at` and `with` lines |
+/// | 3 | `Error::backtrace`, or else a hint to enable backtraces unless disabled by `set_backtrace_hint` |
+///
+/// # Arguments
+///
+/// * e - Error to render.
+/// * color - Whether to use ANSI colors.
+/// * verbosity - Depth of the output, from 0 to 3.
+///
+/// # Examples
+///
+/// ```
+/// use mm_errors::{cli, Error};
+///
+/// let inner = Error::new("invalid digit", "parse.rs", 7);
+/// let e = Error::context(inner, "reading the port number", "config.rs", 3)
+///     .with_code("CFG002")
+///     .with_meta_keyed("path", "app.toml");
+///
+/// assert_eq!(cli::render_at(&e, false, 0), "error[CFG002]: reading the port number");
+/// assert_eq!(
+///     cli::render_at(&e, false, 1),
+///     "error[CFG002]: reading the port number\n  \
+///      caused by: invalid digit");
+/// assert_eq!(
+///     cli::render_at(&e, false, 2),
+///     "error[CFG002]: reading the port number\n  \
+///      at config.rs:3\n  \
+///      with path = \"app.toml\"\n  \
+///      caused by: invalid digit\n    \
+///      at parse.rs:7");
+/// # if e.backtrace().is_none() {
+/// assert!(cli::render_at(&e, false, 3)
+///     .ends_with("\nnote: run with `RUST_BACKTRACE=1` environment variable to display a backtrace"));
+/// # }
+/// assert!(cli::render_at(&e, true, 1).starts_with("\x1b[1;31merror\x1b[0m[CFG002]: "));
+/// ```
+///
+pub fn render_at(e: &Error, color: bool, verbosity: u8) -> String {
+    CliReport { error: e, color, verbosity, backtrace_hint: BACKTRACE_HINT.load(Ordering::Relaxed) }.to_string()
+}
+
+/// Returns the first line of `e` rendered in the style of cargo, as written by `report` in quiet mode.
+///
+/// This is `render_at` with verbosity 0 without the `help` lines.
+///
+/// # Arguments
+///
+/// * e - Error to render.
+/// * color - Whether to use ANSI colors.
+///
+pub fn render_summary(e: &Error, color: bool) -> String {
+    let rendered = render_at(e, color, 0);
+    rendered.lines().next().unwrap_or_default().to_string()
+}
+
+/// Sets the verbosity of `render` and `report`, e.g. the number of `-v` flags.
+///
+/// The default is 1. The setting is global and applies to all threads.
+///
+/// # Arguments
+///
+/// * verbosity - Depth of the output, as described in `render_at`.
+///
+pub fn set_verbosity(verbosity: u8) {
+    VERBOSITY.store(verbosity, Ordering::Relaxed);
+}
+
+/// Sets whether `render` hints at `RUST_BACKTRACE` for errors without a backtrace.
+///
+/// The hint is shown at verbosity 3 only, and is enabled by default. The
+/// setting is global and applies to all threads.
+///
+pub fn set_backtrace_hint(enabled: bool) {
+    BACKTRACE_HINT.store(enabled, Ordering::Relaxed);
+}
@@ -0,0 +1,164 @@
+//! Compression of serialized errors.
+//!
+//! Reports carrying many levels, metadata and aggregates can be several
+//! kilobytes large. `compress` compresses such output and prefixes it with a
+//! magic header naming the method, which `decompress` reads back. Input
+//! without the header is returned as it is, so readers accept both compressed
+//! and plain output.
+//!
+//! This module is available with the `gzip` or the `zstd` feature, and each
+//! method with its feature.
+
+use std::io::{Read, Write};
+
+use super::{Error, Result};
+
+/// Magic bytes starting compressed data, followed by a byte naming the method.
+pub const MAGIC: &[u8; 4] = b"MMEZ";
+
+/// Maximum size of decompressed data, so small malicious input can't exhaust memory.
+pub const MAX_DECOMPRESSED: u64 = 16 * 1024 * 1024;
+
+/// Compression method.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Method {
+    /// gzip, available with the `gzip` feature.
+    #[cfg(feature = "gzip")]
+    Gzip,
+
+    /// Zstandard, available with the `zstd` feature.
+    #[cfg(feature = "zstd")]
+    Zstd,
+}
+
+impl Method {
+    fn id(self) -> u8 {
+        match self {
+            #[cfg(feature = "gzip")]
+            Method::Gzip => 1,
+            #[cfg(feature = "zstd")]
+            Method::Zstd => 2,
+        }
+    }
+}
+
+/// Returns `data` compressed with `method`, prefixed with the magic header.
+///
+/// # Arguments
+///
+/// * data - Data to compress, e.g. the output of `Error::to_json`.
+/// * method - Compression method.
+///
+/// # Examples
+///
+/// ```
+/// use mm_errors::Error;
+/// use mm_errors::compress::{self, Method};
+///
+/// # #[cfg(feature = "gzip")]
+/// # let method = Method::Gzip;
+/// # #[cfg(not(feature = "gzip"))]
+/// # let method = Method::Zstd;
+/// let mut e = Error::new("disk full", "storage.rs", 1);
+/// for line in 2..=50 {
+///     e = Error::context(e, "retrying the write", "retry.rs", line);
+/// }
+///
+/// let compressed = compress::compress(e.to_json().as_bytes(), method);
+/// assert!(compressed.starts_with(compress::MAGIC));
+/// assert!(compressed.len() < e.to_json().len());
+///
+/// let json = compress::decompress(&compressed).unwrap();
+/// assert_eq!(json, e.to_json().as_bytes());
+/// assert_eq!(compress::decompress(b"{}").unwrap(), b"{}");
+/// ```
+///
+pub fn compress(data: &[u8], method: Method) -> Vec<u8> {
+    let mut out = MAGIC.to_vec();
+    out.push(method.id());
+    match method {
+        #[cfg(feature = "gzip")]
+        Method::Gzip => {
+            let mut encoder = flate2::write::GzEncoder::new(out, flate2::Compression::default());
+            encoder.write_all(data).expect("writing to a `Vec` never fails");
+            encoder.finish().expect("writing to a `Vec` never fails")
+        }
+        #[cfg(feature = "zstd")]
+        Method::Zstd => {
+            let mut encoder = zstd::stream::Encoder::new(out, 0).expect("the default level is valid");
+            encoder.write_all(data).expect("writing to a `Vec` never fails");
+            encoder.finish().expect("writing to a `Vec` never fails")
+        }
+    }
+}
+
+/// Returns `data` decompressed, or `data` itself if it has no magic header.
+///
+/// # Arguments
+///
+/// * data - Data returned by `compress`, or uncompressed data.
+///
+/// # Errors
+///
+/// Returns an error if the method is unknown or not enabled, the data is
+/// corrupt, or it decompresses to more than `MAX_DECOMPRESSED` bytes.
+///
+/// # Examples
+///
+/// ```
+/// use mm_errors::compress::{self, Method};
+///
+/// # #[cfg(feature = "gzip")]
+/// # let method = Method::Gzip;
+/// # #[cfg(not(feature = "gzip"))]
+/// # let method = Method::Zstd;
+/// let bomb = compress::compress(&vec![0; compress::MAX_DECOMPRESSED as usize + 1], method);
+/// assert!(bomb.len() < 1024 * 1024);
+/// assert!(compress::decompress(&bomb).is_err());
+/// ```
+///
+pub fn decompress(data: &[u8]) -> Result<Vec<u8>> {
+    let rest = match data.strip_prefix(&MAGIC[..]) {
+        Some(rest) => rest,
+        None => return Ok(data.to_vec()),
+    };
+    let (&id, compressed) = rest.split_first()
+        .ok_or_else(|| Error::here("compressed data has no method"))?;
+    let mut out = Vec::new();
+    let read = match id {
+        #[cfg(feature = "gzip")]
+        1 => flate2::read::GzDecoder::new(compressed).take(MAX_DECOMPRESSED + 1).read_to_end(&mut out),
+        #[cfg(feature = "zstd")]
+        2 => zstd::stream::Decoder::new(compressed)
+            .and_then(|d| d.take(MAX_DECOMPRESSED + 1).read_to_end(&mut out)),
+        id => return Err(Error::here(&format!("unsupported compression method {}", id))),
+    };
+    read.map_err(|e| Error::context(e, "decompressing data", file!(), line!()))?;
+    if out.len() as u64 > MAX_DECOMPRESSED {
+        return Err(Error::here(&format!("decompressed data exceeds {} bytes", MAX_DECOMPRESSED)));
+    }
+    Ok(out)
+}
+
+impl Error {
+    /// Returns `self` as JSON compressed with `method`.
+    ///
+    /// This is `compress::compress` of the output of `to_json`.
+    ///
+    pub fn to_json_compressed(&self, method: Method) -> Vec<u8> {
+        compress(self.to_json().as_bytes(), method)
+    }
+
+    /// Parses JSON written by `to_json_compressed`, or plain JSON written by `to_json`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if decompressing fails, or `from_json` fails to parse the JSON.
+    ///
+    pub fn from_json_compressed(data: &[u8]) -> Result<Error> {
+        let json = decompress(data)?;
+        let json = String::from_utf8(json)
+            .map_err(|e| Error::context(e, "decoding JSON", file!(), line!()))?;
+        Error::from_json(&json)
+    }
+}
@@ -0,0 +1,620 @@
+//! Errors of targets without an allocator.
+//!
+//! `CoreError` depends on `core` only, so firmware can use it with the `std`
+//! feature disabled, and share error codes and messages with host tools,
+//! which convert it into `Error`.
+//!
+//! Where even static strings cost too much flash or bandwidth, `CompactError`
+//! holds the number of the code in the catalog and an index of the location
+//! only, and `Decoder` turns it back into an `Error` on the host, from the
+//! catalog exported by `catalog::export`.
+//!
+//! With the `heapless` feature, `CoreChain` chains `CoreError`s with context,
+//! in arrays of a fixed capacity.
+//!
+//! With the `panic-handler` feature and without `std`, the crate provides the
+//! `#[panic_handler]` of the firmware, which records panics as `CoreError`s
+//! in a `PanicSlot` for post-mortem readout, see `set_panic_slot`.
+//!
+//! With the `defmt` feature, `CoreError`, `CompactError` and `Error` implement
+//! `defmt::Format`, so they can be logged over RTT. The format strings are
+//! interned, and only the code, message, file and line are sent.
+
+#[cfg(feature = "panic-handler")]
+use core::cell::UnsafeCell;
+use core::fmt;
+#[cfg(feature = "panic-handler")]
+use core::sync::atomic::{AtomicPtr, AtomicU8, Ordering};
+#[cfg(feature = "std")]
+use std::borrow::Cow;
+#[cfg(feature = "std")]
+use std::collections::BTreeMap;
+
+#[cfg(feature = "std")]
+use super::{catalog, json, Error, ErrorKind, Metadata, Result};
+
+/// Error of a target without an allocator, e.g. bare-metal firmware.
+///
+/// Everything is static and the type is `Copy`, so it can be returned or
+/// kept in a `static` without allocating. Use `core_error!` to create it
+/// where the error occurred.
+///
+/// With the `std` feature, it is converted into an `Error` with the same
+/// message, code, file and line.
+///
+/// # Examples
+///
+/// ```
+/// # #[macro_use] extern crate mm_errors;
+///
+/// use mm_errors::Error;
+/// use mm_errors::embedded::CoreError;
+///
+/// # fn main() {
+/// fn read_sensor() -> Result<u16, CoreError> {
+///     Err(core_error!("SNS001", "sensor not responding"))
+/// }
+///
+/// let e = read_sensor().unwrap_err();
+/// assert_eq!(e.code, Some("SNS001"));
+/// assert_eq!(e.to_string(), "[SNS001] sensor not responding");
+///
+/// let e = Error::from(e);
+/// assert_eq!(e.code.as_deref(), Some("SNS001"));
+/// assert_eq!(e.message(), Some("sensor not responding"));
+/// # }
+/// ```
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CoreError {
+    /// Error message.
+    pub message: &'static str,
+
+    /// Error code identifying the kind of failure, e.g. `"E042"`.
+    pub code: Option<&'static str>,
+
+    /// File where error occurred.
+    pub file: &'static str,
+
+    /// line number where error occurred.
+    pub line: u32,
+}
+
+impl CoreError {
+    /// Returns a new instance of `CoreError`.
+    ///
+    /// # Arguments
+    ///
+    /// * message - Error message.
+    /// * file - File where error occurred.
+    /// * line - Line number where error occurred.
+    ///
+    pub const fn new(message: &'static str, file: &'static str, line: u32) -> CoreError {
+        CoreError { message, code: None, file, line }
+    }
+
+    /// Returns `self` with `code`.
+    pub const fn with_code(mut self, code: &'static str) -> CoreError {
+        self.code = Some(code);
+        self
+    }
+}
+
+impl fmt::Display for CoreError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.code {
+            Some(code) => write!(f, "[{}] {}", code, self.message),
+            None => f.write_str(self.message),
+        }
+    }
+}
+
+impl core::error::Error for CoreError {}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for CoreError {
+    fn format(&self, f: defmt::Formatter) {
+        match self.code {
+            Some(code) => defmt::write!(f, "[{=str}] {=str} at {=str}:{=u32}", code, self.message, self.file, self.line),
+            None => defmt::write!(f, "{=str} at {=str}:{=u32}", self.message, self.file, self.line),
+        }
+    }
+}
+
+/// Writes the code of the outermost error having one and the first message in the chain.
+#[cfg(all(feature = "defmt", feature = "std"))]
+impl defmt::Format for Error {
+    fn format(&self, f: defmt::Formatter) {
+        self.mark_reported();
+        let code = self.chain()
+            .filter_map(|e| e.downcast_ref::<Error>())
+            .find_map(|e| e.code.as_deref());
+        let message = super::report::first_message(self);
+        match code {
+            Some(code) => defmt::write!(f, "[{=str}] {=str} at {=str}:{=u32}", code, &*message, &*self.file, self.line),
+            None => defmt::write!(f, "{=str} at {=str}:{=u32}", &*message, &*self.file, self.line),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<CoreError> for Error {
+    fn from(e: CoreError) -> Error {
+        let error = Error::new(e.message, e.file, e.line);
+        match e.code {
+            Some(code) => error.with_code(code),
+            None => error,
+        }
+    }
+}
+
+/// Error of a constrained target, holding a code number and a location index only.
+///
+/// The code is the number of the code in the catalog, as set by
+/// `CodeInfo::number`, and the location the index of the place where the
+/// error occurred in a table of locations, or else its line, as set by
+/// `compact_error!`. The error takes 4 bytes over the wire, as written by
+/// `to_bytes`, and is decoded on the host by `Decoder`.
+///
+/// # Examples
+///
+/// ```
+/// use mm_errors::catalog::{self, CodeInfo, ExportFormat};
+/// use mm_errors::embedded::{CompactError, Decoder};
+///
+/// // On the target.
+/// let bytes = CompactError::new(17, 3).to_bytes();
+///
+/// // On the host, with the catalog exported from the shared code definitions.
+/// catalog::register(CodeInfo::new("SNS001", "Sensor not responding").number(17)).unwrap();
+/// let decoder = Decoder::from_json(&catalog::export(ExportFormat::Json))
+///     .unwrap()
+///     .location(3, "src/sensor.rs", 42);
+///
+/// let e = decoder.decode(CompactError::from_bytes(bytes));
+/// assert_eq!(e.code.as_deref(), Some("SNS001"));
+/// assert_eq!(e.message(), Some("Sensor not responding"));
+/// assert_eq!((&*e.file, e.line), ("src/sensor.rs", 42));
+///
+/// let e = decoder.decode(CompactError::new(99, 7));
+/// assert_eq!(e.code, None);
+/// assert_eq!(e.message(), Some("unknown error code 99"));
+/// assert_eq!((&*e.file, e.line), ("", 7));
+/// ```
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CompactError {
+    /// Number of the error code.
+    pub code: u16,
+
+    /// Index of the location where error occurred.
+    pub location: u16,
+}
+
+impl CompactError {
+    /// Returns a new instance of `CompactError`.
+    ///
+    /// # Arguments
+    ///
+    /// * code - Number of the error code.
+    /// * location - Index of the location where error occurred.
+    ///
+    pub const fn new(code: u16, location: u16) -> CompactError {
+        CompactError { code, location }
+    }
+
+    /// Returns `self` as 4 bytes, the code then the location, in little endian.
+    pub const fn to_bytes(self) -> [u8; 4] {
+        let code = self.code.to_le_bytes();
+        let location = self.location.to_le_bytes();
+        [code[0], code[1], location[0], location[1]]
+    }
+
+    /// Returns the error written by `to_bytes`.
+    pub const fn from_bytes(bytes: [u8; 4]) -> CompactError {
+        CompactError {
+            code: u16::from_le_bytes([bytes[0], bytes[1]]),
+            location: u16::from_le_bytes([bytes[2], bytes[3]]),
+        }
+    }
+}
+
+impl fmt::Display for CompactError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "error code {} at location {}", self.code, self.location)
+    }
+}
+
+impl core::error::Error for CompactError {}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for CompactError {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(f, "error code {=u16} at location {=u16}", self.code, self.location)
+    }
+}
+
+/// Decoder of `CompactError`s into `Error`s, on the host.
+///
+/// The code number is looked up in the catalog the decoder was built from,
+/// giving the code and its title as the message, and the location in the
+/// table of `location`. Errors with unknown numbers have no code, and
+/// unknown locations an empty file and the index as the line.
+///
+/// This is available with the `std` feature.
+///
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Default)]
+pub struct Decoder {
+    codes: BTreeMap<u32, (String, String)>,
+    locations: BTreeMap<u16, (String, u32)>,
+}
+
+#[cfg(feature = "std")]
+impl Decoder {
+    /// Returns a new instance of `Decoder` with the codes registered in the catalog.
+    pub fn from_catalog() -> Decoder {
+        let codes = catalog::entries()
+            .into_iter()
+            .filter_map(|info| Some((info.number?, (info.code.to_string(), info.title.to_string()))))
+            .collect();
+        Decoder { codes, locations: BTreeMap::new() }
+    }
+
+    /// Returns a new instance of `Decoder` with the codes of a catalog exported as JSON.
+    ///
+    /// # Arguments
+    ///
+    /// * json - Output of `catalog::export` with `ExportFormat::Json`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `json` is not an exported catalog.
+    ///
+    pub fn from_json(json: &str) -> Result<Decoder> {
+        let codes = json::parse_catalog(json)?
+            .into_iter()
+            .map(|(number, code, title)| (number, (code, title)))
+            .collect();
+        Ok(Decoder { codes, locations: BTreeMap::new() })
+    }
+
+    /// Returns `self` decoding the location `index` as `file` and `line`.
+    pub fn location<F: Into<String>>(mut self, index: u16, file: F, line: u32) -> Decoder {
+        self.locations.insert(index, (file.into(), line));
+        self
+    }
+
+    /// Returns `e` as an `Error`.
+    pub fn decode(&self, e: CompactError) -> Error {
+        let (file, line) = match self.locations.get(&e.location) {
+            Some(&(ref file, line)) => (Cow::Owned(file.clone()), line),
+            None => (Cow::Borrowed(""), u32::from(e.location)),
+        };
+        let (code, message) = match self.codes.get(&u32::from(e.code)) {
+            Some((code, title)) => (Some(Cow::Owned(code.clone())), title.clone()),
+            None => (None, format!("unknown error code {}", e.code)),
+        };
+        Error { file, line, kind: ErrorKind::String(message), code, metadata: Metadata::new() }
+    }
+}
+
+/// Slot of the last panic, recorded by the panic handler of the `panic-handler` feature.
+///
+/// Declare the slot as a `static` and pass it to `set_panic_slot`. Only the
+/// first panic is kept until `take` empties the slot, so put it in RAM kept
+/// across resets, e.g. a section not zeroed at boot, to read it after the
+/// reset, or read it with a debugger.
+///
+/// The slot is claimed with compare-and-swap, so it can be shared between
+/// threads and interrupts, but needs a target with atomic compare-and-swap.
+/// A `store` or `take` racing with another one fails instead of waiting.
+///
+/// This is available with the `panic-handler` feature.
+///
+/// # Examples
+///
+/// ```
+/// # #[macro_use] extern crate mm_errors;
+///
+/// use mm_errors::embedded::PanicSlot;
+///
+/// static LAST_PANIC: PanicSlot = PanicSlot::new();
+///
+/// # fn main() {
+/// // The panic handler stores the panics of the firmware, e.g. after
+/// // `embedded::set_panic_slot(&LAST_PANIC)` at boot.
+/// assert!(LAST_PANIC.store(core_error!("index out of bounds")));
+/// assert!(!LAST_PANIC.store(core_error!("second panic")));
+///
+/// assert_eq!(LAST_PANIC.take().map(|e| e.message), Some("index out of bounds"));
+/// assert_eq!(LAST_PANIC.take(), None);
+/// # }
+/// ```
+///
+#[cfg(feature = "panic-handler")]
+#[derive(Debug)]
+pub struct PanicSlot {
+    /// One of `EMPTY`, `WRITING`, `FULL` and `READING`.
+    state: AtomicU8,
+    error: UnsafeCell<Option<CoreError>>,
+}
+
+// SAFETY: `error` is accessed only by the caller which moved `state` from
+// `EMPTY` to `WRITING`, or from `FULL` to `READING`, so by one at a time.
+#[cfg(feature = "panic-handler")]
+unsafe impl Sync for PanicSlot {}
+
+#[cfg(feature = "panic-handler")]
+impl PanicSlot {
+    const EMPTY: u8 = 0;
+    const WRITING: u8 = 1;
+    const FULL: u8 = 2;
+    const READING: u8 = 3;
+
+    /// Returns a new empty instance of `PanicSlot`.
+    pub const fn new() -> PanicSlot {
+        PanicSlot { state: AtomicU8::new(PanicSlot::EMPTY), error: UnsafeCell::new(None) }
+    }
+
+    /// Stores `e`, returning `false` if the slot is full or being accessed.
+    pub fn store(&self, e: CoreError) -> bool {
+        let claimed = self.state
+            .compare_exchange(PanicSlot::EMPTY, PanicSlot::WRITING, Ordering::Acquire, Ordering::Relaxed);
+        if claimed.is_err() {
+            return false;
+        }
+        // SAFETY: the slot is claimed for writing, see `Sync`.
+        unsafe { *self.error.get() = Some(e) };
+        self.state.store(PanicSlot::FULL, Ordering::Release);
+        true
+    }
+
+    /// Returns the stored error, emptying the slot, or `None` if it is empty or being accessed.
+    pub fn take(&self) -> Option<CoreError> {
+        let claimed = self.state
+            .compare_exchange(PanicSlot::FULL, PanicSlot::READING, Ordering::Acquire, Ordering::Relaxed);
+        if claimed.is_err() {
+            return None;
+        }
+        // SAFETY: the slot is claimed for reading, see `Sync`.
+        let e = unsafe { (*self.error.get()).take() };
+        self.state.store(PanicSlot::EMPTY, Ordering::Release);
+        e
+    }
+}
+
+#[cfg(feature = "panic-handler")]
+impl Default for PanicSlot {
+    fn default() -> PanicSlot {
+        PanicSlot::new()
+    }
+}
+
+#[cfg(feature = "panic-handler")]
+static PANIC_SLOT: AtomicPtr<PanicSlot> = AtomicPtr::new(core::ptr::null_mut());
+
+/// `fn(&CoreError) -> !` called by the panic handler, or null.
+#[cfg(feature = "panic-handler")]
+static PANIC_HOOK: AtomicPtr<()> = AtomicPtr::new(core::ptr::null_mut());
+
+/// Sets the slot where the panic handler stores panics.
+///
+/// The message of the stored error is the message of the panic if it is a
+/// string literal, or else `panicked`, and the file and line are where the
+/// panic occurred. Panics are not stored by default.
+///
+/// This is available with the `panic-handler` feature.
+///
+#[cfg(feature = "panic-handler")]
+pub fn set_panic_slot(slot: &'static PanicSlot) {
+    PANIC_SLOT.store(slot as *const PanicSlot as *mut PanicSlot, Ordering::Release);
+}
+
+/// Sets the function called by the panic handler after the panic is stored.
+///
+/// `hook` receives the panic, e.g. to log it, and resets the device. By
+/// default, the handler loops forever, so a watchdog or the debugger can
+/// take over.
+///
+/// This is available with the `panic-handler` feature.
+///
+#[cfg(feature = "panic-handler")]
+pub fn set_panic_hook(hook: fn(&CoreError) -> !) {
+    PANIC_HOOK.store(hook as *mut (), Ordering::Release);
+}
+
+#[cfg(all(feature = "panic-handler", not(feature = "std")))]
+#[panic_handler]
+fn panic(info: &core::panic::PanicInfo) -> ! {
+    let message = info.message().as_str().unwrap_or("panicked");
+    let (file, line) = match info.location() {
+        // SAFETY: panics of `core` are located with `Location::caller`, which is `'static`.
+        Some(location) => (unsafe { &*(location.file() as *const str) }, location.line()),
+        None => ("", 0),
+    };
+    let e = CoreError::new(message, file, line);
+    let slot = PANIC_SLOT.load(Ordering::Acquire);
+    if !slot.is_null() {
+        // SAFETY: the pointer was set from a `&'static PanicSlot`.
+        unsafe { &*slot }.store(e);
+    }
+    let hook = PANIC_HOOK.load(Ordering::Acquire);
+    if !hook.is_null() {
+        // SAFETY: the pointer was set from a `fn(&CoreError) -> !`.
+        let hook = unsafe { core::mem::transmute::<*mut (), fn(&CoreError) -> !>(hook) };
+        hook(&e);
+    }
+    loop {
+        core::hint::spin_loop();
+    }
+}
+
+/// Chain of `CoreError`s with context, in arrays of fixed capacity.
+///
+/// The chain holds up to `DEPTH` errors, from the root cause, and up to
+/// `CONTEXT` keyed values. On overflow, `context` drops the oldest error
+/// above the root cause, so the root cause and the latest context are kept,
+/// and `with_value` drops the new value. Both count the dropped items, as
+/// returned by `dropped`. `try_context` and `try_value` return the item
+/// instead.
+///
+/// With the `std` feature, the chain is converted into an `Error` with a
+/// level for each error, the values attached to the outermost level, and the
+/// number of dropped items, if any, under `dropped`.
+///
+/// This is available with the `heapless` feature.
+///
+/// # Examples
+///
+/// ```
+/// # #[macro_use] extern crate mm_errors;
+///
+/// use mm_errors::Error;
+/// use mm_errors::embedded::CoreChain;
+///
+/// # fn main() {
+/// let chain = CoreChain::<2, 1>::new(core_error!("SNS001", "sensor not responding"))
+///     .context(core_error!("reading the temperature"))
+///     .context(core_error!("sampling"))
+///     .with_value("channel", 3)
+///     .with_value("attempt", 2);
+///
+/// assert_eq!(chain.root().message, "sensor not responding");
+/// assert_eq!(chain.errors().map(|e| e.message).collect::<Vec<_>>(), ["sampling", "sensor not responding"]);
+/// assert_eq!(chain.values().collect::<Vec<_>>(), [("channel", 3)]);
+/// assert_eq!(chain.dropped(), 2);
+///
+/// let e = Error::from(chain);
+/// assert_eq!(e.message(), Some("sampling"));
+/// assert_eq!(e.chain().count(), 2);
+/// assert_eq!(e.metadata.rendered("channel"), Some("3"));
+/// # }
+/// ```
+///
+#[cfg(feature = "heapless")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CoreChain<const DEPTH: usize, const CONTEXT: usize> {
+    /// Errors, from the root cause.
+    errors: heapless::Vec<CoreError, DEPTH>,
+    values: heapless::Vec<(&'static str, i64), CONTEXT>,
+    dropped: u16,
+}
+
+#[cfg(feature = "heapless")]
+impl<const DEPTH: usize, const CONTEXT: usize> CoreChain<DEPTH, CONTEXT> {
+    /// Returns a new instance of `CoreChain` with `root` as root cause.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `DEPTH` is 0.
+    ///
+    pub fn new(root: CoreError) -> CoreChain<DEPTH, CONTEXT> {
+        let mut errors = heapless::Vec::new();
+        assert!(errors.push(root).is_ok(), "`CoreChain` needs a depth of at least 1");
+        CoreChain { errors, values: heapless::Vec::new(), dropped: 0 }
+    }
+
+    /// Returns `self` with `e` as outermost error, dropping the oldest error above the root cause if full.
+    pub fn context(mut self, e: CoreError) -> CoreChain<DEPTH, CONTEXT> {
+        if let Err(e) = self.try_context(e) {
+            self.dropped = self.dropped.saturating_add(1);
+            // The root cause is kept, so a chain of depth 1 drops `e` instead.
+            if self.errors.len() > 1 {
+                self.errors.remove(1);
+                let _ = self.errors.push(e);
+            }
+        }
+        self
+    }
+
+    /// Adds `e` as outermost error, or returns it if the chain is full.
+    pub fn try_context(&mut self, e: CoreError) -> core::result::Result<(), CoreError> {
+        self.errors.push(e)
+    }
+
+    /// Returns `self` with `value` attached under `key`, dropping it if full.
+    pub fn with_value(mut self, key: &'static str, value: i64) -> CoreChain<DEPTH, CONTEXT> {
+        if self.try_value(key, value).is_err() {
+            self.dropped = self.dropped.saturating_add(1);
+        }
+        self
+    }
+
+    /// Attaches `value` under `key`, or returns them if the context is full.
+    pub fn try_value(&mut self, key: &'static str, value: i64)
+                     -> core::result::Result<(), (&'static str, i64)> {
+        self.values.push((key, value))
+    }
+
+    /// Returns the outermost error.
+    pub fn outermost(&self) -> &CoreError {
+        self.errors.last().expect("a `CoreChain` is never empty")
+    }
+
+    /// Returns the root cause.
+    pub fn root(&self) -> &CoreError {
+        &self.errors[0]
+    }
+
+    /// Returns an iterator over the errors, from the outermost to the root cause.
+    pub fn errors(&self) -> impl Iterator<Item = &CoreError> {
+        self.errors.iter().rev()
+    }
+
+    /// Returns an iterator over the keys and values, in the order they were attached.
+    pub fn values(&self) -> impl Iterator<Item = (&'static str, i64)> + '_ {
+        self.values.iter().copied()
+    }
+
+    /// Returns the number of errors and values dropped on overflow.
+    pub fn dropped(&self) -> u16 {
+        self.dropped
+    }
+}
+
+#[cfg(feature = "heapless")]
+impl<const DEPTH: usize, const CONTEXT: usize> fmt::Display for CoreChain<DEPTH, CONTEXT> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, e) in self.errors().enumerate() {
+            if i > 0 {
+                f.write_str(": ")?;
+            }
+            write!(f, "{}", e)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(all(feature = "heapless", feature = "defmt"))]
+impl<const DEPTH: usize, const CONTEXT: usize> defmt::Format for CoreChain<DEPTH, CONTEXT> {
+    fn format(&self, f: defmt::Formatter) {
+        for (i, e) in self.errors().enumerate() {
+            if i > 0 {
+                defmt::write!(f, ": ");
+            }
+            defmt::write!(f, "{}", e);
+        }
+    }
+}
+
+#[cfg(all(feature = "heapless", feature = "std"))]
+impl<const DEPTH: usize, const CONTEXT: usize> From<CoreChain<DEPTH, CONTEXT>> for Error {
+    fn from(chain: CoreChain<DEPTH, CONTEXT>) -> Error {
+        let mut errors = chain.errors.iter();
+        let mut error = Error::from(*errors.next().expect("a `CoreChain` is never empty"));
+        for e in errors {
+            error = Error::context(error, e.message, e.file, e.line);
+            if let Some(code) = e.code {
+                error = error.with_code(code);
+            }
+        }
+        for &(key, value) in &chain.values {
+            error = error.with_meta_keyed(key, value);
+        }
+        if chain.dropped > 0 {
+            error = error.with_meta_keyed("dropped", chain.dropped);
+        }
+        error
+    }
+}
@@ -0,0 +1,147 @@
+//! Reporting errors to the Windows Event Log.
+//!
+//! Each error is written as an event of the source given to `EventLog::new`,
+//! with the output of `Error::report` followed by the `Display` output as its
+//! only string. The type of the event follows `Error::severity`: `Info` is
+//! written as information, `Warning` as a warning, and `Error` and
+//! `Critical` as errors. Errors are filtered as set by `filter::set_filter`
+//! and sampled as set by `rate::set_sampler`, and a sample rate above 1 is
+//! appended to the string.
+//!
+//! The event ID is the number of the code in the catalog if it fits in 16
+//! bits, or else a hash of the code in `1..=0xffff`, so the events of a code
+//! can be filtered in the Event Viewer. Errors without a code have the ID `0`.
+//!
+//! This module is available with the `windows` feature on Windows.
+
+use std::ffi::OsStr;
+use std::io;
+use std::os::windows::ffi::OsStrExt;
+use std::ptr;
+
+use windows_sys::Win32::Foundation::HANDLE;
+use windows_sys::Win32::System::EventLog::{
+    DeregisterEventSource, RegisterEventSourceW, ReportEventW, EVENTLOG_ERROR_TYPE,
+    EVENTLOG_INFORMATION_TYPE, EVENTLOG_WARNING_TYPE, REPORT_EVENT_TYPE,
+};
+
+use super::catalog::Severity;
+use super::{filter, rate, Error};
+
+/// Writes errors to the Windows Event Log.
+///
+/// # Examples
+///
+/// ```no_run
+/// use mm_errors::Error;
+/// use mm_errors::eventlog::EventLog;
+///
+/// let log = EventLog::new("myservice").unwrap();
+/// log.report(&Error::new("disk full", "storage.rs", 7).with_code("STO001")).unwrap();
+/// ```
+///
+#[derive(Debug)]
+pub struct EventLog {
+    handle: HANDLE,
+}
+
+// The handle is only passed to `ReportEventW`, which may be called from any thread.
+unsafe impl Send for EventLog {}
+unsafe impl Sync for EventLog {}
+
+impl EventLog {
+    /// Returns a new instance of `EventLog` writing events of `source` on the local computer.
+    ///
+    /// `source` is usually registered by the installer of the service under
+    /// `HKLM\SYSTEM\CurrentControlSet\Services\EventLog\Application`, or else
+    /// the events are written to the Application log with a note that the
+    /// source is unknown.
+    ///
+    /// # Arguments
+    ///
+    /// * source - Name of the event source.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the source can't be opened.
+    ///
+    pub fn new(source: &str) -> io::Result<EventLog> {
+        let source = wide(source);
+        // SAFETY: `source` is a null-terminated UTF-16 string.
+        let handle = unsafe { RegisterEventSourceW(ptr::null(), source.as_ptr()) };
+        if handle.is_null() {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(EventLog { handle })
+    }
+
+    /// Writes `e` as a single event, unless it is filtered or sampled out.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the event can't be written.
+    ///
+    pub fn report(&self, e: &Error) -> io::Result<()> {
+        if !filter::enabled(e) {
+            return Ok(());
+        }
+        let mut message = format!("{}\r\n\r\n{}", e.report(), e);
+        match rate::sample(e) {
+            Some(1) => {}
+            Some(rate) => message.push_str(&format!("\r\n\r\nsampled 1 in {}", rate)),
+            None => return Ok(()),
+        }
+        let message = wide(&message);
+        let strings = [message.as_ptr()];
+        // SAFETY: `handle` is open, and `strings` holds one null-terminated UTF-16 string.
+        let ok = unsafe {
+            ReportEventW(self.handle, event_type(e.severity()), 0, event_id(e), ptr::null_mut(),
+                         1, 0, strings.as_ptr(), ptr::null())
+        };
+        if ok == 0 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl Drop for EventLog {
+    fn drop(&mut self) {
+        // SAFETY: `handle` is open, and is not used after this.
+        unsafe {
+            DeregisterEventSource(self.handle);
+        }
+    }
+}
+
+/// Returns the event type of `severity`.
+fn event_type(severity: Severity) -> REPORT_EVENT_TYPE {
+    match severity {
+        Severity::Info => EVENTLOG_INFORMATION_TYPE,
+        Severity::Warning => EVENTLOG_WARNING_TYPE,
+        Severity::Error | Severity::Critical => EVENTLOG_ERROR_TYPE,
+    }
+}
+
+/// Returns the event ID of `e`, as described in the module level document.
+fn event_id(e: &Error) -> u32 {
+    let code = match e.code {
+        Some(ref code) => code,
+        None => return 0,
+    };
+    match e.code_number() {
+        Some(number) if number <= 0xffff => number,
+        _ => {
+            // FNV-1a, folded into the 16 bits of the ID.
+            let hash = code.bytes()
+                .fold(0x811c_9dc5u32, |h, b| (h ^ u32::from(b)).wrapping_mul(0x0100_0193));
+            (hash ^ hash >> 16) % 0xffff + 1
+        }
+    }
+}
+
+/// Returns `s` as a null-terminated UTF-16 string.
+fn wide(s: &str) -> Vec<u16> {
+    OsStr::new(s).encode_wide().chain(Some(0)).collect()
+}
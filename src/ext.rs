@@ -0,0 +1,66 @@
+//! Extensions for `Result`.
+
+use std::error;
+use std::marker;
+use std::panic;
+use std::result;
+
+use super::{Error, MultiError, Result};
+
+/// Adds combinators to `Result`s of any error type.
+pub trait ResultExt<T, E> {
+    /// Calls `f` with the error of `self` to produce an alternative value.
+    ///
+    /// If `f` also fails, the returned error wraps a `MultiError` holding
+    /// both the original and the fallback failure, in this order, so neither
+    /// is lost. The location of the returned errors is where this method is
+    /// called.
+    ///
+    /// # Arguments
+    ///
+    /// * f - Function producing the alternative, given the original error.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mm_errors::{MultiError, ResultExt};
+    ///
+    /// let port = "XXX".parse::<u16>().or_fallback(|_| Ok::<u16, std::num::ParseIntError>(8080));
+    /// assert_eq!(port.unwrap(), 8080);
+    ///
+    /// let e = "XXX".parse::<u16>().or_fallback(|_| "YYY".parse::<u16>()).unwrap_err();
+    /// let failures = e.chain().find_map(|e| e.downcast_ref::<MultiError>()).unwrap();
+    /// assert_eq!(failures.len(), 2);
+    /// assert!(e.to_string().contains("<context>fallback failed</context>"));
+    /// ```
+    ///
+    #[track_caller]
+    fn or_fallback<F, E2>(self, f: F) -> Result<T>
+        where F: FnOnce(&Error) -> result::Result<T, E2>,
+              E2: Into<Box<dyn error::Error + marker::Send + marker::Sync>>;
+}
+
+impl<T, E> ResultExt<T, E> for result::Result<T, E>
+    where E: Into<Box<dyn error::Error + marker::Send + marker::Sync>> {
+    #[track_caller]
+    fn or_fallback<F, E2>(self, f: F) -> Result<T>
+        where F: FnOnce(&Error) -> result::Result<T, E2>,
+              E2: Into<Box<dyn error::Error + marker::Send + marker::Sync>> {
+        let location = panic::Location::caller();
+        let (file, line) = (location.file(), location.line());
+        let original = match self {
+            Ok(x) => return Ok(x),
+            Err(e) => Error::context(e, "original attempt failed", file, line),
+        };
+        match f(&original) {
+            Ok(x) => {
+                original.mark_handled();
+                Ok(x)
+            }
+            Err(e) => {
+                let failures = MultiError::from(vec![original, Error::wrap(e, file, line)]);
+                Err(Error::context(failures, "fallback failed", file, line))
+            }
+        }
+    }
+}
@@ -0,0 +1,183 @@
+//! Fault injection for exercising error paths.
+//!
+//! Place `maybe_fail!` at failure points of the code under test, and make
+//! them fail from integration tests. A failure point is either named, failing
+//! only when enabled, or a probability, failing randomly.
+//!
+//! Named failure points are enabled with `enable`, or by listing their names,
+//! separated by commas, in the environment variable `MM_ERRORS_FAULTS`.
+//!
+//! This module is available with the `faultinject` feature, which is meant for
+//! test and development builds only.
+//!
+//! # Examples
+//!
+//! ```
+//! # #[macro_use] extern crate mm_errors;
+//!
+//! use mm_errors::faultinject;
+//!
+//! fn save() -> mm_errors::Result<()> {
+//!     maybe_fail!("save.write");
+//!     Ok(())
+//! }
+//!
+//! # fn main() {
+//!     assert!(save().is_ok());
+//!
+//!     faultinject::enable("save.write");
+//!     let e = save().unwrap_err();
+//!     assert!(e.to_string().contains("<reason>injected fault at `save.write`</reason>"));
+//!
+//!     faultinject::disable("save.write");
+//!     assert!(save().is_ok());
+//! # }
+//! ```
+
+use std::cell::Cell;
+use std::collections::hash_map::RandomState;
+use std::collections::HashSet;
+use std::env;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::Mutex;
+
+use super::{retry, Error};
+
+/// Name of the environment variable listing enabled failure points.
+pub const FAULTS_VAR: &str = "MM_ERRORS_FAULTS";
+
+static ENABLED: Mutex<Option<HashSet<String>>> = Mutex::new(None);
+
+thread_local! {
+    /// State of the random number generator of the current thread, 0 if not seeded yet.
+    static RNG: Cell<u64> = const { Cell::new(0) };
+}
+
+/// Failure point of `maybe_fail!`.
+pub trait FailPoint {
+    /// Returns the message of the injected error if the failure point fails now.
+    fn fail(&self) -> Option<String>;
+}
+
+/// Named failure point, failing when enabled.
+impl FailPoint for &str {
+    fn fail(&self) -> Option<String> {
+        if is_enabled(self) {
+            Some(format!("injected fault at `{}`", self))
+        } else {
+            None
+        }
+    }
+}
+
+/// Failure point failing with the probability, from 0.0 to 1.0.
+impl FailPoint for f64 {
+    fn fail(&self) -> Option<String> {
+        if random() < *self {
+            Some(format!("injected fault with probability {}", self))
+        } else {
+            None
+        }
+    }
+}
+
+/// Enables the failure point `name`.
+pub fn enable(name: &str) {
+    enabled(|names| names.insert(name.to_string()));
+}
+
+/// Disables the failure point `name`.
+///
+/// Failure points listed in `MM_ERRORS_FAULTS` stay enabled.
+///
+pub fn disable(name: &str) {
+    enabled(|names| names.remove(name));
+}
+
+/// Disables every failure point enabled by `enable`.
+pub fn reset() {
+    enabled(|names| names.clear());
+}
+
+/// Returns `true` if the failure point `name` is enabled by `enable` or `MM_ERRORS_FAULTS`.
+pub fn is_enabled(name: &str) -> bool {
+    if enabled(|names| names.contains(name)) {
+        return true;
+    }
+    match env::var(FAULTS_VAR) {
+        Ok(names) => names.split(',').any(|n| n.trim() == name),
+        Err(_) => false,
+    }
+}
+
+/// Seeds the random number generator used by probabilities on the current thread.
+///
+/// Without a seed, each thread uses a random one. Seed it to make probabilistic
+/// failures reproducible.
+///
+pub fn set_seed(seed: u64) {
+    RNG.with(|rng| rng.set(retry::mix_seed(seed)));
+}
+
+#[doc(hidden)]
+#[track_caller]
+pub fn check<P: FailPoint>(point: P) -> Option<Error> {
+    point.fail().map(|message| Error::here(&message))
+}
+
+fn enabled<F, T>(f: F) -> T
+    where F: FnOnce(&mut HashSet<String>) -> T {
+    let mut enabled = ENABLED.lock().unwrap_or_else(|e| e.into_inner());
+    f(enabled.get_or_insert_with(HashSet::new))
+}
+
+/// Returns a random number in `[0, 1)`.
+fn random() -> f64 {
+    RNG.with(|rng| {
+        let mut x = rng.get();
+        if x == 0 {
+            x = RandomState::new().build_hasher().finish() | 1;
+        }
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        rng.set(x);
+        (x >> 11) as f64 / (1u64 << 53) as f64
+    })
+}
+
+/// Returns an injected error if the failure point fails.
+///
+/// The argument is either the name of a failure point, failing when enabled,
+/// or a probability from 0.0 to 1.0. The error is located where the macro is
+/// called, and converted into the error type of the function with `From`.
+///
+/// This macro is available with the `faultinject` feature.
+///
+/// # Examples
+///
+/// ```
+/// # #[macro_use] extern crate mm_errors;
+///
+/// use mm_errors::faultinject;
+///
+/// fn flaky() -> mm_errors::Result<()> {
+///     maybe_fail!(0.5);
+///     Ok(())
+/// }
+///
+/// # fn main() {
+///     faultinject::set_seed(42);
+///     let failures = (0..1000).filter(|_| flaky().is_err()).count();
+///     assert!(failures > 400 && failures < 600);
+/// # }
+/// ```
+///
+#[macro_export]
+macro_rules! maybe_fail {
+    ($point:expr) => ({
+        if let Some(e) = $crate::faultinject::check($point) {
+            return Err(::std::convert::From::from(e));
+        }
+    })
+}
@@ -0,0 +1,83 @@
+//! Structured logging fields of errors.
+//!
+//! With the `slog` feature, `Error` implements `slog::KV`, writing the
+//! following fields, and `slog::Value`, writing `Display` under the given key.
+//!
+//! | Key | Value |
+//! |-----|-------|
+//! | `error.message` | First message in the chain, as in `Error::report` |
+//! | `error.file` | File of the outermost error, as rendered by `Display` |
+//! | `error.line` | Line of the outermost error |
+//! | `error.context` | Context message, only for `ErrorKind::Context` |
+//! | `error.code` | Error code, only if the error has a code |
+//! | `error.root_cause` | Message of the innermost error |
+//! | `error.chain` | Whole chain, as written by `Error::to_json` |
+//!
+//! # Examples
+//!
+//! ```
+//! use std::sync::{Arc, Mutex};
+//!
+//! use slog::{info, o, Drain, Key, Logger, OwnedKVList, Record, Serializer, KV};
+//!
+//! use mm_errors::Error;
+//!
+//! /// Collects the keys of the fields of each record.
+//! struct Keys(Arc<Mutex<Vec<Key>>>);
+//!
+//! impl Serializer for Keys {
+//!     fn emit_arguments(&mut self, key: Key, _: &std::fmt::Arguments) -> slog::Result {
+//!         self.0.lock().unwrap().push(key);
+//!         Ok(())
+//!     }
+//! }
+//!
+//! impl Drain for Keys {
+//!     type Ok = ();
+//!     type Err = slog::Never;
+//!
+//!     fn log(&self, record: &Record, _: &OwnedKVList) -> Result<(), slog::Never> {
+//!         record.kv().serialize(record, &mut Keys(self.0.clone())).unwrap();
+//!         Ok(())
+//!     }
+//! }
+//!
+//! let keys = Arc::new(Mutex::new(Vec::new()));
+//! let log = Logger::root(Keys(keys.clone()), o!());
+//!
+//! let e = Error::context(Error::new("disk full", "storage.rs", 7), "saving", "service.rs", 21);
+//! info!(log, "request failed"; &e);
+//!
+//! let keys = keys.lock().unwrap();
+//! for key in ["error.message", "error.file", "error.line", "error.context", "error.root_cause"] {
+//!     assert!(keys.contains(&key));
+//! }
+//! assert!(!keys.contains(&"error.code"));
+//! ```
+
+use slog::{Key, Record, Serializer, Value, KV};
+
+use super::{render, report, Error, ErrorKind};
+
+impl KV for Error {
+    fn serialize(&self, _record: &Record, serializer: &mut dyn Serializer) -> slog::Result {
+        let (file, line) = render::location(&self.file, self.line);
+        serializer.emit_str("error.message", &report::first_message(self))?;
+        serializer.emit_str("error.file", &file)?;
+        serializer.emit_u32("error.line", line)?;
+        if let ErrorKind::Context(ref context, _) = self.kind {
+            serializer.emit_str("error.context", context)?;
+        }
+        if let Some(ref code) = self.code {
+            serializer.emit_str("error.code", code)?;
+        }
+        serializer.emit_str("error.root_cause", &report::root_message(self))?;
+        serializer.emit_str("error.chain", &self.to_json())
+    }
+}
+
+impl Value for Error {
+    fn serialize(&self, _record: &Record, key: Key, serializer: &mut dyn Serializer) -> slog::Result {
+        serializer.emit_arguments(key, &format_args!("{}", self))
+    }
+}
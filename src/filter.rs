@@ -0,0 +1,219 @@
+//! Filtering the errors emitted by the reporters and the log integrations.
+//!
+//! A filter is a list of directives separated by commas, in the syntax of
+//! `env_logger`. Each directive is either `module=level`, applying to errors
+//! from `module` and its submodules, `module`, emitting every error from it,
+//! or `level`, applying to errors from modules without a directive. The most
+//! specific module wins, and errors from modules without any directive are
+//! all emitted.
+//!
+//! The levels are the severities, `info`, `warning`, `error` and `critical`,
+//! emitting errors of that severity or higher, and `off`, emitting none.
+//! `warn` and `fatal` are accepted for `warning` and `critical`.
+//!
+//! The module of an error is `Error::module_path`. The filter is read from
+//! the environment variable `MM_ERRORS_FILTER` the first time an error is
+//! emitted, unless it is set with `set_filter`.
+//!
+//! # Examples
+//!
+//! ```
+//! use mm_errors::{Error, Severity};
+//! use mm_errors::filter::Filter;
+//!
+//! let filter = Filter::parse("warning,storage=off,net=fatal").unwrap();
+//!
+//! assert!(!filter.enabled(&Error::new("disk full", "src/storage/disk.rs", 7)));
+//! assert!(!filter.enabled(&Error::new("timeout", "src/net.rs", 3)));
+//! assert!(filter.enabled(&Error::new("timeout", "src/net.rs", 3).with_severity(Severity::Critical)));
+//! assert!(filter.enabled(&Error::new("bad input", "src/api.rs", 1)));
+//! assert!(!filter.enabled(&Error::new("bad input", "src/api.rs", 1).with_severity(Severity::Info)));
+//! ```
+
+use std::env;
+use std::str::FromStr;
+use std::sync::{Arc, OnceLock, RwLock};
+
+use super::catalog::{self, Severity};
+use super::{hooks, Error, Result};
+
+/// Name of the environment variable holding the filter.
+pub const FILTER_VAR: &str = "MM_ERRORS_FILTER";
+
+static FILTER: RwLock<Option<Arc<Filter>>> = RwLock::new(None);
+
+static ENV_FILTER: OnceLock<Option<Filter>> = OnceLock::new();
+
+/// Filter of errors by module and severity.
+///
+/// See the module level document for the syntax.
+///
+#[derive(Debug, Clone, Default)]
+pub struct Filter {
+    /// Directives in the order they were given, as module and lowest
+    /// severity emitted, `None` for `off`.
+    directives: Vec<(Option<String>, Option<Severity>)>,
+}
+
+impl Filter {
+    /// Parses the filter `s`.
+    ///
+    /// # Arguments
+    ///
+    /// * s - Directives separated by commas, e.g. `storage=off,net=fatal`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a directive has an unknown level.
+    ///
+    pub fn parse(s: &str) -> Result<Filter> {
+        let mut directives = Vec::new();
+        for directive in s.split(',').map(str::trim).filter(|d| !d.is_empty()) {
+            let (module, level) = match directive.split_once('=') {
+                Some((module, level)) => (Some(module.trim()), Some(level.trim())),
+                None => match parse_level(directive) {
+                    Some(level) => {
+                        directives.push((None, level));
+                        continue;
+                    }
+                    None => (Some(directive), None),
+                },
+            };
+            let level = match level {
+                Some(level) => parse_level(level).ok_or_else(|| {
+                    Error::new(&format!("unknown level `{}` in filter directive `{}`", level, directive),
+                               file!(), line!())
+                })?,
+                None => Some(Severity::Info),
+            };
+            directives.push((module.map(str::to_string), level));
+        }
+        Ok(Filter { directives })
+    }
+
+    /// Returns `true` if `self` lets `e` through.
+    pub fn enabled(&self, e: &Error) -> bool {
+        let module = e.module_path();
+        let mut best: Option<(usize, Option<Severity>)> = None;
+        for (name, level) in &self.directives {
+            let len = match *name {
+                Some(ref name) if is_within(&module, name) => name.len() + 1,
+                Some(_) => continue,
+                None => 0,
+            };
+            if best.is_none_or(|(best, _)| len >= best) {
+                best = Some((len, *level));
+            }
+        }
+        match best {
+            Some((_, Some(level))) => e.severity() >= level,
+            Some((_, None)) => false,
+            None => true,
+        }
+    }
+}
+
+impl FromStr for Filter {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Filter> {
+        Filter::parse(s)
+    }
+}
+
+impl Error {
+    /// Returns the path of the module `self` comes from, relative to its crate.
+    ///
+    /// This is the namespace of the code of `self` if it is hierarchical, as
+    /// created by `error_code!`, or else the path derived from the file of
+    /// `self`, e.g. `net::http` for `src/net/http.rs` or `storage` for
+    /// `src/storage/mod.rs`. It is empty for the root module of a crate.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mm_errors::Error;
+    ///
+    /// assert_eq!(Error::new("timeout", "src/net/http.rs", 1).module_path(), "net::http");
+    /// assert_eq!(Error::new("disk full", "src/storage/mod.rs", 1).module_path(), "storage");
+    /// assert_eq!(Error::new("bad flag", "src/main.rs", 1).module_path(), "");
+    /// assert_eq!(
+    ///     Error::new("disk full", "src/lib.rs", 1).with_code("myapp::storage::E012").module_path(),
+    ///     "myapp::storage");
+    /// ```
+    ///
+    pub fn module_path(&self) -> String {
+        if let Some(namespace) = self.code.as_ref().and_then(|code| catalog::namespace(code)) {
+            return namespace.to_string();
+        }
+        let file = self.file.replace('\\', "/");
+        let file = match file.rfind("src/") {
+            Some(i) => &file[i + 4..],
+            None => &file,
+        };
+        let file = file.strip_suffix(".rs").unwrap_or(file);
+        let mut parts: Vec<_> = file.split('/').filter(|part| !part.is_empty()).collect();
+        if let Some(&last) = parts.last() {
+            if last == "mod" || (parts.len() == 1 && (last == "lib" || last == "main")) {
+                parts.pop();
+            }
+        }
+        parts.join("::")
+    }
+}
+
+/// Sets the filter of the reporters and the log integrations.
+///
+/// This replaces the filter of `MM_ERRORS_FILTER`. The setting is global and
+/// applies to all threads.
+///
+/// # Arguments
+///
+/// * filter - Filter, or `None` to use the filter of `MM_ERRORS_FILTER` again.
+///
+pub fn set_filter(filter: Option<Filter>) {
+    *FILTER.write().unwrap_or_else(|e| e.into_inner()) = filter.map(Arc::new);
+}
+
+/// Returns `true` if the filter of the reporters and the log integrations lets `e` through.
+///
+/// This is the filter set by `set_filter`, or else the filter of
+/// `MM_ERRORS_FILTER`. An invalid `MM_ERRORS_FILTER` is reported to the
+/// warning hook once, and ignored.
+///
+pub fn enabled(e: &Error) -> bool {
+    let filter = FILTER.read().unwrap_or_else(|e| e.into_inner()).clone();
+    match filter {
+        Some(filter) => filter.enabled(e),
+        None => ENV_FILTER.get_or_init(env_filter).as_ref().is_none_or(|filter| filter.enabled(e)),
+    }
+}
+
+/// Returns the filter of `MM_ERRORS_FILTER`, if it is set and valid.
+fn env_filter() -> Option<Filter> {
+    let s = env::var(FILTER_VAR).ok()?;
+    match Filter::parse(&s) {
+        Ok(filter) => Some(filter),
+        Err(e) => {
+            hooks::warn(&format!("ignoring `{}`: {}", FILTER_VAR, e.report()));
+            None
+        }
+    }
+}
+
+/// Returns the lowest severity emitted at `level`, or `None` if `level` is not a level.
+fn parse_level(level: &str) -> Option<Option<Severity>> {
+    match &*level.to_ascii_lowercase() {
+        "off" => Some(None),
+        "info" => Some(Some(Severity::Info)),
+        "warning" | "warn" => Some(Some(Severity::Warning)),
+        "error" => Some(Some(Severity::Error)),
+        "critical" | "fatal" => Some(Some(Severity::Critical)),
+        _ => None,
+    }
+}
+
+/// Returns `true` if `module` is `parent` or one of its submodules.
+fn is_within(module: &str, parent: &str) -> bool {
+    module.strip_prefix(parent).is_some_and(|rest| rest.is_empty() || rest.starts_with("::"))
+}
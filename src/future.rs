@@ -0,0 +1,343 @@
+//! Extensions for futures returning `Result`.
+//!
+//! This module is available with the `async` feature.
+
+use std::error;
+use std::fmt;
+use std::future::Future;
+use std::marker;
+use std::panic;
+use std::pin::{self, Pin};
+use std::result;
+use std::task;
+use std::time::Instant;
+
+use super::{timing, Error, MultiError, Result};
+
+/// Adds context to errors of futures returning `Result`.
+///
+/// The location of the returned error is where the method is called,
+/// which is usually the line awaiting the future.
+///
+/// # Examples
+///
+/// ```
+/// use mm_errors::{Error, ErrorKind};
+/// use mm_errors::future::ResultFutureExt;
+///
+/// async fn read_port() -> Result<u32, std::num::ParseIntError> {
+///     "XXX".parse::<u32>()
+/// }
+///
+/// async fn connect() -> mm_errors::Result<u32> {
+///     let port = read_port().wrap_err_async("reading the port number").await?;
+///     Ok(port)
+/// }
+///
+/// # fn main() {
+/// let e = futures::executor::block_on(connect()).unwrap_err();
+/// match e.kind {
+///     ErrorKind::Context(ref message, _) => assert_eq!(message, "reading the port number"),
+///     _ => panic!("the error should have context"),
+/// }
+/// # }
+/// ```
+///
+pub trait ResultFutureExt<T, E>: Future<Output = result::Result<T, E>> + Sized
+    where E: Into<Box<dyn error::Error + marker::Send + marker::Sync>> {
+    /// Wraps the error of `self` with `message`.
+    ///
+    /// # Arguments
+    ///
+    /// * message - Context message.
+    ///
+    #[track_caller]
+    fn wrap_err_async(self, message: &str) -> WrapErr<Self> {
+        WrapErr {
+            future: self,
+            message: Some(message.to_string()),
+            location: panic::Location::caller(),
+        }
+    }
+
+    /// Wraps the error of `self` with the message returned by `f`.
+    ///
+    /// `f` is called only when the future fails.
+    ///
+    /// # Arguments
+    ///
+    /// * f - Function returning the context message.
+    ///
+    #[track_caller]
+    fn context_async<F, M>(self, f: F) -> WithContext<Self, F>
+        where F: FnOnce() -> M, M: AsRef<str> {
+        WithContext {
+            future: self,
+            f: Some(f),
+            location: panic::Location::caller(),
+        }
+    }
+}
+
+impl<T, E, F> ResultFutureExt<T, E> for F
+    where F: Future<Output = result::Result<T, E>>,
+          E: Into<Box<dyn error::Error + marker::Send + marker::Sync>> {}
+
+/// Future returned by `ResultFutureExt::wrap_err_async`.
+#[derive(Debug)]
+#[must_use = "futures do nothing unless polled"]
+pub struct WrapErr<Fut> {
+    future: Fut,
+    message: Option<String>,
+    location: &'static panic::Location<'static>,
+}
+
+impl<Fut, T, E> Future for WrapErr<Fut>
+    where Fut: Future<Output = result::Result<T, E>>,
+          E: Into<Box<dyn error::Error + marker::Send + marker::Sync>> {
+    type Output = Result<T>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut task::Context) -> task::Poll<Result<T>> {
+        // SAFETY: `future` is never moved out of `self`, and no other field is pinned.
+        let this = unsafe { self.get_unchecked_mut() };
+        let future = unsafe { Pin::new_unchecked(&mut this.future) };
+        match future.poll(cx) {
+            task::Poll::Pending => task::Poll::Pending,
+            task::Poll::Ready(Ok(x)) => task::Poll::Ready(Ok(x)),
+            task::Poll::Ready(Err(e)) => {
+                let message = this.message.take().expect("`WrapErr` polled after completion");
+                task::Poll::Ready(Err(context_at(e, &message, this.location)))
+            }
+        }
+    }
+}
+
+/// Future returned by `ResultFutureExt::context_async`.
+#[derive(Debug)]
+#[must_use = "futures do nothing unless polled"]
+pub struct WithContext<Fut, F> {
+    future: Fut,
+    f: Option<F>,
+    location: &'static panic::Location<'static>,
+}
+
+impl<Fut, F, M, T, E> Future for WithContext<Fut, F>
+    where Fut: Future<Output = result::Result<T, E>>,
+          E: Into<Box<dyn error::Error + marker::Send + marker::Sync>>,
+          F: FnOnce() -> M,
+          M: AsRef<str> {
+    type Output = Result<T>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut task::Context) -> task::Poll<Result<T>> {
+        // SAFETY: `future` is never moved out of `self`, and no other field is pinned.
+        let this = unsafe { self.get_unchecked_mut() };
+        let future = unsafe { Pin::new_unchecked(&mut this.future) };
+        match future.poll(cx) {
+            task::Poll::Pending => task::Poll::Pending,
+            task::Poll::Ready(Ok(x)) => task::Poll::Ready(Ok(x)),
+            task::Poll::Ready(Err(e)) => {
+                let f = this.f.take().expect("`WithContext` polled after completion");
+                task::Poll::Ready(Err(context_at(e, f().as_ref(), this.location)))
+            }
+        }
+    }
+}
+
+fn context_at<E>(e: E, message: &str, location: &'static panic::Location<'static>) -> Error
+    where E: Into<Box<dyn error::Error + marker::Send + marker::Sync>> {
+    Error::context(e, message, location.file(), location.line())
+}
+
+/// Runs all `futures` to completion, collecting every failure.
+///
+/// Unlike `try_join_all` of the `futures` crate, this does not stop at the
+/// first failure. If any future fails, the returned `MultiError` holds an
+/// error for each failed future, with its index, in the order of `futures`.
+/// The location of these errors is where this function is called.
+///
+/// # Arguments
+///
+/// * futures - Futures to run.
+///
+/// # Examples
+///
+/// ```
+/// use mm_errors::future;
+///
+/// async fn parse(s: &str) -> Result<u32, std::num::ParseIntError> {
+///     s.parse::<u32>()
+/// }
+///
+/// # fn main() {
+/// let all = future::try_join_all_errors(vec![parse("1"), parse("X"), parse("Y")]);
+/// let errors = futures::executor::block_on(all).unwrap_err();
+///
+/// assert_eq!(errors.len(), 2);
+/// assert!(errors.to_string().contains("<context>future 1 failed</context>"));
+/// assert!(errors.to_string().contains("<context>future 2 failed</context>"));
+///
+/// let all = future::try_join_all_errors(vec![parse("1"), parse("2")]);
+/// assert_eq!(futures::executor::block_on(all).unwrap(), vec![1, 2]);
+/// # }
+/// ```
+///
+#[track_caller]
+pub fn try_join_all_errors<I, T, E>(futures: I) -> TryJoinAllErrors<I::Item>
+    where I: IntoIterator,
+          I::Item: Future<Output = result::Result<T, E>>,
+          E: Into<Box<dyn error::Error + marker::Send + marker::Sync>> {
+    let futures: Vec<_> = futures.into_iter().map(|f| Some(Box::pin(f))).collect();
+    let outputs = futures.iter().map(|_| None).collect();
+    TryJoinAllErrors {
+        futures,
+        outputs,
+        location: panic::Location::caller(),
+    }
+}
+
+/// Future returned by `try_join_all_errors`.
+#[must_use = "futures do nothing unless polled"]
+pub struct TryJoinAllErrors<Fut: Future> {
+    futures: Vec<Option<Pin<Box<Fut>>>>,
+    outputs: Vec<Option<Fut::Output>>,
+    location: &'static panic::Location<'static>,
+}
+
+// The futures are pinned in their own boxes, and the outputs are never pinned.
+impl<Fut: Future> Unpin for TryJoinAllErrors<Fut> {}
+
+impl<Fut, T, E> Future for TryJoinAllErrors<Fut>
+    where Fut: Future<Output = result::Result<T, E>>,
+          E: Into<Box<dyn error::Error + marker::Send + marker::Sync>> {
+    type Output = result::Result<Vec<T>, MultiError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut task::Context) -> task::Poll<Self::Output> {
+        let this = self.get_mut();
+        let mut pending = false;
+        for (future, output) in this.futures.iter_mut().zip(this.outputs.iter_mut()) {
+            if let Some(f) = future {
+                match f.as_mut().poll(cx) {
+                    task::Poll::Pending => pending = true,
+                    task::Poll::Ready(r) => {
+                        *output = Some(r);
+                        *future = None;
+                    }
+                }
+            }
+        }
+        if pending {
+            return task::Poll::Pending;
+        }
+
+        let mut values = Vec::with_capacity(this.outputs.len());
+        let mut errors = MultiError::new();
+        for (i, output) in this.outputs.drain(..).enumerate() {
+            match output.expect("`TryJoinAllErrors` polled after completion") {
+                Ok(x) => values.push(x),
+                Err(e) => {
+                    let message = format!("future {} failed", i);
+                    errors.push(context_at(e, &message, this.location));
+                }
+            }
+        }
+        if errors.is_empty() {
+            task::Poll::Ready(Ok(values))
+        } else {
+            task::Poll::Ready(Err(errors))
+        }
+    }
+}
+
+impl<Fut: Future> fmt::Debug for TryJoinAllErrors<Fut> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("TryJoinAllErrors")
+            .field("len", &self.futures.len())
+            .field("location", &self.location)
+            .finish()
+    }
+}
+
+/// Runs `future` until it completes or `cancelled` completes.
+///
+/// `cancelled` is typically the future returned by the `cancelled` method of a
+/// cancellation token, e.g. `tokio_util::sync::CancellationToken`. If it
+/// completes first, `future` is dropped and an `ErrorKind::Cancelled` error
+/// located where this function is called is returned.
+///
+/// # Arguments
+///
+/// * cancelled - Future completing when the operation should be cancelled.
+/// * future - Operation to run.
+///
+/// # Examples
+///
+/// ```
+/// use mm_errors::future;
+///
+/// # fn main() {
+/// let operation = async {
+///     std::future::pending::<()>().await;
+///     "1".parse::<u32>()
+/// };
+/// let shutdown = std::future::ready(());
+///
+/// let e = futures::executor::block_on(future::until_cancelled(shutdown, operation)).unwrap_err();
+/// assert!(e.is_cancelled());
+/// # }
+/// ```
+///
+#[track_caller]
+pub fn until_cancelled<C, F, T, E>(cancelled: C, future: F) -> impl Future<Output = Result<T>>
+    where C: Future<Output = ()>,
+          F: Future<Output = result::Result<T, E>>,
+          E: Into<Box<dyn error::Error + marker::Send + marker::Sync>> {
+    let location = panic::Location::caller();
+    async move {
+        let mut cancelled = pin::pin!(cancelled);
+        let mut future = pin::pin!(future);
+        std::future::poll_fn(|cx| {
+            if let task::Poll::Ready(r) = future.as_mut().poll(cx) {
+                let (file, line) = (location.file(), location.line());
+                return task::Poll::Ready(r.map_err(|e| Error::wrap(e, file, line)));
+            }
+            if cancelled.as_mut().poll(cx).is_ready() {
+                let (file, line) = (location.file(), location.line());
+                return task::Poll::Ready(Err(Error::cancelled("operation was cancelled", file, line)));
+            }
+            task::Poll::Pending
+        }).await
+    }
+}
+
+/// Runs `future`, attaching how long it ran to the error if it fails.
+///
+/// This is the asynchronous version of `timing::timed`. The time is measured
+/// from the first poll, and includes the time the future was pending.
+///
+/// # Arguments
+///
+/// * future - Operation to run.
+///
+/// # Examples
+///
+/// ```
+/// use std::time::Duration;
+///
+/// use mm_errors::{future, metadata};
+///
+/// # fn main() {
+/// let e = futures::executor::block_on(future::timed_async(async { "XXX".parse::<u32>() })).unwrap_err();
+/// assert!(e.metadata.get_keyed::<Duration>(metadata::ELAPSED).is_some());
+/// # }
+/// ```
+///
+#[track_caller]
+pub fn timed_async<F, T, E>(future: F) -> impl Future<Output = Result<T>>
+    where F: Future<Output = result::Result<T, E>>,
+          E: Into<Box<dyn error::Error + marker::Send + marker::Sync>> {
+    let location = panic::Location::caller();
+    async move {
+        let start = Instant::now();
+        future.await.map_err(|e| timing::with_elapsed(e, start.elapsed(), location))
+    }
+}
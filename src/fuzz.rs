@@ -0,0 +1,74 @@
+//! `Arbitrary` implementations for fuzzing.
+//!
+//! This module is available with the `arbitrary` feature.
+//!
+//! # Examples
+//!
+//! ```
+//! use arbitrary::{Arbitrary, Unstructured};
+//!
+//! use mm_errors::{Error, MultiError};
+//!
+//! let data = [7u8; 256];
+//! let mut u = Unstructured::new(&data);
+//!
+//! let e = Error::arbitrary(&mut u).unwrap();
+//! let _ = Error::from_xml(&e.to_string());
+//!
+//! let errors = MultiError::arbitrary(&mut u).unwrap();
+//! let _ = errors.to_string();
+//! ```
+
+use std::borrow::Cow;
+
+use arbitrary::{Arbitrary, Result, Unstructured};
+
+use super::{Error, ErrorKind, Metadata, MultiError};
+
+/// Maximum number of nested levels of a generated chain.
+const MAX_DEPTH: usize = 8;
+
+/// Maximum number of errors of a generated `MultiError`.
+const MAX_ERRORS: usize = 8;
+
+impl<'a> Arbitrary<'a> for Error {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Error> {
+        error(u, MAX_DEPTH)
+    }
+}
+
+impl<'a> Arbitrary<'a> for MultiError {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<MultiError> {
+        errors(u, MAX_DEPTH)
+    }
+}
+
+fn error(u: &mut Unstructured, depth: usize) -> Result<Error> {
+    let file: String = u.arbitrary()?;
+    let line = u.arbitrary()?;
+    let choice = if depth == 0 { u.int_in_range(0..=1)? } else { u.int_in_range(0..=4)? };
+    let kind = match choice {
+        0 => ErrorKind::String(u.arbitrary()?),
+        1 => ErrorKind::Cancelled(u.arbitrary()?),
+        2 => ErrorKind::Wrapped(Box::new(error(u, depth - 1)?)),
+        3 => ErrorKind::Context(u.arbitrary()?, Box::new(error(u, depth - 1)?)),
+        _ => ErrorKind::Wrapped(Box::new(errors(u, depth - 1)?)),
+    };
+    let code: Option<String> = u.arbitrary()?;
+    Ok(Error {
+        file: Cow::Owned(file),
+        line,
+        kind,
+        code: code.map(Cow::Owned),
+        metadata: Metadata::new(),
+    })
+}
+
+fn errors(u: &mut Unstructured, depth: usize) -> Result<MultiError> {
+    let len = u.int_in_range(0..=MAX_ERRORS)?;
+    let mut errors = MultiError::new();
+    for _ in 0..len {
+        errors.push(error(u, depth)?);
+    }
+    Ok(errors)
+}
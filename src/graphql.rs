@@ -0,0 +1,67 @@
+//! Errors of GraphQL APIs built with async-graphql.
+
+use async_graphql::ErrorExtensions;
+
+use super::{report, Error};
+
+/// Converts `Error` into an `async_graphql::Error` with structured extensions.
+///
+/// The message is the first message in the chain, as in `Error::report`, and
+/// the extensions are:
+///
+/// | Extension | Value |
+/// |-----------|-------|
+/// | `code` | Error code of the outermost error having one, if any |
+/// | `traceId` | `Error::trace_id`, if any |
+/// | `requestId` | `Error::request_id`, if any |
+/// | `chain` | Whole chain, as written by `Error::to_json`, only in debug builds |
+///
+/// The source of the converted error is a clone of `self`. Note that `?`
+/// converts errors with `Display` instead, so call `extend` on errors, or the
+/// `extend` method of `async_graphql::ResultExt` on results.
+///
+/// This is available with the `graphql` feature.
+///
+/// # Examples
+///
+/// ```
+/// use async_graphql::{ErrorExtensions, ResultExt, Value};
+///
+/// use mm_errors::Error;
+///
+/// fn order(id: u32) -> async_graphql::Result<String> {
+///     Err(Error::here(&format!("no order {}", id)).with_code("GQL404").with_trace_id("t-1")).extend()
+/// }
+///
+/// let e = order(7).unwrap_err();
+/// assert_eq!(e.message, "no order 7");
+/// let extensions = e.extensions.unwrap();
+/// assert_eq!(extensions.get("code"), Some(&Value::from("GQL404")));
+/// assert_eq!(extensions.get("traceId"), Some(&Value::from("t-1")));
+/// assert_eq!(extensions.get("chain").is_some(), cfg!(debug_assertions));
+/// ```
+///
+impl ErrorExtensions for Error {
+    fn extend(&self) -> async_graphql::Error {
+        self.mark_reported();
+        let mut error = async_graphql::Error::new_with_source(self.clone());
+        error.message = report::first_message(self);
+        error.extend_with(|_, extensions| {
+            let code = self.chain()
+                .filter_map(|e| e.downcast_ref::<Error>())
+                .find_map(|e| e.code.as_ref());
+            if let Some(code) = code {
+                extensions.set("code", &**code);
+            }
+            if let Some(id) = self.trace_id() {
+                extensions.set("traceId", id);
+            }
+            if let Some(id) = self.request_id() {
+                extensions.set("requestId", id);
+            }
+            if cfg!(debug_assertions) {
+                extensions.set("chain", self.to_json());
+            }
+        })
+    }
+}
@@ -0,0 +1,193 @@
+//! Summaries of recent errors for health checks.
+//!
+//! `Health` keeps the errors reported within a window of time in a ring
+//! buffer, and summarizes them by code and severity, e.g. to back a
+//! `/healthz` endpoint. Feed it from the report hook, so every error reported
+//! by the integrations is counted.
+//!
+//! # Examples
+//!
+//! ```
+//! use std::sync::Arc;
+//! use std::time::Duration;
+//!
+//! use mm_errors::{hooks, Error, Severity};
+//! use mm_errors::health::Health;
+//!
+//! let health = Arc::new(Health::new(Duration::from_secs(60), 1000));
+//! let recorder = health.clone();
+//! hooks::set_report_hook(move |e: &Error| recorder.record(e));
+//!
+//! hooks::report(&Error::here("query failed").with_code("DB001"));
+//! hooks::report(&Error::here("query failed").with_code("DB001"));
+//! hooks::report(&Error::here("cache miss").with_severity(Severity::Warning));
+//!
+//! let summary = health.summary();
+//! assert_eq!(summary.total, 3);
+//! assert!(summary.is_healthy());
+//! assert_eq!(
+//!     summary.to_json(),
+//!     "{\"status\":\"degraded\",\"window\":60,\"total\":3,\
+//!      \"by_code\":{\"DB001\":2},\"by_severity\":{\"warning\":1,\"error\":2}}");
+//! # hooks::reset_report_hook();
+//! ```
+
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use super::catalog::Severity;
+use super::{json, Error};
+
+/// Recent errors, summarized by `summary`.
+///
+/// The errors can be recorded from several threads, e.g. through an `Arc`.
+///
+#[derive(Debug)]
+pub struct Health {
+    window: Duration,
+    capacity: usize,
+    recent: Mutex<VecDeque<Occurrence>>,
+}
+
+/// Error recorded by `Health::record`.
+#[derive(Debug)]
+struct Occurrence {
+    time: Instant,
+    code: Option<String>,
+    severity: Severity,
+}
+
+impl Health {
+    /// Returns a new instance of `Health`.
+    ///
+    /// # Arguments
+    ///
+    /// * window - Length of the sliding window of the summaries.
+    /// * capacity - Maximum number of errors kept, beyond which the oldest are dropped.
+    ///
+    pub fn new(window: Duration, capacity: usize) -> Health {
+        Health {
+            window,
+            capacity,
+            recent: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// Records an occurrence of `e`.
+    ///
+    /// The code is the code of the outermost error having one.
+    ///
+    pub fn record(&self, e: &Error) {
+        let code = e.chain()
+            .filter_map(|e| e.downcast_ref::<Error>())
+            .find_map(|e| e.code.as_ref().map(|code| code.to_string()));
+        let now = Instant::now();
+        let mut recent = self.recent.lock().unwrap_or_else(|e| e.into_inner());
+        self.expire(&mut recent, now);
+        if self.capacity == 0 {
+            return;
+        }
+        if recent.len() == self.capacity {
+            recent.pop_front();
+        }
+        recent.push_back(Occurrence { time: now, code, severity: e.severity() });
+    }
+
+    /// Returns the summary of the errors recorded within the window.
+    pub fn summary(&self) -> Summary {
+        let mut recent = self.recent.lock().unwrap_or_else(|e| e.into_inner());
+        self.expire(&mut recent, Instant::now());
+        let mut summary = Summary {
+            window: self.window,
+            total: recent.len(),
+            by_code: BTreeMap::new(),
+            by_severity: BTreeMap::new(),
+        };
+        for occurrence in recent.iter() {
+            if let Some(ref code) = occurrence.code {
+                *summary.by_code.entry(code.clone()).or_insert(0) += 1;
+            }
+            *summary.by_severity.entry(occurrence.severity).or_insert(0) += 1;
+        }
+        summary
+    }
+
+    /// Forgets every error.
+    pub fn reset(&self) {
+        self.recent.lock().unwrap_or_else(|e| e.into_inner()).clear();
+    }
+
+    fn expire(&self, recent: &mut VecDeque<Occurrence>, now: Instant) {
+        while recent.front().is_some_and(|o| now.duration_since(o.time) > self.window) {
+            recent.pop_front();
+        }
+    }
+}
+
+/// Summary of recent errors, returned by `Health::summary`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Summary {
+    /// Length of the window.
+    pub window: Duration,
+
+    /// Number of errors within the window, including errors without a code.
+    pub total: usize,
+
+    /// Number of errors per code.
+    pub by_code: BTreeMap<String, usize>,
+
+    /// Number of errors per severity.
+    pub by_severity: BTreeMap<Severity, usize>,
+}
+
+impl Summary {
+    /// Returns `true` if no critical error occurred within the window.
+    pub fn is_healthy(&self) -> bool {
+        !self.by_severity.contains_key(&Severity::Critical)
+    }
+
+    /// Returns the status of the summary.
+    ///
+    /// This is `failing` if a critical error occurred within the window,
+    /// `degraded` if any other error occurred, or else `ok`.
+    ///
+    pub fn status(&self) -> &'static str {
+        if !self.is_healthy() {
+            "failing"
+        } else if self.total > 0 {
+            "degraded"
+        } else {
+            "ok"
+        }
+    }
+
+    /// Returns `self` as JSON.
+    ///
+    /// The output is an object with the members `status`, `window` in
+    /// seconds, `total`, and the objects `by_code` and `by_severity`.
+    ///
+    pub fn to_json(&self) -> String {
+        let mut out = String::new();
+        write!(out, "{{\"status\":\"{}\",\"window\":{},\"total\":{},\"by_code\":{{",
+               self.status(), self.window.as_secs(), self.total)
+            .expect("writing to a `String` never fails");
+        for (i, (code, count)) in self.by_code.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            json::write_string(&mut out, code).expect("writing to a `String` never fails");
+            write!(out, ":{}", count).expect("writing to a `String` never fails");
+        }
+        out.push_str("},\"by_severity\":{");
+        for (i, (severity, count)) in self.by_severity.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            write!(out, "\"{}\":{}", severity, count).expect("writing to a `String` never fails");
+        }
+        out.push_str("}}");
+        out
+    }
+}
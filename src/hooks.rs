@@ -0,0 +1,272 @@
+//! Hooks called by the crate.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+
+use super::{filter, rate, Error};
+
+type WarningHook = Arc<dyn Fn(&str) + Send + Sync>;
+
+type ReportHook = Arc<dyn Fn(&Error, u64) + Send + Sync>;
+
+type ExtensionHook = Arc<dyn Fn(&str) -> bool + Send + Sync>;
+
+static WARNING_HOOK: RwLock<Option<WarningHook>> = RwLock::new(None);
+
+static REPORT_HOOK: RwLock<Option<ReportHook>> = RwLock::new(None);
+
+static EXTENSION_HOOK: RwLock<Option<ExtensionHook>> = RwLock::new(None);
+
+/// Sets the function receiving warnings of the crate, such as uses of deprecated error codes.
+///
+/// By default, warnings are written to the standard error. The setting is
+/// global and applies to all threads.
+///
+/// # Arguments
+///
+/// * f - Function receiving the warning messages.
+///
+/// # Examples
+///
+/// ```
+/// use std::sync::{Arc, Mutex};
+///
+/// use mm_errors::hooks;
+///
+/// let warnings = Arc::new(Mutex::new(Vec::new()));
+/// let sink = warnings.clone();
+/// hooks::set_warning_hook(move |message: &str| sink.lock().unwrap().push(message.to_string()));
+/// # hooks::reset_warning_hook();
+/// ```
+///
+pub fn set_warning_hook<F>(f: F)
+    where F: Fn(&str) + Send + Sync + 'static {
+    *WARNING_HOOK.write().unwrap_or_else(|e| e.into_inner()) = Some(Arc::new(f));
+}
+
+/// Restores writing warnings to the standard error.
+pub fn reset_warning_hook() {
+    *WARNING_HOOK.write().unwrap_or_else(|e| e.into_inner()) = None;
+}
+
+/// Passes `message` to the warning hook.
+pub(crate) fn warn(message: &str) {
+    let hook = WARNING_HOOK.read().unwrap_or_else(|e| e.into_inner()).clone();
+    match hook {
+        Some(f) => f(message),
+        None => eprintln!("warning: {}", message),
+    }
+}
+
+/// Sets the function receiving errors reported by the integrations, such as `middleware::ErrorLayer`.
+///
+/// By default, errors are logged with `Error::log_err` if the `log` feature
+/// is enabled, or else written to the standard error as by `Error::report`.
+/// Errors dropped by the sampler of `rate::set_sampler` are not passed to
+/// `f`. The setting is global and applies to all threads.
+///
+/// # Arguments
+///
+/// * f - Function receiving the errors.
+///
+/// # Examples
+///
+/// ```
+/// use std::sync::{Arc, Mutex};
+///
+/// use mm_errors::{hooks, Error};
+///
+/// let codes = Arc::new(Mutex::new(Vec::new()));
+/// let sink = codes.clone();
+/// hooks::set_report_hook(move |e: &Error| sink.lock().unwrap().push(e.code.clone()));
+///
+/// hooks::report(&Error::here("disk full").with_code("STO001"));
+/// assert_eq!(codes.lock().unwrap().as_slice(), [Some("STO001".into())]);
+/// # hooks::reset_report_hook();
+/// ```
+///
+pub fn set_report_hook<F>(f: F)
+    where F: Fn(&Error) + Send + Sync + 'static {
+    *REPORT_HOOK.write().unwrap_or_else(|e| e.into_inner()) = Some(Arc::new(move |e: &Error, _| f(e)));
+}
+
+/// Sets the function receiving errors reported by the integrations, and their sample rate.
+///
+/// This is `set_report_hook` for functions which forward how many errors
+/// like the sampled one it stands for, e.g. as a field of a metrics event.
+/// The rate is 1 if no sampler is set.
+///
+/// # Arguments
+///
+/// * f - Function receiving the errors and their sample rates.
+///
+/// # Examples
+///
+/// ```
+/// use std::sync::{Arc, Mutex};
+///
+/// use mm_errors::{hooks, rate, Error};
+///
+/// let rates = Arc::new(Mutex::new(Vec::new()));
+/// let sink = rates.clone();
+/// hooks::set_sampled_report_hook(move |_: &Error, rate| sink.lock().unwrap().push(rate));
+/// rate::set_sampler(Some(rate::Sampler::one_in(2)));
+///
+/// for _ in 0..4 {
+///     hooks::report(&Error::here("connection refused"));
+/// }
+/// assert_eq!(rates.lock().unwrap().as_slice(), [2, 2]);
+/// # rate::set_sampler(None);
+/// # hooks::reset_report_hook();
+/// ```
+///
+pub fn set_sampled_report_hook<F>(f: F)
+    where F: Fn(&Error, u64) + Send + Sync + 'static {
+    *REPORT_HOOK.write().unwrap_or_else(|e| e.into_inner()) = Some(Arc::new(f));
+}
+
+/// Restores the default reporting of errors.
+pub fn reset_report_hook() {
+    *REPORT_HOOK.write().unwrap_or_else(|e| e.into_inner()) = None;
+}
+
+/// Passes `e` to the report hook, unless it is filtered out by `filter::enabled` or the sampler.
+pub fn report(e: &Error) {
+    e.mark_reported();
+    if !filter::enabled(e) {
+        return;
+    }
+    let hook = REPORT_HOOK.read().unwrap_or_else(|e| e.into_inner()).clone();
+    match hook {
+        Some(f) => {
+            if let Some(rate) = rate::sample(e) {
+                f(e, rate);
+            }
+        }
+        // Sampled by `log_err` itself.
+        #[cfg(feature = "log")]
+        None => e.log_err(),
+        #[cfg(not(feature = "log"))]
+        None => {
+            if rate::sample(e).is_some() {
+                eprintln!("{}", e.report());
+            }
+        }
+    }
+}
+
+/// Sets the function choosing the keys of `Error::all_context` written as extension members of Problem Details.
+///
+/// `Error::to_problem_details` writes the body returned to clients, so by
+/// default it writes no extension members, since values such as
+/// `Error::with_env`, `Error::with_args`, `Error::with_io` and
+/// `Error::with_user` must not leak. The setting is global and applies to
+/// all threads.
+///
+/// # Arguments
+///
+/// * f - Function returning `true` for the keys to write.
+///
+/// # Examples
+///
+/// ```
+/// use mm_errors::{hooks, Error};
+///
+/// hooks::set_problem_extensions_hook(|key: &str| key == "order");
+///
+/// let e = Error::here("no order 7").with_meta_keyed("order", 7).with_user("alice");
+/// assert!(e.to_problem_details().ends_with(",\"order\":\"7\"}"));
+/// # hooks::reset_problem_extensions_hook();
+/// ```
+///
+pub fn set_problem_extensions_hook<F>(f: F)
+    where F: Fn(&str) -> bool + Send + Sync + 'static {
+    *EXTENSION_HOOK.write().unwrap_or_else(|e| e.into_inner()) = Some(Arc::new(f));
+}
+
+/// Restores writing no extension members of Problem Details.
+pub fn reset_problem_extensions_hook() {
+    *EXTENSION_HOOK.write().unwrap_or_else(|e| e.into_inner()) = None;
+}
+
+/// Returns the function set by `set_problem_extensions_hook`.
+pub(crate) fn problem_extensions() -> Option<ExtensionHook> {
+    EXTENSION_HOOK.read().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+static MUST_REPORT: AtomicBool = AtomicBool::new(false);
+
+/// Sets whether errors dropped without being reported are warned about.
+///
+/// When enabled, every error created afterwards tracks whether it was ever
+/// reported, i.e. rendered with `Display`, `Debug` or `Error::report`,
+/// serialized, or logged, either itself or as part of an outer error. Errors
+/// dropped without being reported are passed to the warning hook, so errors
+/// silently swallowed by `let _ = ...` or `.ok()` show up. Call
+/// `Error::mark_handled` for errors handled on purpose, e.g. by matching
+/// their kind. Errors retried by the retry helpers, or replaced by
+/// `ResultExt::or_fallback`, are handled.
+///
+/// The tracking has a cost for every error, so enable it in debug builds
+/// and tests. The setting is global and applies to all threads.
+///
+/// # Examples
+///
+/// ```
+/// use std::sync::{Arc, Mutex};
+///
+/// use mm_errors::{hooks, Error};
+///
+/// let warnings = Arc::new(Mutex::new(Vec::new()));
+/// let sink = warnings.clone();
+/// hooks::set_warning_hook(move |message: &str| sink.lock().unwrap().push(message.to_string()));
+/// hooks::set_must_report(true);
+///
+/// let reported = Error::new("disk full", "storage.rs", 7);
+/// println!("{}", reported);
+/// drop(reported);
+/// assert!(warnings.lock().unwrap().is_empty());
+///
+/// let _ = Error::new("disk full", "storage.rs", 8);
+/// assert_eq!(
+///     warnings.lock().unwrap().as_slice(),
+///     ["error dropped without being reported: disk full (storage.rs:8)"]);
+/// # hooks::set_must_report(false);
+/// # hooks::reset_warning_hook();
+/// ```
+///
+pub fn set_must_report(enabled: bool) {
+    MUST_REPORT.store(enabled, Ordering::Relaxed);
+}
+
+/// Returns whether errors dropped without being reported are warned about.
+pub fn is_must_report() -> bool {
+    MUST_REPORT.load(Ordering::Relaxed)
+}
+
+/// Warns when dropped unless reported, shared by the clones of an error.
+#[derive(Debug)]
+pub(crate) struct ReportGuard {
+    reported: AtomicBool,
+
+    /// Message and location of the error, for the warning.
+    description: String,
+}
+
+impl ReportGuard {
+    pub(crate) fn new(description: String) -> ReportGuard {
+        ReportGuard { reported: AtomicBool::new(false), description }
+    }
+
+    pub(crate) fn report(&self) {
+        self.reported.store(true, Ordering::Relaxed);
+    }
+}
+
+impl Drop for ReportGuard {
+    fn drop(&mut self) {
+        if !*self.reported.get_mut() {
+            warn(&format!("error dropped without being reported: {}", self.description));
+        }
+    }
+}
@@ -0,0 +1,123 @@
+//! Context for failed IO operations.
+
+use std::borrow::Cow;
+use std::fmt;
+use std::io;
+use std::panic;
+use std::path::{Path, PathBuf};
+use std::result;
+
+use super::{metadata, Error, Result};
+
+/// Operation, path and bytes processed of a failed IO operation.
+///
+/// An `IoContext` attached to an error is rendered as an `<io>` element
+/// following the `<meta>` elements, holding `<operation>`, `<path>` and, if
+/// known, `<bytes>`.
+///
+/// # Examples
+///
+/// ```
+/// use mm_errors::io::{IoContext, IoResultExt};
+///
+/// let e = std::fs::read("/no/such/file").io_context("read", "/no/such/file").unwrap_err();
+///
+/// assert_eq!(e.io_context().map(|io| io.path.as_path()), Some("/no/such/file".as_ref()));
+/// assert!(e.to_string().contains(
+///     "<io><operation>read</operation><path>/no/such/file</path></io>"));
+///
+/// let e = mm_errors::Error::here("short write").with_io(IoContext::new("write", "out.bin").bytes(512));
+/// assert!(e.to_string().contains("<bytes>512</bytes>"));
+/// ```
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IoContext {
+    /// Operation, e.g. `"read"` or `"rename"`.
+    pub operation: Cow<'static, str>,
+
+    /// Path of the file or directory.
+    pub path: PathBuf,
+
+    /// Number of bytes processed before the failure, if known.
+    pub bytes: Option<u64>,
+}
+
+impl IoContext {
+    /// Returns a new instance of `IoContext`.
+    ///
+    /// # Arguments
+    ///
+    /// * operation - Operation, e.g. `"read"`.
+    /// * path - Path of the file or directory.
+    ///
+    pub fn new<O, P>(operation: O, path: P) -> IoContext
+        where O: Into<Cow<'static, str>>,
+              P: Into<PathBuf> {
+        IoContext {
+            operation: operation.into(),
+            path: path.into(),
+            bytes: None,
+        }
+    }
+
+    /// Returns `self` with the number of bytes processed set.
+    pub fn bytes(mut self, bytes: u64) -> IoContext {
+        self.bytes = Some(bytes);
+        self
+    }
+}
+
+impl fmt::Display for IoContext {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}", self.operation, self.path.display())?;
+        if let Some(bytes) = self.bytes {
+            write!(f, " after {} bytes", bytes)?;
+        }
+        Ok(())
+    }
+}
+
+/// Adds an `IoContext` to errors of IO operations.
+pub trait IoResultExt<T> {
+    /// Wraps the error with an `IoContext` of `operation` and `path`.
+    ///
+    /// The location of the returned error is where this method is called.
+    ///
+    /// # Arguments
+    ///
+    /// * operation - Operation, e.g. `"read"`.
+    /// * path - Path of the file or directory.
+    ///
+    #[track_caller]
+    fn io_context<P: AsRef<Path>>(self, operation: &'static str, path: P) -> Result<T>;
+}
+
+impl<T> IoResultExt<T> for result::Result<T, io::Error> {
+    #[track_caller]
+    fn io_context<P: AsRef<Path>>(self, operation: &'static str, path: P) -> Result<T> {
+        let location = panic::Location::caller();
+        self.map_err(|e| {
+            Error::wrap(e, location.file(), location.line())
+                .with_io(IoContext::new(operation, path.as_ref()))
+        })
+    }
+}
+
+impl Error {
+    /// Returns `self` with `io` attached under `metadata::IO`.
+    ///
+    /// # Arguments
+    ///
+    /// * io - Context of the failed IO operation.
+    ///
+    pub fn with_io(self, io: IoContext) -> Error {
+        self.with_meta_keyed(metadata::IO, io)
+    }
+
+    /// Returns the `IoContext` attached to the outermost error in the chain having one.
+    pub fn io_context(&self) -> Option<&IoContext> {
+        self.chain()
+            .filter_map(|e| e.downcast_ref::<Error>())
+            .find_map(|e| e.metadata.get_keyed::<IoContext>(metadata::IO))
+    }
+}
@@ -0,0 +1,193 @@
+//! Reporting errors to systemd-journald.
+//!
+//! Errors are sent over the native protocol of the journal, one entry per
+//! error, with the following fields:
+//!
+//! | Field | Value |
+//! |-------|-------|
+//! | `MESSAGE` | Output of `Error::report` |
+//! | `PRIORITY` | Syslog priority of `Error::severity` |
+//! | `CODE_FILE` | File of the outermost error, as rendered by `Display` |
+//! | `CODE_LINE` | Line of the outermost error |
+//! | `ERROR_CODE` | Error code, only if the error has a code |
+//! | `ERROR` | Output of `Display` |
+//! | `SYSLOG_IDENTIFIER` | Identifier set by `Journald::identifier`, if any |
+//! | `SAMPLE_RATE` | Sample rate of `rate::set_sampler`, only if above 1 |
+//!
+//! followed by a field for each value of `Error::metadata` of every level of
+//! the chain, outermost first, e.g. `REQUEST_ID` for `metadata::REQUEST_ID`.
+//! Strings are written without quotes, and secrets as `***`. Errors are
+//! filtered as set by `filter::set_filter`, and sampled as set by
+//! `rate::set_sampler`.
+//!
+//! This module is available with the `journald` feature on Unix.
+
+use std::io;
+use std::os::unix::net::UnixDatagram;
+use std::path::{Path, PathBuf};
+
+use super::catalog::Severity;
+use super::{filter, rate, render, xml, Error};
+
+/// Path of the socket of the journal.
+pub const SOCKET: &str = "/run/systemd/journal/socket";
+
+/// Writes errors to the journal.
+///
+/// # Examples
+///
+/// ```
+/// use std::os::unix::net::UnixDatagram;
+///
+/// use mm_errors::Error;
+/// use mm_errors::journald::Journald;
+///
+/// # let dir = std::env::temp_dir().join(format!("mm-errors-journald-{}", std::process::id()));
+/// # std::fs::create_dir_all(&dir).unwrap();
+/// # let path = dir.join("socket");
+/// # let _ = std::fs::remove_file(&path);
+/// // A stand-in for the journal.
+/// let journal = UnixDatagram::bind(&path).unwrap();
+///
+/// let journald = Journald::with_path(&path).unwrap().identifier("myapp");
+/// let e = Error::new("disk full", "storage.rs", 7)
+///     .with_code("STO001")
+///     .with_request_id("r-42");
+/// journald.report(&e).unwrap();
+///
+/// let mut buf = vec![0; 4096];
+/// let len = journal.recv(&mut buf).unwrap();
+/// let entry = String::from_utf8_lossy(&buf[..len]);
+/// assert!(entry.contains("\nPRIORITY=3\n"));
+/// assert!(entry.contains("\nCODE_FILE=storage.rs\nCODE_LINE=7\nERROR_CODE=STO001\n"));
+/// assert!(entry.contains("\nREQUEST_ID=r-42\n"));
+/// # std::fs::remove_dir_all(&dir).unwrap();
+/// ```
+///
+#[derive(Debug)]
+pub struct Journald {
+    socket: UnixDatagram,
+    path: PathBuf,
+    identifier: Option<String>,
+}
+
+impl Journald {
+    /// Returns a new instance of `Journald` writing to the journal of the system.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the socket can't be created.
+    ///
+    pub fn new() -> io::Result<Journald> {
+        Journald::with_path(SOCKET)
+    }
+
+    /// Returns a new instance of `Journald` writing to the socket at `path`.
+    ///
+    /// # Arguments
+    ///
+    /// * path - Path of the socket of the journal.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the socket can't be created.
+    ///
+    pub fn with_path<P: AsRef<Path>>(path: P) -> io::Result<Journald> {
+        Ok(Journald {
+            socket: UnixDatagram::unbound()?,
+            path: path.as_ref().to_path_buf(),
+            identifier: None,
+        })
+    }
+
+    /// Sets the `SYSLOG_IDENTIFIER` of the entries, usually the name of the program.
+    pub fn identifier<S: Into<String>>(mut self, identifier: S) -> Journald {
+        self.identifier = Some(identifier.into());
+        self
+    }
+
+    /// Writes `e` to the journal as a single entry, unless it is filtered or sampled out.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the journal is not running, or the entry is too
+    /// large for a datagram.
+    ///
+    pub fn report(&self, e: &Error) -> io::Result<()> {
+        if !filter::enabled(e) {
+            return Ok(());
+        }
+        match rate::sample(e) {
+            Some(rate) => self.socket.send_to(&self.entry(e, rate), &self.path).map(|_| ()),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns the entry of `e` in the native protocol of the journal.
+    fn entry(&self, e: &Error, rate: u64) -> Vec<u8> {
+        let (file, line) = render::location(&e.file, e.line);
+        let mut out = Vec::new();
+        write_field(&mut out, "MESSAGE", &e.report().to_string());
+        write_field(&mut out, "PRIORITY", priority(e.severity()));
+        write_field(&mut out, "CODE_FILE", &file);
+        write_field(&mut out, "CODE_LINE", &line.to_string());
+        if let Some(ref code) = e.code {
+            write_field(&mut out, "ERROR_CODE", code);
+        }
+        write_field(&mut out, "ERROR", &e.to_string());
+        if let Some(ref identifier) = self.identifier {
+            write_field(&mut out, "SYSLOG_IDENTIFIER", identifier);
+        }
+        if rate > 1 {
+            write_field(&mut out, "SAMPLE_RATE", &rate.to_string());
+        }
+        for level in e.chain().filter_map(|e| e.downcast_ref::<Error>()) {
+            for (key, value) in level.metadata.iter() {
+                if let Some(name) = field_name(key) {
+                    write_field(&mut out, &name, &xml::unquote(value).unwrap_or_else(|| value.to_string()));
+                }
+            }
+        }
+        out
+    }
+}
+
+/// Returns the syslog priority of `severity`.
+fn priority(severity: Severity) -> &'static str {
+    match severity {
+        Severity::Info => "6",
+        Severity::Warning => "4",
+        Severity::Error => "3",
+        Severity::Critical => "2",
+    }
+}
+
+/// Returns the name of the field for the metadata key `key`, or `None` if
+/// it has no valid name.
+///
+/// Field names consist of uppercase letters, digits and underscores, and
+/// don't start with an underscore, which is reserved for trusted fields.
+fn field_name(key: &str) -> Option<String> {
+    let name: String = key.chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_uppercase() } else { '_' })
+        .collect();
+    let name = name.trim_start_matches(|c: char| c == '_' || c.is_ascii_digit());
+    if name.is_empty() {
+        None
+    } else {
+        Some(name.chars().take(64).collect())
+    }
+}
+
+/// Writes a field, in the binary form if `value` has newlines.
+fn write_field(out: &mut Vec<u8>, name: &str, value: &str) {
+    out.extend_from_slice(name.as_bytes());
+    if value.contains('\n') {
+        out.push(b'\n');
+        out.extend_from_slice(&(value.len() as u64).to_le_bytes());
+    } else {
+        out.push(b'=');
+    }
+    out.extend_from_slice(value.as_bytes());
+    out.push(b'\n');
+}
@@ -0,0 +1,513 @@
+//! Writer and parser of the JSON format of errors.
+
+use std::borrow::Cow;
+use std::error;
+use std::fmt;
+use std::io;
+use std::marker;
+use std::sync::Arc;
+
+use super::io::IoContext;
+use super::{metadata, render, xml};
+use super::{Error, ErrorKind, LocalError, Metadata, MultiError, RemoteError, Result, SCHEMA_VERSION};
+
+/// Writes `s` as a JSON string, including the quotes.
+pub(crate) fn write_string(w: &mut dyn fmt::Write, s: &str) -> fmt::Result {
+    w.write_char('"')?;
+    for c in s.chars() {
+        match c {
+            '"' => w.write_str("\\\"")?,
+            '\\' => w.write_str("\\\\")?,
+            '\n' => w.write_str("\\n")?,
+            '\r' => w.write_str("\\r")?,
+            '\t' => w.write_str("\\t")?,
+            c if (c as u32) < 0x20 => write!(w, "\\u{:04x}", c as u32)?,
+            c => w.write_char(c)?,
+        }
+    }
+    w.write_char('"')
+}
+
+/// Writes `e` as a JSON object, in the format described in `Error::to_json`.
+///
+/// The outermost object carries the schema version.
+pub(crate) fn write_error(w: &mut dyn fmt::Write, e: &Error, root: bool) -> fmt::Result {
+    if root {
+        e.mark_reported();
+    }
+    write_open(w, &e.file, e.line, root)?;
+    match e.kind {
+        ErrorKind::String(ref s) | ErrorKind::Cancelled(ref s) => write_string(w, s)?,
+        ErrorKind::Wrapped(ref inner) | ErrorKind::Context(_, ref inner) => write_reason(w, &**inner)?,
+    }
+    if let ErrorKind::Context(ref context, _) = e.kind {
+        w.write_str(",\"context\":")?;
+        write_string(w, context)?;
+    }
+    if let ErrorKind::Cancelled(..) = e.kind {
+        w.write_str(",\"cancelled\":true")?;
+    }
+    if let Some(ref code) = e.code {
+        w.write_str(",\"code\":")?;
+        write_string(w, code)?;
+    }
+    let mut meta = e.metadata.iter_rendered(render::is_unredacted())
+        .filter(|&(key, _)| key != metadata::IO)
+        .peekable();
+    if meta.peek().is_some() {
+        w.write_str(",\"meta\":[")?;
+        for (i, (key, value)) in meta.enumerate() {
+            if i > 0 {
+                w.write_char(',')?;
+            }
+            w.write_char('[')?;
+            write_string(w, key)?;
+            w.write_char(',')?;
+            write_string(w, value)?;
+            w.write_char(']')?;
+        }
+        w.write_char(']')?;
+    }
+    if let Some(io) = e.metadata.get_keyed::<IoContext>(metadata::IO) {
+        w.write_str(",\"io\":{\"operation\":")?;
+        write_string(w, &io.operation)?;
+        w.write_str(",\"path\":")?;
+        write_string(w, &io.path.to_string_lossy())?;
+        if let Some(bytes) = io.bytes {
+            write!(w, ",\"bytes\":{}", bytes)?;
+        }
+        w.write_char('}')?;
+    }
+    w.write_char('}')
+}
+
+/// Writes `errors` as the outermost object, in the format described in `MultiError::to_json`.
+pub(crate) fn write_multi(w: &mut dyn fmt::Write, errors: &MultiError) -> fmt::Result {
+    errors.mark_reported();
+    write!(w, "{{\"schema\":{},\"version\":", SCHEMA_VERSION)?;
+    write_string(w, render::crate_version())?;
+    w.write_str(",\"errors\":")?;
+    write_errors(w, errors)?;
+    w.write_char('}')
+}
+
+/// Writes `errors` to `w` as `write_multi` does, one error at a time.
+pub(crate) fn write_multi_into<W: io::Write>(w: W, errors: &MultiError) -> io::Result<()> {
+    let mut w = IoWriter { inner: w, error: None };
+    match write_multi(&mut w, errors) {
+        Ok(()) => w.inner.flush(),
+        Err(_) => Err(w.error.unwrap_or_else(|| io::Error::other("formatting an error failed"))),
+    }
+}
+
+/// Writes text to an `io::Write`, keeping the IO error which `fmt::Write` can't return.
+struct IoWriter<W> {
+    inner: W,
+    error: Option<io::Error>,
+}
+
+impl<W: io::Write> fmt::Write for IoWriter<W> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.inner.write_all(s.as_bytes()).map_err(|e| {
+            self.error = Some(e);
+            fmt::Error
+        })
+    }
+}
+
+/// Writes `errors` as a JSON array of error objects.
+fn write_errors(w: &mut dyn fmt::Write, errors: &MultiError) -> fmt::Result {
+    w.write_char('[')?;
+    for (i, e) in errors.iter().enumerate() {
+        if i > 0 {
+            w.write_char(',')?;
+        }
+        write_error(w, e, false)?;
+    }
+    w.write_char(']')
+}
+
+/// Writes the start of an error object up to the value of `reason`.
+fn write_open(w: &mut dyn fmt::Write, file: &str, line: u32, root: bool) -> fmt::Result {
+    let (file, line) = render::location(file, line);
+    w.write_char('{')?;
+    if root {
+        write!(w, "\"schema\":{},\"version\":", SCHEMA_VERSION)?;
+        write_string(w, render::crate_version())?;
+        w.write_char(',')?;
+    }
+    w.write_str("\"file\":")?;
+    write_string(w, &file)?;
+    write!(w, ",\"line\":{},\"reason\":", line)
+}
+
+/// Writes an inner error as the value of `reason`.
+fn write_reason(w: &mut dyn fmt::Write, e: &(dyn error::Error + 'static)) -> fmt::Result {
+    if let Some(inner) = e.downcast_ref::<Error>() {
+        write_error(w, inner, false)
+    } else if let Some(inner) = e.downcast_ref::<LocalError>() {
+        write_open(w, inner.file, inner.line, false)?;
+        write_reason(w, &*inner.inner)?;
+        w.write_char('}')
+    } else if let Some(inner) = e.downcast_ref::<MultiError>() {
+        write_errors(w, inner)
+    } else if let Some(inner) = e.downcast_ref::<RemoteError>() {
+        w.write_str("{\"host\":")?;
+        write_string(w, &inner.host)?;
+        w.write_str(",\"service\":")?;
+        write_string(w, &inner.service)?;
+        w.write_str(",\"error\":")?;
+        write_error(w, &inner.error, false)?;
+        w.write_char('}')
+    } else {
+        write_string(w, &e.to_string())
+    }
+}
+
+/// Parsed JSON value.
+enum Value {
+    Null,
+    Bool(bool),
+    /// Number, as written.
+    Number(String),
+    String(String),
+    Array(Vec<Value>),
+    /// Object, with its members in the order written.
+    Object(Vec<(String, Value)>),
+}
+
+/// Parses `s` as an error object written by `write_error`.
+pub(crate) fn parse(s: &str) -> Result<Error> {
+    to_error(parse_value(s)?, true)
+}
+
+/// Parses `s` as an object written by `write_multi`.
+pub(crate) fn parse_multi(s: &str) -> Result<MultiError> {
+    let members = match parse_value(s)? {
+        Value::Object(members) => members,
+        _ => return Err(invalid("(expected an object)")),
+    };
+    let mut errors = None;
+    for (name, value) in members {
+        match (name.as_str(), value) {
+            ("schema", Value::Number(ref n)) => check_schema(n)?,
+            ("version", Value::String(_)) => {}
+            ("errors", Value::Array(values)) => errors = Some(to_errors(values)?),
+            (name, _) => return Err(invalid(&format!("(unexpected member `{}`)", name))),
+        }
+    }
+    errors.ok_or_else(|| invalid("(missing `errors`)"))
+}
+
+/// Parses `s` as written by `catalog::export`, returning the number, code and title of each numbered code.
+pub(crate) fn parse_catalog(s: &str) -> Result<Vec<(u32, String, String)>> {
+    let invalid = |message: &str| Error::new(&format!("invalid catalog JSON {}", message), file!(), line!());
+    let values = match parse_value(s)? {
+        Value::Array(values) => values,
+        _ => return Err(invalid("(expected an array)")),
+    };
+    let mut codes = Vec::new();
+    for value in values {
+        let members = match value {
+            Value::Object(members) => members,
+            _ => return Err(invalid("(expected an object)")),
+        };
+        let (mut number, mut code, mut title) = (None, None, None);
+        for (name, value) in members {
+            match (name.as_str(), value) {
+                ("number", Value::Number(n)) => {
+                    number = Some(n.parse().map_err(|_| invalid("(invalid number)"))?);
+                }
+                ("code", Value::String(s)) => code = Some(s),
+                ("title", Value::String(s)) => title = Some(s),
+                _ => {}
+            }
+        }
+        match (number, code, title) {
+            (Some(number), Some(code), Some(title)) => codes.push((number, code, title)),
+            (None, Some(_), Some(_)) => {}
+            _ => return Err(invalid("(missing `code` or `title`)")),
+        }
+    }
+    Ok(codes)
+}
+
+fn parse_value(s: &str) -> Result<Value> {
+    let mut parser = Parser { s, pos: 0 };
+    let value = parser.value(0)?;
+    parser.skip_ws();
+    if parser.pos != s.len() {
+        return Err(parser.fail("unexpected content after the value"));
+    }
+    Ok(value)
+}
+
+fn check_schema(version: &str) -> Result<()> {
+    if version.parse().is_ok_and(super::is_supported_schema) {
+        Ok(())
+    } else {
+        Err(invalid(&format!("(unsupported schema version `{}`)", version)))
+    }
+}
+
+/// Maximum nesting depth of values, so malicious input can't overflow the stack.
+const MAX_DEPTH: usize = 512;
+
+struct Parser<'a> {
+    s: &'a str,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn rest(&self) -> &'a str {
+        &self.s[self.pos..]
+    }
+
+    fn fail(&self, message: &str) -> Error {
+        invalid(&format!("at offset {}: {}", self.pos, message))
+    }
+
+    fn skip_ws(&mut self) {
+        let rest = self.rest();
+        self.pos += rest.len() - rest.trim_start_matches([' ', '\t', '\n', '\r']).len();
+    }
+
+    fn eat(&mut self, token: &str) -> bool {
+        self.skip_ws();
+        if self.rest().starts_with(token) {
+            self.pos += token.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, token: &str) -> Result<()> {
+        if self.eat(token) {
+            Ok(())
+        } else {
+            Err(self.fail(&format!("expected `{}`", token)))
+        }
+    }
+
+    fn value(&mut self, depth: usize) -> Result<Value> {
+        if depth > MAX_DEPTH {
+            return Err(self.fail("too deeply nested"));
+        }
+        self.skip_ws();
+        match self.rest().chars().next() {
+            Some('{') => {
+                self.pos += 1;
+                let mut members = Vec::new();
+                if !self.eat("}") {
+                    loop {
+                        self.skip_ws();
+                        let name = self.string()?;
+                        self.expect(":")?;
+                        members.push((name, self.value(depth + 1)?));
+                        if self.eat("}") {
+                            break;
+                        }
+                        self.expect(",")?;
+                    }
+                }
+                Ok(Value::Object(members))
+            }
+            Some('[') => {
+                self.pos += 1;
+                let mut values = Vec::new();
+                if !self.eat("]") {
+                    loop {
+                        values.push(self.value(depth + 1)?);
+                        if self.eat("]") {
+                            break;
+                        }
+                        self.expect(",")?;
+                    }
+                }
+                Ok(Value::Array(values))
+            }
+            Some('"') => Ok(Value::String(self.string()?)),
+            Some('-') | Some('0'..='9') => {
+                let rest = self.rest();
+                let len = rest.find(|c: char| !matches!(c, '0'..='9' | '-' | '+' | '.' | 'e' | 'E'))
+                    .unwrap_or(rest.len());
+                self.pos += len;
+                Ok(Value::Number(rest[..len].to_string()))
+            }
+            _ if self.eat("true") => Ok(Value::Bool(true)),
+            _ if self.eat("false") => Ok(Value::Bool(false)),
+            _ if self.eat("null") => Ok(Value::Null),
+            _ => Err(self.fail("expected a value")),
+        }
+    }
+
+    fn string(&mut self) -> Result<String> {
+        if !self.rest().starts_with('"') {
+            return Err(self.fail("expected a string"));
+        }
+        self.pos += 1;
+        let mut out = String::new();
+        loop {
+            let mut chars = self.rest().char_indices();
+            let (i, c) = chars.next().ok_or_else(|| self.fail("unterminated string"))?;
+            self.pos += i + c.len_utf8();
+            match c {
+                '"' => return Ok(out),
+                '\\' => {
+                    let escape = self.rest().chars().next().ok_or_else(|| self.fail("unterminated string"))?;
+                    self.pos += escape.len_utf8();
+                    out.push(match escape {
+                        '"' => '"',
+                        '\\' => '\\',
+                        '/' => '/',
+                        'b' => '\u{8}',
+                        'f' => '\u{c}',
+                        'n' => '\n',
+                        'r' => '\r',
+                        't' => '\t',
+                        'u' => self.unicode_escape()?,
+                        _ => return Err(self.fail("invalid escape sequence")),
+                    });
+                }
+                c if (c as u32) < 0x20 => return Err(self.fail("control character in string")),
+                c => out.push(c),
+            }
+        }
+    }
+
+    /// Reads the hex digits of a `\u` escape, including a following low surrogate.
+    fn unicode_escape(&mut self) -> Result<char> {
+        let high = self.hex4()?;
+        if !(0xd800..0xdc00).contains(&high) {
+            return char::from_u32(high).ok_or_else(|| self.fail("invalid unicode escape"));
+        }
+        if !self.rest().starts_with("\\u") {
+            return Err(self.fail("unpaired surrogate"));
+        }
+        self.pos += 2;
+        let low = self.hex4()?;
+        if !(0xdc00..0xe000).contains(&low) {
+            return Err(self.fail("unpaired surrogate"));
+        }
+        char::from_u32(0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00))
+            .ok_or_else(|| self.fail("invalid unicode escape"))
+    }
+
+    fn hex4(&mut self) -> Result<u32> {
+        let digits = self.rest().get(..4).ok_or_else(|| self.fail("invalid unicode escape"))?;
+        let n = u32::from_str_radix(digits, 16).map_err(|_| self.fail("invalid unicode escape"))?;
+        self.pos += 4;
+        Ok(n)
+    }
+}
+
+fn invalid(message: &str) -> Error {
+    Error::new(&format!("invalid error JSON {}", message), file!(), line!())
+}
+
+/// Converts the parsed `value` into an `Error`.
+fn to_error(value: Value, root: bool) -> Result<Error> {
+    let members = match value {
+        Value::Object(members) => members,
+        _ => return Err(invalid("(expected an error object)")),
+    };
+    let (mut file, mut line, mut reason, mut context, mut code) = (None, None, None, None, None);
+    let mut cancelled = false;
+    let mut metadata = Metadata::new();
+    for (name, value) in members {
+        match (name.as_str(), value) {
+            ("schema", Value::Number(ref n)) if root => check_schema(n)?,
+            ("version", Value::String(_)) if root => {}
+            ("file", Value::String(s)) => file = Some(s),
+            ("line", Value::Number(n)) => {
+                line = Some(n.parse().map_err(|_| invalid("(invalid line number)"))?);
+            }
+            ("reason", value) => reason = Some(value),
+            ("context", Value::String(s)) => context = Some(s),
+            ("cancelled", Value::Bool(b)) => cancelled = b,
+            ("code", Value::String(s)) => code = Some(Cow::Owned(s)),
+            ("meta", Value::Array(entries)) => {
+                for entry in entries {
+                    let (key, value) = match entry {
+                        Value::Array(ref pair) => match pair.as_slice() {
+                            [Value::String(key), Value::String(value)] => (key.clone(), value.clone()),
+                            _ => return Err(invalid("(invalid meta entry)")),
+                        },
+                        _ => return Err(invalid("(invalid meta entry)")),
+                    };
+                    let parsed = xml::unquote(&value).unwrap_or_else(|| value.clone());
+                    metadata.insert_entry(Cow::Owned(key), Arc::new(parsed), value, None);
+                }
+            }
+            ("io", Value::Object(io)) => metadata.insert_keyed(metadata::IO, to_io(io)?),
+            (name, _) => return Err(invalid(&format!("(unexpected member `{}`)", name))),
+        }
+    }
+
+    let file = file.ok_or_else(|| invalid("(missing `file`)"))?;
+    let line = line.ok_or_else(|| invalid("(missing `line`)"))?;
+    let reason: Box<dyn error::Error + marker::Send + marker::Sync> = match reason {
+        Some(Value::String(s)) => {
+            if context.is_none() {
+                let kind = if cancelled { ErrorKind::Cancelled(s) } else { ErrorKind::String(s) };
+                return Ok(Error { file: Cow::Owned(file), line, kind, code, metadata });
+            }
+            s.into()
+        }
+        Some(Value::Array(errors)) => Box::new(to_errors(errors)?),
+        Some(Value::Object(members)) if members.first().is_some_and(|(name, _)| name == "host") => {
+            Box::new(to_remote(members)?)
+        }
+        Some(inner @ Value::Object(_)) => Box::new(to_error(inner, false)?),
+        _ => return Err(invalid("(missing or invalid `reason`)")),
+    };
+    let kind = match context {
+        Some(context) => ErrorKind::Context(context, reason),
+        None => ErrorKind::Wrapped(reason),
+    };
+    Ok(Error { file: Cow::Owned(file), line, kind, code, metadata })
+}
+
+fn to_remote(members: Vec<(String, Value)>) -> Result<RemoteError> {
+    let (mut host, mut service, mut error) = (None, None, None);
+    for (name, value) in members {
+        match (name.as_str(), value) {
+            ("host", Value::String(s)) => host = Some(s),
+            ("service", Value::String(s)) => service = Some(s),
+            ("error", value) => error = Some(to_error(value, false)?),
+            (name, _) => return Err(invalid(&format!("(unexpected member `remote.{}`)", name))),
+        }
+    }
+    match (host, service, error) {
+        (Some(host), Some(service), Some(error)) => Ok(RemoteError::new(host, service, error)),
+        _ => Err(invalid("(incomplete remote error)")),
+    }
+}
+
+fn to_errors(values: Vec<Value>) -> Result<MultiError> {
+    let errors = values.into_iter().map(|e| to_error(e, false)).collect::<Result<Vec<_>>>()?;
+    Ok(MultiError::from(errors))
+}
+
+fn to_io(members: Vec<(String, Value)>) -> Result<IoContext> {
+    let (mut operation, mut path, mut bytes) = (None, None, None);
+    for (name, value) in members {
+        match (name.as_str(), value) {
+            ("operation", Value::String(s)) => operation = Some(s),
+            ("path", Value::String(s)) => path = Some(s),
+            ("bytes", Value::Number(n)) => {
+                bytes = Some(n.parse().map_err(|_| invalid("(invalid number of bytes)"))?);
+            }
+            (name, _) => return Err(invalid(&format!("(unexpected member `io.{}`)", name))),
+        }
+    }
+    match (operation, path) {
+        (Some(operation), Some(path)) => {
+            let mut io = IoContext::new(operation, path);
+            io.bytes = bytes;
+            Ok(io)
+        }
+        _ => Err(invalid("(incomplete `io`)")),
+    }
+}
@@ -0,0 +1,44 @@
+//! Errors of AWS Lambda functions built with lambda_runtime.
+
+use lambda_runtime::Diagnostic;
+
+use super::{hooks, Error};
+
+/// Converts `Error` into the error payload of Lambda functions.
+///
+/// The `errorType` of the payload is the code of the outermost error having
+/// one, or else `mm_errors::Error`, and the `errorMessage` is the whole chain,
+/// as written by `Error::to_json`. The error is also passed to
+/// `hooks::report`, so with the `log` feature it is logged at the level of
+/// its severity, e.g. to CloudWatch through the logger of the function.
+///
+/// This is available with the `lambda` feature.
+///
+/// # Examples
+///
+/// ```
+/// use lambda_runtime::Diagnostic;
+///
+/// use mm_errors::{hooks, Error};
+///
+/// # hooks::set_report_hook(|_: &Error| {});
+/// let e = Error::wrap(Error::here("no order 7").with_code("LMB404"), "handler.rs", 3);
+/// let diagnostic = Diagnostic::from(e.clone());
+///
+/// assert_eq!(diagnostic.error_type, "LMB404");
+/// assert_eq!(Error::from_json(&diagnostic.error_message).unwrap().to_json(), e.to_json());
+/// # hooks::reset_report_hook();
+/// ```
+///
+impl From<Error> for Diagnostic {
+    fn from(e: Error) -> Diagnostic {
+        hooks::report(&e);
+        let code = e.chain()
+            .filter_map(|e| e.downcast_ref::<Error>())
+            .find_map(|e| e.code.as_ref());
+        Diagnostic {
+            error_type: code.map_or_else(|| "mm_errors::Error".to_string(), |code| code.to_string()),
+            error_message: e.to_json(),
+        }
+    }
+}
@@ -41,7 +41,7 @@
 //! The above code outputs following.
 //!
 //! ```xml
-//! <error>
+//! <error schema="2" version="0.1.0">
 //!       <file>src\lib.rs</file>
 //!       <line>20</line>
 //!       <reason>
@@ -100,7 +100,7 @@
 //! The above code outputs following.
 //!
 //! ```xml
-//! <error>
+//! <error schema="2" version="0.1.0">
 //!     <file>src\lib.rs</file>
 //!     <line>21</line>
 //!     <reason>
@@ -149,137 +149,1296 @@
 //! The above code outputs following.
 //!
 //! ```xml
-//! <error>
+//! <error schema="2" version="0.1.0">
 //!     <file>src\lib.rs</file>
 //!     <line>10</line>
 //!     <reason>This function always returns error</reason>
 //! </error>
 //! ```
 //!
+//! # Output format
+//!
+//! `Display` renders an error as an `<error>` element. Whitespace is added
+//! above for readability only; the actual output contains none.
+//!
+//! * The outermost `<error>` element has a `schema` attribute holding
+//!   [`SCHEMA_VERSION`] and a `version` attribute holding the version of this
+//!   crate which wrote it.
+//! * The children of `<error>` always appear in the order `<file>`, `<line>`, `<reason>`,
+//!   `<context>`, `<cancelled/>`, `<code>`. `<context>` is present only for `ErrorKind::Context`,
+//!   the empty `<cancelled/>` element only for `ErrorKind::Cancelled`, and `<code>`
+//!   only if the error has a code, followed by a `<meta key="...">` element for
+//!   each value of `Error::metadata` and an `<io>` element if an `io::IoContext`
+//!   is attached.
+//! * `<reason>` holds either text or, if the inner error is an `Error`,
+//!   a single child `<error>` element without the `schema` and `version` attributes.
+//!   If the inner error is a `MultiError`, `<reason>` holds an `<errors>`
+//!   element, which holds an `<error>` element for each error.
+//!   If the inner error is a `RemoteError`, `<reason>` holds a `<remote>`
+//!   element with `host` and `service` attributes, which holds the `<error>`
+//!   element of the remote chain.
+//! * Text is XML-escaped, and control characters are handled as configured by
+//!   [`render::set_control_chars`].
+//!
+//! New elements may be added in a minor release, always after the existing ones.
+//! Removing, renaming, or reordering elements increments [`SCHEMA_VERSION`].
+//! Parsers accept every version from [`MIN_SCHEMA_VERSION`] on, so stored
+//! errors remain readable after upgrades. The versions are:
+//!
+//! * 1 - The initial format.
+//! * 2 - Adds the `version` attribute and the `version` member of JSON, which
+//!   readers of version 1 reject.
+//!
+//! [`SCHEMA_VERSION`]: constant.SCHEMA_VERSION.html
+//! [`MIN_SCHEMA_VERSION`]: constant.MIN_SCHEMA_VERSION.html
+//! [`render::set_control_chars`]: render/fn.set_control_chars.html
+//!
+//! # `no_std`
+//!
+//! Everything but the `embedded` module and the `core_error!` macro needs the
+//! `std` feature, which is enabled by default and by every other feature.
+//! Disable the default features for targets without `std` or an allocator.
+//!
 
+#![cfg_attr(not(feature = "std"), no_std)]
 
+#[cfg(feature = "std")]
+use std::any;
+#[cfg(feature = "std")]
+use std::backtrace::{Backtrace, BacktraceStatus};
+#[cfg(feature = "std")]
+use std::borrow::Cow;
+#[cfg(feature = "std")]
+use std::env;
+#[cfg(feature = "std")]
 use std::error;
+#[cfg(feature = "std")]
 use std::fmt;
+#[cfg(feature = "std")]
+use std::fmt::Write;
+#[cfg(feature = "std")]
 use std::marker;
+#[cfg(feature = "std")]
+use std::panic;
+#[cfg(feature = "std")]
 use std::result;
 
+#[cfg(feature = "clap")]
+mod args;
+#[cfg(feature = "std")]
+pub mod body;
+#[cfg(feature = "std")]
+pub mod breaker;
+#[cfg(feature = "std")]
+pub mod catalog;
+#[cfg(feature = "cbor")]
+pub mod cbor;
+#[cfg(feature = "std")]
+pub mod cli;
+#[cfg(any(feature = "gzip", feature = "zstd"))]
+pub mod compress;
+#[cfg(all(feature = "windows", windows))]
+pub mod eventlog;
+pub mod embedded;
+#[cfg(feature = "std")]
+mod ext;
+#[cfg(feature = "faultinject")]
+pub mod faultinject;
+#[cfg(feature = "slog")]
+mod fields;
+#[cfg(feature = "std")]
+pub mod filter;
+#[cfg(feature = "arbitrary")]
+mod fuzz;
+#[cfg(feature = "async")]
+pub mod future;
+#[cfg(feature = "graphql")]
+mod graphql;
+#[cfg(feature = "std")]
+pub mod health;
+#[cfg(feature = "std")]
+pub mod hooks;
+#[cfg(feature = "std")]
+pub mod io;
+#[cfg(all(feature = "journald", unix))]
+pub mod journald;
+#[cfg(feature = "std")]
+mod json;
+#[cfg(feature = "lambda")]
+mod lambda;
+#[cfg(feature = "std")]
+mod local;
+#[cfg(feature = "log")]
+pub mod logging;
+#[cfg(feature = "std")]
+pub mod metadata;
+#[cfg(feature = "tower")]
+pub mod middleware;
+#[cfg(feature = "std")]
+mod multi;
+#[cfg(feature = "std")]
+mod problem;
+#[cfg(feature = "prost")]
+pub mod proto;
+#[cfg(feature = "std")]
+pub mod rate;
+#[cfg(feature = "warp")]
+pub mod rejection;
+#[cfg(feature = "std")]
+mod remote;
+#[cfg(feature = "std")]
+pub mod render;
+#[cfg(feature = "std")]
+pub mod report;
+#[cfg(feature = "http")]
+pub mod response;
+#[cfg(feature = "std")]
+pub mod retry;
+#[cfg(any(feature = "tokio", feature = "async-std"))]
+pub mod rt;
+#[cfg(feature = "stream")]
+pub mod stream;
+#[cfg(feature = "std")]
+pub mod sync;
+#[cfg(feature = "std")]
+pub mod testing;
+#[cfg(feature = "std")]
+pub mod timing;
+#[cfg(feature = "std")]
+mod token;
+#[cfg(feature = "std")]
+pub mod truncate;
+#[cfg(feature = "std")]
+mod xml;
+
+#[cfg(feature = "std")]
+pub use catalog::{ErrorCode, Severity};
+/// Derives `ErrorCode` and `From<T> for Error` for an enum of error codes.
+///
+/// This macro is available with the `derive` feature. Each variant takes an
+/// optional `#[error_code(code = "...", severity = "...", message = "...")]`
+/// attribute. The code defaults to the name of the variant, the severity to
+/// `error`, and the message to the registered title of the code.
+///
+/// # Examples
+///
+/// ```
+/// use mm_errors::{Error, ErrorCode, Severity};
+///
+/// #[derive(ErrorCode)]
+/// enum StorageError {
+///     #[error_code(code = "STO001", severity = "critical", message = "disk full")]
+///     DiskFull,
+///     #[error_code(code = "STO002")]
+///     NotFound { path: String },
+/// }
+///
+/// fn save() -> mm_errors::Result<()> {
+///     Err(StorageError::DiskFull)?
+/// }
+///
+/// let e = save().unwrap_err();
+/// assert_eq!(e.code.as_deref(), Some("STO001"));
+/// assert_eq!(e.severity(), Severity::Critical);
+/// assert_eq!(e.message(), Some("disk full"));
+///
+/// let e = Error::from(StorageError::NotFound { path: "a.txt".to_string() });
+/// assert_eq!(e.message(), Some("STO002"));
+/// ```
+///
+#[cfg(feature = "derive")]
+pub use mm_errors_derive::ErrorCode;
+#[cfg(feature = "std")]
+pub use ext::ResultExt;
+#[cfg(feature = "std")]
+pub use local::LocalError;
+#[cfg(feature = "std")]
+pub use metadata::{Metadata, Redacted};
+#[cfg(feature = "std")]
+pub use multi::MultiError;
+#[cfg(feature = "std")]
+pub use remote::RemoteError;
+#[cfg(feature = "std")]
+pub use retry::retry;
+
 /// Holds error information.
 ///
 ///
 /// See [the module level document] for detail.
 ///
+/// `Display` renders the error as XML, while `Debug` renders a multi-line
+/// representation of the fields and the whole chain of inner errors.
+///
+/// # Examples
+///
+/// ```
+/// use mm_errors::Error;
+///
+/// let e = Error::wrap(Error::new("inner", "inner.rs", 1), "outer.rs", 2);
+///
+/// assert_eq!(format!("{:?}", e), "\
+/// Error {
+///     file: \"outer.rs\",
+///     line: 2,
+///     kind: Wrapped(
+///         Error {
+///             file: \"inner.rs\",
+///             line: 1,
+///             kind: String(
+///                 \"inner\",
+///             ),
+///             code: None,
+///             metadata: {},
+///         },
+///     ),
+///     code: None,
+///     metadata: {},
+/// }");
+/// ```
+///
 /// [the module level document]: index.html
 ///
+#[cfg(feature = "std")]
 #[derive(Clone)]
 pub struct Error {
     /// File where error occurred.
-    pub file: &'static str,
+    ///
+    /// This is borrowed for errors created in this process, and owned for
+    /// errors parsed with `Error::from_xml`.
+    pub file: Cow<'static, str>,
 
     /// line number where error occurred.
     pub line: u32,
 
     /// Error kind.
     pub kind: ErrorKind,
+
+    /// Error code identifying the kind of failure, e.g. `"E042"`.
+    pub code: Option<Cow<'static, str>>,
+
+    /// Typed values attached to the error.
+    pub metadata: Metadata,
 }
 
 /// Error kinds.
+#[cfg(feature = "std")]
 #[derive(Debug)]
 pub enum ErrorKind {
     /// Error with error message.
     String(String),
 
     /// Error with internal error.
-    Wrapped(Box<error::Error + marker::Send + marker::Sync>),
+    Wrapped(Box<dyn error::Error + marker::Send + marker::Sync>),
+
+    /// Error with a message describing what was being done, and internal error.
+    Context(String, Box<dyn error::Error + marker::Send + marker::Sync>),
+
+    /// Operation was cancelled deliberately, e.g. on shutdown, with a message.
+    Cancelled(String),
+}
+
+#[cfg(feature = "std")]
+impl Clone for ErrorKind {
+    fn clone(&self) -> Self {
+        match *self {
+            ErrorKind::String(ref s) => ErrorKind::String(s.clone()),
+            ErrorKind::Cancelled(ref s) => ErrorKind::Cancelled(s.clone()),
+            ErrorKind::Wrapped(ref e) => match clone_inner(&**e) {
+                Some(inner) => ErrorKind::Wrapped(inner),
+                None => ErrorKind::String(format!("{}", e)),
+            },
+            ErrorKind::Context(ref m, ref e) => match clone_inner(&**e) {
+                Some(inner) => ErrorKind::Context(m.clone(), inner),
+                None => ErrorKind::Context(m.clone(), e.to_string().into()),
+            },
+        }
+    }
+}
+
+/// Clones `e` if it is one of the error types of this crate.
+#[cfg(feature = "std")]
+fn clone_inner(e: &(dyn error::Error + 'static))
+    -> Option<Box<dyn error::Error + marker::Send + marker::Sync>> {
+    if let Some(inner) = e.downcast_ref::<Error>() {
+        Some(Box::new(inner.clone()))
+    } else if let Some(inner) = e.downcast_ref::<MultiError>() {
+        Some(Box::new(inner.clone()))
+    } else if let Some(inner) = e.downcast_ref::<RemoteError>() {
+        Some(Box::new(inner.clone()))
+    } else {
+        None
+    }
+}
+
+#[cfg(feature = "std")]
+impl fmt::Debug for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.mark_reported();
+        // Always use the multi-line form so `unwrap()` panics stay readable.
+        write!(f, "{:#?}", DebugFields(self))
+    }
+}
+
+#[cfg(feature = "std")]
+struct DebugFields<'a>(&'a Error);
+
+#[cfg(feature = "std")]
+impl<'a> fmt::Debug for DebugFields<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (file, line) = render::location(&self.0.file, self.0.line);
+        f.debug_struct("Error")
+            .field("file", &file)
+            .field("line", &line)
+            .field("kind", &self.0.kind)
+            .field("code", &self.0.code)
+            .field("metadata", &self.0.metadata)
+            .finish()
+    }
+}
+
+
+#[cfg(feature = "std")]
+impl Error {
+    /// Returns a new instance of `Error`.
+    ///
+    /// # Arguments
+    ///
+    /// * message - Error message.
+    /// * file - File where error occurred.
+    /// * line - Line number where error occurred.
+    ///
+    pub fn new(message: &str, file: &'static str, line: u32) -> Error {
+        created(Error {
+            file: Cow::Borrowed(file),
+            line,
+            kind: ErrorKind::String(message.to_string()),
+            code: None,
+            metadata: Metadata::new(),
+        })
+    }
+
+    /// Returns a new instance of `Error`
+    ///
+    /// The return value holds `e` as inner error.
+    ///
+    /// # Arguments
+    ///
+    /// * e - Inner error.
+    /// * file - File where error occurred.
+    /// * line - Line number where error occurred.
+    ///
+    /// # Examples
+    ///
+    /// A wrapped `Error` is rendered as a child `<error>` element.
+    ///
+    /// ```
+    /// use mm_errors::Error;
+    ///
+    /// let inner = Error::new("a < b", "inner.rs", 1);
+    /// let outer = Error::wrap(inner, "outer.rs", 2);
+    ///
+    /// assert_eq!(
+    ///     outer.to_string(),
+    ///     format!(
+    ///         "<error schema=\"2\" version=\"{}\"><file>outer.rs</file><line>2</line><reason>\
+    ///          <error><file>inner.rs</file><line>1</line><reason>a &lt; b</reason></error>\
+    ///          </reason></error>",
+    ///         mm_errors::CRATE_VERSION));
+    /// ```
+    ///
+    pub fn wrap<T>(e: T, file: &'static str, line: u32) -> Error
+        where T: Into<Box<dyn error::Error + marker::Send + marker::Sync>> {
+        created(Error {
+            file: Cow::Borrowed(file),
+            line,
+            kind: ErrorKind::Wrapped(e.into()),
+            code: None,
+            metadata: Metadata::new(),
+        })
+    }
+
+    /// Returns a new instance of `Error`
+    ///
+    /// The return value holds `e` as inner error, and `message` describing
+    /// what was being done when `e` occurred.
+    ///
+    /// # Arguments
+    ///
+    /// * e - Inner error.
+    /// * message - Context message.
+    /// * file - File where error occurred.
+    /// * line - Line number where error occurred.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mm_errors::Error;
+    ///
+    /// let inner = "XXX".parse::<u32>().unwrap_err();
+    /// let e = Error::context(inner, "reading the port number", "config.rs", 3);
+    ///
+    /// assert_eq!(
+    ///     e.to_string(),
+    ///     format!(
+    ///         "<error schema=\"2\" version=\"{}\"><file>config.rs</file><line>3</line>\
+    ///          <reason>invalid digit found in string</reason>\
+    ///          <context>reading the port number</context></error>",
+    ///         mm_errors::CRATE_VERSION));
+    /// ```
+    ///
+    pub fn context<T>(e: T, message: &str, file: &'static str, line: u32) -> Error
+        where T: Into<Box<dyn error::Error + marker::Send + marker::Sync>> {
+        created(Error {
+            file: Cow::Borrowed(file),
+            line,
+            kind: ErrorKind::Context(message.to_string(), e.into()),
+            code: None,
+            metadata: Metadata::new(),
+        })
+    }
+
+    /// Returns a new instance of `Error` for an operation cancelled deliberately.
+    ///
+    /// Use this for shutdown or other intended cancellation, so handlers can
+    /// tell it from genuine failures with `is_cancelled`.
+    ///
+    /// # Arguments
+    ///
+    /// * message - Error message.
+    /// * file - File where error occurred.
+    /// * line - Line number where error occurred.
+    ///
+    pub fn cancelled(message: &str, file: &'static str, line: u32) -> Error {
+        created(Error {
+            file: Cow::Borrowed(file),
+            line,
+            kind: ErrorKind::Cancelled(message.to_string()),
+            code: None,
+            metadata: Metadata::new(),
+        })
+    }
+
+    /// Returns `true` if `self` or any error in its chain is `ErrorKind::Cancelled`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mm_errors::Error;
+    ///
+    /// let e = Error::wrap(Error::cancelled("shutting down", "worker.rs", 3), "main.rs", 9);
+    /// assert!(e.is_cancelled());
+    ///
+    /// let e = Error::wrap(Error::new("disk full", "worker.rs", 3), "main.rs", 9);
+    /// assert!(!e.is_cancelled());
+    /// ```
+    ///
+    pub fn is_cancelled(&self) -> bool {
+        self.chain().any(|e| {
+            matches!(e.downcast_ref::<Error>(), Some(&Error { kind: ErrorKind::Cancelled(..), .. }))
+        })
+    }
+
+    /// Returns `self` with its error code set to `code`.
+    ///
+    /// # Arguments
+    ///
+    /// * code - Error code.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # #[macro_use] extern crate mm_errors;
+    ///
+    /// # fn main() {
+    ///     let e = new_error!("disk full").with_code("E042");
+    ///     assert_eq!(e.code.as_deref(), Some("E042"));
+    ///     assert!(e.to_string().ends_with("<code>E042</code></error>"));
+    /// # }
+    /// ```
+    ///
+    pub fn with_code<C>(mut self, code: C) -> Error
+        where C: Into<Cow<'static, str>> {
+        let code = code.into();
+        catalog::check_deprecated(&code);
+        self.code = Some(code);
+        self
+    }
+
+    /// Returns `self` with `value` attached under the name of its type.
+    ///
+    /// See `Metadata` for how values are stored and rendered.
+    ///
+    /// # Arguments
+    ///
+    /// * value - Value to attach.
+    ///
+    pub fn with_meta<T>(mut self, value: T) -> Error
+        where T: any::Any + marker::Send + marker::Sync + fmt::Debug {
+        self.metadata.insert(value);
+        self
+    }
+
+    /// Returns `self` with `value` attached under `key`.
+    ///
+    /// # Arguments
+    ///
+    /// * key - Key of the value.
+    /// * value - Value to attach.
+    ///
+    pub fn with_meta_keyed<K, T>(mut self, key: K, value: T) -> Error
+        where K: Into<Cow<'static, str>>,
+              T: any::Any + marker::Send + marker::Sync + fmt::Debug {
+        self.metadata.insert_keyed(key, value);
+        self
+    }
+
+    /// Returns `self` with the sensitive `value` attached under `key`.
+    ///
+    /// The value is rendered as `***` in every output, except by `render_unredacted`.
+    /// Read it back with `Metadata::get_secret`.
+    ///
+    /// # Arguments
+    ///
+    /// * key - Key of the value.
+    /// * value - Value to attach.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mm_errors::Error;
+    ///
+    /// let e = Error::new("login failed", "auth.rs", 1).with_secret("password", "hunter2");
+    ///
+    /// assert!(e.to_string().contains("<meta key=\"password\">***</meta>"));
+    /// assert!(!format!("{:?}", e).contains("hunter2"));
+    /// assert_eq!(e.metadata.get_secret::<&str>("password"), Some(&"hunter2"));
+    /// assert!(e.render_unredacted().contains("<meta key=\"password\">&quot;hunter2&quot;</meta>"));
+    /// ```
+    ///
+    pub fn with_secret<K, T>(mut self, key: K, value: T) -> Error
+        where K: Into<Cow<'static, str>>,
+              T: any::Any + marker::Send + marker::Sync + fmt::Debug {
+        self.metadata.insert_secret(key, value);
+        self
+    }
+
+    /// Returns `self` with the current values of the environment variables `names` attached.
+    ///
+    /// The value of each variable is attached as a `String` under the key
+    /// `env.` followed by its name. Variables which are not set, or whose
+    /// values are not valid Unicode, are skipped.
+    ///
+    /// # Arguments
+    ///
+    /// * names - Names of the environment variables to capture.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mm_errors::Error;
+    ///
+    /// std::env::set_var("APP_MODE", "staging");
+    /// let e = Error::new("startup failed", "main.rs", 1).with_env(&["APP_MODE", "NO_SUCH_VARIABLE"]);
+    ///
+    /// assert_eq!(e.metadata.get_keyed::<String>("env.APP_MODE").map(|s| s.as_str()), Some("staging"));
+    /// assert_eq!(e.metadata.len(), 1);
+    /// ```
+    ///
+    pub fn with_env(mut self, names: &[&str]) -> Error {
+        for name in names {
+            if let Ok(value) = env::var(name) {
+                self.metadata.insert_keyed(format!("env.{}", name), value);
+            }
+        }
+        self
+    }
+
+    /// Returns `self` with the ID of the request being processed attached.
+    ///
+    /// The ID is attached as a `String` under `metadata::REQUEST_ID`, so every
+    /// service reports it under the same key.
+    ///
+    /// # Arguments
+    ///
+    /// * id - Request ID.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mm_errors::Error;
+    ///
+    /// let inner = Error::new("query failed", "db.rs", 1).with_request_id("req-42").with_user("alice");
+    /// let e = Error::context(inner, "handling /orders", "api.rs", 9).with_trace_id("4bf92f35");
+    ///
+    /// assert_eq!(e.request_id(), Some("req-42"));
+    /// assert_eq!(e.trace_id(), Some("4bf92f35"));
+    /// assert_eq!(e.user(), Some("alice"));
+    /// assert!(e.to_string().contains("<meta key=\"request_id\">&quot;req-42&quot;</meta>"));
+    ///
+    /// let received = Error::from_xml(&e.to_string()).unwrap();
+    /// assert_eq!(received.request_id(), Some("req-42"));
+    /// ```
+    ///
+    pub fn with_request_id<S: Into<String>>(self, id: S) -> Error {
+        self.with_meta_keyed(metadata::REQUEST_ID, id.into())
+    }
+
+    /// Returns `self` with the distributed trace ID attached.
+    ///
+    /// The ID is attached as a `String` under `metadata::TRACE_ID`.
+    ///
+    /// # Arguments
+    ///
+    /// * id - Trace ID.
+    ///
+    pub fn with_trace_id<S: Into<String>>(self, id: S) -> Error {
+        self.with_meta_keyed(metadata::TRACE_ID, id.into())
+    }
+
+    /// Returns `self` with the user on whose behalf the operation ran attached.
+    ///
+    /// The user is attached as a `String` under `metadata::USER`. Use
+    /// `with_secret` instead if the user must not appear in logs.
+    ///
+    /// # Arguments
+    ///
+    /// * user - User name or ID.
+    ///
+    pub fn with_user<S: Into<String>>(self, user: S) -> Error {
+        self.with_meta_keyed(metadata::USER, user.into())
+    }
+
+    /// Returns `self` with a hint on how to fix the error attached.
+    ///
+    /// The hint is attached as a `String` under `metadata::HELP`, and each of
+    /// its lines is written as a `help` line by `cli::render`.
+    ///
+    /// # Arguments
+    ///
+    /// * help - Hint for the user.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mm_errors::{cli, Error};
+    ///
+    /// let e = Error::here("no configuration file found").with_help("run `app init` to create one");
+    ///
+    /// assert_eq!(e.help(), Some("run `app init` to create one"));
+    /// assert_eq!(
+    ///     cli::render_at(&e, false, 1),
+    ///     "error: no configuration file found\n\
+    ///      help: run `app init` to create one");
+    /// ```
+    ///
+    pub fn with_help<S: Into<String>>(self, help: S) -> Error {
+        self.with_meta_keyed(metadata::HELP, help.into())
+    }
+
+    /// Returns the request ID attached to the outermost error in the chain having one.
+    pub fn request_id(&self) -> Option<&str> {
+        self.find_string(metadata::REQUEST_ID)
+    }
+
+    /// Returns the trace ID attached to the outermost error in the chain having one.
+    pub fn trace_id(&self) -> Option<&str> {
+        self.find_string(metadata::TRACE_ID)
+    }
+
+    /// Returns the user attached to the outermost error in the chain having one.
+    pub fn user(&self) -> Option<&str> {
+        self.find_string(metadata::USER)
+    }
+
+    /// Returns the hint attached to the outermost error in the chain having one.
+    pub fn help(&self) -> Option<&str> {
+        self.find_string(metadata::HELP)
+    }
+
+    fn find_string(&self, key: &str) -> Option<&str> {
+        self.chain()
+            .filter_map(|e| e.downcast_ref::<Error>())
+            .find_map(|e| e.metadata.get_keyed::<String>(key))
+            .map(|s| s.as_str())
+    }
+
+    /// Returns the keys and rendered values attached to every error in the chain.
+    ///
+    /// Each key appears once, with the value of the innermost error having it,
+    /// in the order keys first appear walking the chain from the outermost error.
+    /// Secrets are rendered as `***`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mm_errors::Error;
+    ///
+    /// let inner = Error::new("query failed", "db.rs", 1)
+    ///     .with_meta_keyed("table", "orders")
+    ///     .with_request_id("req-1");
+    /// let e = Error::wrap(inner, "api.rs", 2).with_meta_keyed("table", "users");
+    ///
+    /// assert_eq!(e.all_context(), [("table", "\"orders\""), ("request_id", "\"req-1\"")]);
+    /// ```
+    ///
+    pub fn all_context(&self) -> Vec<(&str, &str)> {
+        self.all_context_by(metadata::Precedence::Innermost)
+    }
+
+    /// Returns the keys and rendered values attached to every error in the chain,
+    /// taking the value of a key attached at several levels by `precedence`.
+    ///
+    /// See `all_context` for the order of the keys.
+    ///
+    /// # Arguments
+    ///
+    /// * precedence - Which value wins.
+    ///
+    pub fn all_context_by(&self, precedence: metadata::Precedence) -> Vec<(&str, &str)> {
+        let mut merged: Vec<(&str, &str)> = Vec::new();
+        for e in self.chain().filter_map(|e| e.downcast_ref::<Error>()) {
+            for (key, value) in &e.metadata {
+                match merged.iter_mut().find(|(k, _)| *k == key) {
+                    Some(entry) if precedence == metadata::Precedence::Innermost => entry.1 = value,
+                    Some(_) => (),
+                    None => merged.push((key, value)),
+                }
+            }
+        }
+        merged
+    }
+
+    /// Returns the rendered value under `key` of the outermost error in the chain
+    /// having one, and the level of that error.
+    ///
+    /// The level is the position of the error in `chain`, 0 being `self`.
+    /// Secrets are rendered as `***`.
+    ///
+    /// # Arguments
+    ///
+    /// * key - Key of the value.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mm_errors::Error;
+    ///
+    /// let inner = Error::new("not found", "db.rs", 1).with_meta_keyed("status", 404u16);
+    /// let e = Error::context(inner, "loading the order", "api.rs", 2);
+    ///
+    /// assert_eq!(e.find_context("status"), Some(("404", 1)));
+    /// assert_eq!(e.find_attachment::<u16>(), Some((&404, 1)));
+    /// assert_eq!(e.find_context("tenant"), None);
+    /// ```
+    ///
+    pub fn find_context(&self, key: &str) -> Option<(&str, usize)> {
+        self.chain().enumerate().find_map(|(level, e)| {
+            e.downcast_ref::<Error>().and_then(|e| e.metadata.rendered(key)).map(|v| (v, level))
+        })
+    }
+
+    /// Returns the first value of type `T` attached to the outermost error in the
+    /// chain having one, under any key, and the level of that error.
+    ///
+    /// See `find_context` for the level.
+    ///
+    pub fn find_attachment<T: any::Any>(&self) -> Option<(&T, usize)> {
+        self.chain().enumerate().find_map(|(level, e)| {
+            e.downcast_ref::<Error>().and_then(|e| e.metadata.find::<T>()).map(|v| (v, level))
+        })
+    }
+
+    /// Returns the `Display` output of `self` with secrets written in place of `***`.
+    ///
+    /// The output contains whatever was attached with `with_secret` anywhere in
+    /// the chain, so never log it or send it anywhere: it is meant for local
+    /// debugging only.
+    ///
+    pub fn render_unredacted(&self) -> String {
+        render::unredacted(|| self.to_string())
+    }
+
+    /// Returns the message of `self`, or its context message for `ErrorKind::Context`.
+    ///
+    /// Returns `None` for `ErrorKind::Wrapped`, whose message is the inner error.
+    ///
+    pub fn message(&self) -> Option<&str> {
+        match self.kind {
+            ErrorKind::String(ref message)
+            | ErrorKind::Cancelled(ref message)
+            | ErrorKind::Context(ref message, _) => Some(message),
+            ErrorKind::Wrapped(_) => None,
+        }
+    }
+
+    /// Returns an iterator over `self` and its chain of inner errors, outermost first.
+    ///
+    /// The chain follows `source` of each error, so it also continues through
+    /// errors which are not `Error`s.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mm_errors::Error;
+    ///
+    /// let inner = "XXX".parse::<u32>().unwrap_err();
+    /// let e = Error::wrap(Error::wrap(inner, "a.rs", 1), "b.rs", 2);
+    ///
+    /// assert_eq!(e.chain().count(), 3);
+    /// ```
+    ///
+    pub fn chain(&self) -> Chain<'_> {
+        Chain { next: Some(self) }
+    }
+
+    /// Returns the innermost error of the chain.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::num::ParseIntError;
+    ///
+    /// use mm_errors::Error;
+    ///
+    /// let inner = "XXX".parse::<u32>().unwrap_err();
+    /// let e = Error::wrap(Error::wrap(inner, "a.rs", 1), "b.rs", 2);
+    ///
+    /// assert!(e.root_cause().downcast_ref::<ParseIntError>().is_some());
+    /// ```
+    ///
+    pub fn root_cause(&self) -> &(dyn error::Error + 'static) {
+        self.chain().last().unwrap_or(self)
+    }
+
+    /// Returns the backtrace captured when the innermost `Error` of the chain was created.
+    ///
+    /// Backtraces are captured only if enabled by the `RUST_BACKTRACE` or
+    /// `RUST_LIB_BACKTRACE` environment variables, as by
+    /// `std::backtrace::Backtrace::capture`, and only for errors not wrapping
+    /// an `Error` which has one already. They are not written to the output
+    /// formats.
+    ///
+    pub fn backtrace(&self) -> Option<&Backtrace> {
+        self.chain()
+            .filter_map(|e| e.downcast_ref::<Error>())
+            .find_map(|e| e.metadata.backtrace())
+    }
+
+    /// Marks `self` and its inner errors as handled, so they are not warned
+    /// about when dropped without being reported.
+    ///
+    /// This has an effect only if `hooks::set_must_report` is enabled.
+    ///
+    pub fn mark_handled(&self) {
+        self.mark_reported();
+    }
+
+    /// Records that `self` and its inner errors were reported, for `hooks::set_must_report`.
+    pub(crate) fn mark_reported(&self) {
+        for e in self.chain() {
+            if let Some(e) = e.downcast_ref::<Error>() {
+                e.metadata.report();
+            } else if let Some(errors) = e.downcast_ref::<MultiError>() {
+                errors.mark_reported();
+            }
+        }
+    }
+
+    /// Returns a new instance of `Error` located at the caller.
+    ///
+    /// Unlike `new_error!`, the location is captured with `#[track_caller]`,
+    /// so functions marked `#[track_caller]` themselves pass their caller's
+    /// location through.
+    ///
+    /// # Arguments
+    ///
+    /// * message - Error message.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mm_errors::Error;
+    ///
+    /// #[track_caller]
+    /// fn fail() -> Error {
+    ///     Error::here("failed")
+    /// }
+    ///
+    /// let e = fail();
+    /// assert_eq!(e.line, line!() - 1);
+    /// ```
+    ///
+    #[track_caller]
+    pub fn here(message: &str) -> Error {
+        let location = panic::Location::caller();
+        Error::new(message, location.file(), location.line())
+    }
+
+    /// Returns a new instance of `Error` holding `e` as inner error, located at the caller.
+    ///
+    /// See `Error::here` for how the location is captured.
+    ///
+    /// # Arguments
+    ///
+    /// * e - Inner error.
+    ///
+    #[track_caller]
+    pub fn wrap_here<T>(e: T) -> Error
+        where T: Into<Box<dyn error::Error + marker::Send + marker::Sync>> {
+        let location = panic::Location::caller();
+        Error::wrap(e, location.file(), location.line())
+    }
+
+    /// Returns `self` with its location replaced.
+    ///
+    /// Code generated by macros can use this to report the location of the
+    /// user's call site instead of the location inside the macro.
+    ///
+    /// # Arguments
+    ///
+    /// * file - File where error occurred.
+    /// * line - Line number where error occurred.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # #[macro_use] extern crate mm_errors;
+    ///
+    /// use mm_errors::Error;
+    ///
+    /// macro_rules! checked {
+    ///     ($cond:expr, $file:expr, $line:expr) => ({
+    ///         if $cond { Ok(()) } else { Err(new_error!("check failed").at($file, $line)) }
+    ///     })
+    /// }
+    ///
+    /// # fn main() {
+    ///     let r: Result<(), Error> = checked!(false, "user.rs", 42);
+    ///     let e = r.unwrap_err();
+    ///     assert_eq!(e.file, "user.rs");
+    ///     assert_eq!(e.line, 42);
+    /// # }
+    /// ```
+    ///
+    pub fn at(mut self, file: &'static str, line: u32) -> Error {
+        self.file = Cow::Borrowed(file);
+        self.line = line;
+        self
+    }
+
+    /// Parses the XML written by `Display` back into an `Error`.
+    ///
+    /// Every level of the chain becomes an `Error` holding the original file and line.
+    /// Reasons of errors which were not `Error`s are restored as text,
+    /// so their types are not restored.
+    /// Whitespace between elements is ignored.
+    ///
+    /// # Arguments
+    ///
+    /// * s - XML written by `Display`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `s` is not well-formed, its schema version is not
+    /// supported, or it is nested too deeply.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mm_errors::{Error, ErrorKind};
+    ///
+    /// let sent = Error::wrap(Error::new("disk full", "storage.rs", 7), "service.rs", 21);
+    ///
+    /// let received = Error::from_xml(&sent.to_string()).unwrap();
+    /// assert_eq!(received.to_string(), sent.to_string());
+    ///
+    /// // Re-propagate the remote error with a local location.
+    /// let local = Error::wrap(received, "client.rs", 3);
+    /// match local.kind {
+    ///     ErrorKind::Wrapped(ref e) => assert!(e.downcast_ref::<Error>().is_some()),
+    ///     _ => panic!("the error should be wrapped"),
+    /// }
+    ///
+    /// // Untrusted input can't overflow the stack.
+    /// let deep = "<error><file>a.rs</file><line>1</line><reason>".repeat(200_000);
+    /// assert!(Error::from_xml(&deep).is_err());
+    /// ```
+    ///
+    pub fn from_xml(s: &str) -> Result<Error> {
+        xml::parse(s)
+    }
+
+    /// Returns `self` as JSON.
+    ///
+    /// Each level of the chain is an object with the members of the elements
+    /// described in [the module level document], in the same order:
+    /// `file`, `line`, `reason`, then `context`, `cancelled`, `code`, `meta`
+    /// and `io` if present. `reason` is a string, an object for an inner
+    /// `Error`, or an array of objects for an inner `MultiError`. `meta` is an
+    /// array of `[key, value]` pairs. The outermost object starts with a
+    /// `schema` member holding `SCHEMA_VERSION` and a `version` member holding
+    /// `CRATE_VERSION`.
+    ///
+    /// The output contains no whitespace. Unlike `Display`, control characters
+    /// are always escaped as JSON requires.
+    ///
+    /// # Round trips
+    ///
+    /// Parsing the output with `from_json` and writing the result again yields
+    /// exactly the same bytes, for any chain of errors with any codes,
+    /// contexts, metadata and `MultiError`s, so the output can be stored and
+    /// forwarded safely. Values of the metadata are kept as they were
+    /// rendered, and errors which were not `Error`s as their text. This
+    /// requires the same `render` settings when writing both times, except
+    /// that `render::set_path_root` may shorten file paths once more.
+    ///
+    /// [the module level document]: index.html#output-format
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mm_errors::Error;
+    ///
+    /// let e = Error::context(Error::new("disk full", "storage.rs", 7), "saving", "service.rs", 21)
+    ///     .with_code("STO001");
+    /// assert_eq!(
+    ///     e.to_json(),
+    ///     format!(
+    ///         "{{\"schema\":2,\"version\":\"{}\",\"file\":\"service.rs\",\"line\":21,\
+    ///          \"reason\":{{\"file\":\"storage.rs\",\"line\":7,\"reason\":\"disk full\"}},\
+    ///          \"context\":\"saving\",\"code\":\"STO001\"}}",
+    ///         mm_errors::CRATE_VERSION));
+    ///
+    /// // Errors stored by older releases remain readable.
+    /// let stored = "{\"schema\":1,\"file\":\"service.rs\",\"line\":21,\"reason\":\"disk full\"}";
+    /// assert_eq!(Error::from_json(stored).unwrap().line, 21);
+    /// ```
+    ///
+    pub fn to_json(&self) -> String {
+        let mut out = String::new();
+        json::write_error(&mut out, self, true).expect("writing to a `String` never fails");
+        out
+    }
+
+    /// Parses the JSON written by `to_json` back into an `Error`.
+    ///
+    /// As with `from_xml`, every level of the chain becomes an `Error` holding
+    /// the original file and line, and reasons of errors which were not
+    /// `Error`s are restored as text. Whitespace between values is ignored.
+    ///
+    /// # Arguments
+    ///
+    /// * s - JSON written by `to_json`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `s` is not valid JSON in that format, or its schema
+    /// version is not supported.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mm_errors::Error;
+    ///
+    /// let sent = Error::wrap(Error::new("disk full", "storage.rs", 7), "service.rs", 21);
+    ///
+    /// let received = Error::from_json(&sent.to_json()).unwrap();
+    /// assert_eq!(received.to_string(), sent.to_string());
+    ///
+    /// // Re-propagate the remote error with a local location.
+    /// let local = Error::wrap(received, "client.rs", 3);
+    /// assert_eq!(local.chain().count(), 3);
+    /// ```
+    ///
+    pub fn from_json(s: &str) -> Result<Error> {
+        json::parse(s)
+    }
+
+    /// Writes `self` as an `<error>` element.
+    ///
+    /// Nested `Error`s are written as child `<error>` elements instead of
+    /// being flattened into the text of `<reason>`.
+    fn format_xml(&self, f: &mut fmt::Formatter, root: bool) -> fmt::Result {
+        xml_open(f, &self.file, self.line, root)?;
+        match self.kind {
+            ErrorKind::String(ref s) | ErrorKind::Cancelled(ref s) => {
+                write!(XmlText(f), "{}", s)?;
+            },
+            ErrorKind::Wrapped(ref e) | ErrorKind::Context(_, ref e) => xml_reason(f, &**e)?,
+        }
+        xml_close(f, Some(self))
+    }
+}
+
+/// Attaches the context of the registered providers to the newly created `e`,
+/// and records it for `testing::capture_errors`.
+#[cfg(feature = "std")]
+fn created(mut e: Error) -> Error {
+    metadata::provide(&mut e.metadata);
+    if e.backtrace().is_none() {
+        let backtrace = Backtrace::capture();
+        if backtrace.status() == BacktraceStatus::Captured {
+            e.metadata.set_backtrace(backtrace);
+        }
+    }
+    if hooks::is_must_report() {
+        let description = format!("{} ({}:{})", report::first_message(&e), e.file, e.line);
+        e.metadata.guard(description);
+    }
+    testing::created(e)
+}
+
+/// Writes the start of an `<error>` element up to the opening `<reason>` tag.
+///
+/// The outermost element carries the schema version.
+#[cfg(feature = "std")]
+fn xml_open(f: &mut fmt::Formatter, file: &str, line: u32, root: bool) -> fmt::Result {
+    let (file, line) = render::location(file, line);
+    if root {
+        write!(f, "<error schema=\"{}\" version=\"{}\">", SCHEMA_VERSION, render::crate_version())?;
+    } else {
+        write!(f, "<error>")?;
+    }
+    write!(f, "<file>")?;
+    write!(XmlText(f), "{}", file)?;
+    write!(f, "</file>")?;
+    write!(f, "<line>{}", line)?;
+    write!(f, "</line>")?;
+    write!(f, "<reason>")
+}
+
+/// Writes an inner error as the content of `<reason>`.
+#[cfg(feature = "std")]
+fn xml_reason(f: &mut fmt::Formatter, e: &(dyn error::Error + 'static)) -> fmt::Result {
+    if let Some(inner) = e.downcast_ref::<Error>() {
+        inner.format_xml(f, false)
+    } else if let Some(inner) = e.downcast_ref::<LocalError>() {
+        inner.format_xml(f, false)
+    } else if let Some(inner) = e.downcast_ref::<MultiError>() {
+        inner.format_xml(f, false)
+    } else if let Some(inner) = e.downcast_ref::<RemoteError>() {
+        inner.format_xml(f, false)
+    } else {
+        write!(XmlText(f), "{}", e)
+    }
 }
 
-impl Clone for ErrorKind {
-    fn clone(&self) -> Self {
-        match self {
-            &ErrorKind::String(ref s) => ErrorKind::String(s.clone()),
-            &ErrorKind::Wrapped(ref e) => ErrorKind::String(format!("{}", e)),
+/// Writes the end of an `<error>` element from the closing `</reason>` tag.
+///
+/// The elements following `<reason>` are written from `e`, if any.
+#[cfg(feature = "std")]
+fn xml_close(f: &mut fmt::Formatter, e: Option<&Error>) -> fmt::Result {
+    write!(f, "</reason>")?;
+    if let Some(e) = e {
+        if let ErrorKind::Context(ref context, _) = e.kind {
+            write!(f, "<context>")?;
+            write!(XmlText(f), "{}", context)?;
+            write!(f, "</context>")?;
+        }
+        if let ErrorKind::Cancelled(..) = e.kind {
+            write!(f, "<cancelled/>")?;
+        }
+        if let Some(ref code) = e.code {
+            write!(f, "<code>")?;
+            write!(XmlText(f), "{}", code)?;
+            write!(f, "</code>")?;
+        }
+        for (key, value) in e.metadata.iter_rendered(render::is_unredacted()) {
+            if key == metadata::IO {
+                continue;
+            }
+            write!(f, "<meta key=\"")?;
+            write!(XmlText(f), "{}", key)?;
+            write!(f, "\">")?;
+            write!(XmlText(f), "{}", value)?;
+            write!(f, "</meta>")?;
+        }
+        if let Some(io) = e.metadata.get_keyed::<io::IoContext>(metadata::IO) {
+            write!(f, "<io><operation>")?;
+            write!(XmlText(f), "{}", io.operation)?;
+            write!(f, "</operation><path>")?;
+            write!(XmlText(f), "{}", io.path.display())?;
+            write!(f, "</path>")?;
+            if let Some(bytes) = io.bytes {
+                write!(f, "<bytes>{}</bytes>", bytes)?;
+            }
+            write!(f, "</io>")?;
         }
     }
+    write!(f, "</error>")
 }
 
-impl fmt::Debug for Error {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}", self)
-    }
+/// Iterator over an error and its chain of inner errors.
+///
+/// This is returned by `Error::chain`.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone)]
+pub struct Chain<'a> {
+    next: Option<&'a (dyn error::Error + 'static)>,
 }
 
+#[cfg(feature = "std")]
+impl<'a> Iterator for Chain<'a> {
+    type Item = &'a (dyn error::Error + 'static);
 
-impl Error {
-    /// Returns a new instance of `Error`.
-    ///
-    /// # Arguments
-    ///
-    /// * message - Error message.
-    /// * file - File where error occurred.
-    /// * line - Line number where error occurred.
-    ///
-    pub fn new(message: &str, file: &'static str, line: u32) -> Error {
-        Error {
-            file,
-            line,
-            kind: ErrorKind::String(message.to_string()),
-        }
+    fn next(&mut self) -> Option<Self::Item> {
+        let current = self.next?;
+        self.next = current.source();
+        Some(current)
     }
+}
 
-    /// Returns a new instance of `Error`
-    ///
-    /// The return value holds `e` as inner error.
-    ///
-    /// # Arguments
-    ///
-    /// * e - Inner error.
-    /// * file - File where error occurred.
-    /// * line - Line number where error occurred.
-    ///
-    pub fn wrap<T>(e: T, file: &'static str, line: u32) -> Error
-        where T: Into<Box<error::Error + marker::Send + marker::Sync>> {
-        Error {
-            file,
-            line,
-            kind: ErrorKind::Wrapped(e.into()),
-        }
-    }
+/// Escapes text written through it so it can be embedded in XML.
+///
+/// Control characters are handled as configured by `render::set_control_chars`.
+#[cfg(feature = "std")]
+struct XmlText<'a, 'b: 'a>(&'a mut fmt::Formatter<'b>);
 
-    fn format_xml(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "<error>")?;
-        write!(f, "<file>{}", self.file)?;
-        write!(f, "</file>")?;
-        write!(f, "<line>{}", self.line)?;
-        write!(f, "</line>")?;
-        match self.kind {
-            ErrorKind::String(ref s) => {
-                write!(f, "<reason>{}", s)?;
-                write!(f, "</reason>")?;
-            },
-            ErrorKind::Wrapped(ref e) => {
-                write!(f, "<reason>{}", e)?;
-                write!(f, "</reason>")?;
+#[cfg(feature = "std")]
+impl<'a, 'b> fmt::Write for XmlText<'a, 'b> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let mut start = 0;
+        for (i, c) in s.char_indices() {
+            if c.is_control() {
+                self.0.write_str(&s[start..i])?;
+                render::write_control(self.0, c)?;
+            } else {
+                let escaped = match c {
+                    '&' => "&amp;",
+                    '<' => "&lt;",
+                    '>' => "&gt;",
+                    '"' => "&quot;",
+                    '\'' => "&apos;",
+                    _ => continue,
+                };
+                self.0.write_str(&s[start..i])?;
+                self.0.write_str(escaped)?;
             }
+            start = i + c.len_utf8();
         }
-        write!(f, "</error>")
+        self.0.write_str(&s[start..])
     }
 }
 
+#[cfg(feature = "std")]
 impl error::Error for Error {
     fn description(&self) -> &str {
         "font processing error"
     }
 
-    fn cause(&self) -> Option<&error::Error> {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
         match self.kind {
-            ErrorKind::String(..) => None,
-            ErrorKind::Wrapped(ref e) => e.cause(),
+            ErrorKind::String(..) | ErrorKind::Cancelled(..) => None,
+            ErrorKind::Wrapped(ref e) | ErrorKind::Context(_, ref e) => Some(&**e),
         }
     }
 }
 
+#[cfg(feature = "std")]
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        self.format_xml(f)
+        self.mark_reported();
+        self.format_xml(f, true)
     }
 }
 
@@ -305,7 +1464,7 @@ impl fmt::Display for Error {
 ///
 ///     match return_err() {
 ///         Err(e) => match e {
-///             Error{file, line, kind} => {
+///             Error{file, line, kind, ..} => {
 ///                 println!("{}", file);
 ///                 println!("{}", line);
 ///                 println!("{:?}", kind);
@@ -317,6 +1476,7 @@ impl fmt::Display for Error {
 /// # }
 /// ```
 ///
+#[cfg(feature = "std")]
 #[macro_export]
 macro_rules! try_wrap {
     ($exp:expr) => ({
@@ -327,6 +1487,157 @@ macro_rules! try_wrap {
     })
 }
 
+/// Similar to `try_wrap!` macro, but this awaits the future given as the argument.
+///
+/// Like `try_wrap!`, this returns early on error. Note that inside an `async`
+/// block `return` leaves the block, not the enclosing function, so the block
+/// evaluates to the `Err`. Use `wrap_result!` with `?` where an explicit
+/// result is clearer.
+///
+/// # Examples
+///
+/// ```
+/// # #[macro_use] extern crate mm_errors;
+///
+/// use mm_errors::Error;
+///
+/// async fn parse(s: &str) -> Result<u32, std::num::ParseIntError> {
+///     s.parse::<u32>()
+/// }
+///
+/// async fn level1() -> Result<u32, Error> {
+///     let v = try_wrap_async!(parse("XXX"));
+///     Ok(v)
+/// }
+///
+/// # fn main() {
+///     let task = async {
+///         let v = try_wrap_async!(level1());
+///         Ok::<u32, Error>(v)
+///     };
+///     assert!(futures::executor::block_on(task).is_err());
+/// # }
+/// ```
+///
+#[cfg(feature = "std")]
+#[macro_export]
+macro_rules! try_wrap_async {
+    ($exp:expr) => ({
+        match $exp.await {
+            Ok(x) => x,
+            Err(e) => return Err($crate::Error::wrap(e, file!(), line!())),
+        }
+    })
+}
+
+/// Similar to `try_wrap!` macro, but this also marks the returned error as transient.
+///
+/// Use this where the failure is known to be temporary, e.g. a lock timeout.
+/// See `Error::mark_transient`.
+///
+/// # Examples
+///
+/// ```
+/// # #[macro_use] extern crate mm_errors;
+///
+/// use std::io;
+///
+/// use mm_errors::Error;
+///
+/// fn acquire() -> Result<(), Error> {
+///     try_wrap_transient!(Err::<(), _>(io::Error::new(io::ErrorKind::Other, "lock is busy")));
+///     Ok(())
+/// }
+///
+/// # fn main() {
+///     let e = Error::wrap(acquire().unwrap_err(), "service.rs", 1);
+///     assert!(e.is_retryable());
+/// # }
+/// ```
+///
+#[cfg(feature = "std")]
+#[macro_export]
+macro_rules! try_wrap_transient {
+    ($exp:expr) => ({
+        match $exp {
+            Ok(x) => x,
+            Err(e) => return Err($crate::Error::wrap(e, file!(), line!()).mark_transient()),
+        }
+    })
+}
+
+/// Similar to `try_wrap!` macro, but this also marks the returned error as permanent.
+///
+/// Use this where retrying is known to be useless, e.g. for invalid input.
+/// See `Error::mark_permanent`.
+///
+/// # Examples
+///
+/// ```
+/// # #[macro_use] extern crate mm_errors;
+///
+/// use std::io;
+///
+/// use mm_errors::Error;
+///
+/// fn open() -> Result<(), Error> {
+///     try_wrap_permanent!(Err::<(), _>(io::Error::from(io::ErrorKind::TimedOut)));
+///     Ok(())
+/// }
+///
+/// # fn main() {
+///     // The marker takes precedence over the kind of the I/O error.
+///     assert!(!open().unwrap_err().is_retryable());
+/// # }
+/// ```
+///
+#[cfg(feature = "std")]
+#[macro_export]
+macro_rules! try_wrap_permanent {
+    ($exp:expr) => ({
+        match $exp {
+            Ok(x) => x,
+            Err(e) => return Err($crate::Error::wrap(e, file!(), line!()).mark_permanent()),
+        }
+    })
+}
+
+/// Converts the error of the `Result` given as the argument into an `Error` wrapping it.
+///
+/// Unlike `try_wrap!`, this does not return early; it evaluates to a `Result`,
+/// so it can be combined with `?`, `.await`, or any combinator.
+///
+/// # Examples
+///
+/// ```
+/// # #[macro_use] extern crate mm_errors;
+///
+/// use mm_errors::Error;
+///
+/// async fn parse(s: &str) -> Result<u32, std::num::ParseIntError> {
+///     s.parse::<u32>()
+/// }
+///
+/// # fn main() {
+///     let task = async {
+///         let v = wrap_result!(parse("XXX").await)?;
+///         Ok::<u32, Error>(v)
+///     };
+///     assert!(futures::executor::block_on(task).is_err());
+/// # }
+/// ```
+///
+#[cfg(feature = "std")]
+#[macro_export]
+macro_rules! wrap_result {
+    ($exp:expr) => ({
+        match $exp {
+            Ok(x) => Ok(x),
+            Err(e) => Err($crate::Error::wrap(e, file!(), line!())),
+        }
+    })
+}
+
 ///
 /// # Examples
 ///
@@ -344,6 +1655,7 @@ macro_rules! try_wrap {
 /// # }
 /// ```
 ///
+#[cfg(feature = "std")]
 #[macro_export]
 macro_rules! wrap_error {
     ($exp:expr) => ({
@@ -352,6 +1664,7 @@ macro_rules! wrap_error {
 }
 
 /// Returns a new instance of `Error`.
+#[cfg(feature = "std")]
 #[macro_export]
 macro_rules! new_error {
     ($message:expr) => ({
@@ -377,13 +1690,13 @@ macro_rules! new_error {
 /// # fn main() {
 ///     match return_error() {
 ///         Err(e) => match e {
-///             Error{file, line, kind} => match kind {
+///             Error{file, line, kind, ..} => match kind {
 ///                 ErrorKind::String(s) => {
 ///                     println!("{}", file);
 ///                     println!("{}", line);
 ///                     println!("{}", s);
 ///                 },
-///                 ErrorKind::Wrapped(_) => (),
+///                 _ => (),
 ///             },
 ///         },
 ///         Ok(_) => panic!("The function never success"),
@@ -392,6 +1705,7 @@ macro_rules! new_error {
 ///
 /// ```
 ///
+#[cfg(feature = "std")]
 #[macro_export]
 macro_rules! new_result {
     ($message:expr) => ({
@@ -418,13 +1732,13 @@ macro_rules! new_result {
 /// # fn main() {
 ///     match return_none() {
 ///         Err(e) => match e {
-///             Error{file, line, kind} => match kind {
+///             Error{file, line, kind, ..} => match kind {
 ///                 ErrorKind::String(s) => {
 ///                     println!("{}", file);
 ///                     println!("{}", line);
 ///                     println!("{}", s);
 ///                 },
-///                 ErrorKind::Wrapped(_) => (),
+///                 _ => (),
 ///             },
 ///         },
 ///         Ok(_) => panic!("The function never success"),
@@ -433,6 +1747,7 @@ macro_rules! new_result {
 ///
 /// ```
 ///
+#[cfg(feature = "std")]
 #[macro_export]
 macro_rules! try_opt {
     ($exp:expr, $message:expr) => ({
@@ -443,6 +1758,7 @@ macro_rules! try_opt {
     })
 }
 
+#[cfg(feature = "std")]
 #[macro_export]
 macro_rules! try_opt_ref {
     ($exp:expr, $message:expr) => ({
@@ -453,5 +1769,211 @@ macro_rules! try_opt_ref {
     })
 }
 
+/// Asserts that the `Result` given as the argument is `Err`, and evaluates to the error.
+///
+/// # Panics
+///
+/// Panics with the value if the result is `Ok`.
+///
+/// # Examples
+///
+/// ```
+/// # #[macro_use] extern crate mm_errors;
+///
+/// use mm_errors::Error;
+///
+/// # fn main() {
+///     let res: Result<u32, Error> = new_result!("Stack underflow!");
+///     let e = assert_err!(res);
+///     assert_eq!(e.line, line!() - 2);
+/// # }
+/// ```
+///
+#[cfg(feature = "std")]
+#[macro_export]
+macro_rules! assert_err {
+    ($exp:expr) => ({
+        match $exp {
+            Err(e) => e,
+            Ok(x) => panic!("assertion failed: expected `Err`, got `Ok({:?})`", x),
+        }
+    })
+}
+
+/// Asserts that the `Result` given as the argument is `Err` and its `ErrorKind` matches the pattern.
+///
+/// # Panics
+///
+/// Panics with the error if it does not match, and with the value if the result is `Ok`.
+///
+/// # Examples
+///
+/// ```
+/// # #[macro_use] extern crate mm_errors;
+///
+/// use mm_errors::{Error, ErrorKind};
+///
+/// # fn main() {
+///     let res: Result<u32, Error> = new_result!("Stack underflow!");
+///     assert_err_matches!(res, ErrorKind::String(s) if s.contains("underflow"));
+/// # }
+/// ```
+///
+#[cfg(feature = "std")]
+#[macro_export]
+macro_rules! assert_err_matches {
+    ($exp:expr, $pat:pat $(if $guard:expr)?) => ({
+        match $exp {
+            Err(ref e) => match &e.kind {
+                $pat $(if $guard)? => (),
+                _ => panic!("assertion failed: error kind does not match `{}`: {:?}",
+                            stringify!($pat $(if $guard)?), e),
+            },
+            Ok(ref x) => panic!("assertion failed: expected `Err`, got `Ok({:?})`", x),
+        }
+    })
+}
+
+/// Asserts that the `Result` given as the argument is `Err` with a root cause of the given type.
+///
+/// # Panics
+///
+/// Panics with the error if the root cause has another type, and with the value if the result is `Ok`.
+///
+/// # Examples
+///
+/// ```
+/// # #[macro_use] extern crate mm_errors;
+///
+/// use std::num::ParseIntError;
+///
+/// use mm_errors::Error;
+///
+/// fn parse() -> Result<u32, Error> {
+///     Ok(try_wrap!("XXX".parse::<u32>()))
+/// }
+///
+/// # fn main() {
+///     assert_root_cause!(parse(), ParseIntError);
+/// # }
+/// ```
+///
+#[cfg(feature = "std")]
+#[macro_export]
+macro_rules! assert_root_cause {
+    ($exp:expr, $ty:ty) => ({
+        match $exp {
+            Err(ref e) => if e.root_cause().downcast_ref::<$ty>().is_none() {
+                panic!("assertion failed: root cause is not `{}`: {:?}", stringify!($ty), e);
+            },
+            Ok(ref x) => panic!("assertion failed: expected `Err`, got `Ok({:?})`", x),
+        }
+    })
+}
+
+/// Returns a new instance of `embedded::CoreError`.
+///
+/// The file and line are where the macro is called. This is available
+/// without the `std` feature.
+///
+/// # Arguments
+///
+/// * code - Error code, if any.
+/// * message - Error message.
+///
+/// # Examples
+///
+/// ```
+/// # #[macro_use] extern crate mm_errors;
+///
+/// # fn main() {
+/// let e = core_error!("sensor not responding");
+/// assert_eq!(e.code, None);
+///
+/// let e = core_error!("SNS001", "sensor not responding");
+/// assert_eq!(e.code, Some("SNS001"));
+/// # }
+/// ```
+///
+#[macro_export]
+macro_rules! core_error {
+    ($message:expr) => ({
+        $crate::embedded::CoreError::new($message, file!(), line!())
+    });
+    ($code:expr, $message:expr) => ({
+        $crate::embedded::CoreError::new($message, file!(), line!()).with_code($code)
+    });
+}
+
+/// Returns a new instance of `embedded::CompactError` with the number of a code.
+///
+/// Without `location`, the location is the line where the macro is called,
+/// which the host decodes without a file unless given to
+/// `embedded::Decoder::location`. Lines which don't fit in a `u16` fail to
+/// compile. This is available without the `std` feature.
+///
+/// # Arguments
+///
+/// * code - Number of the error code.
+/// * location - Index of the location, if not the line.
+///
+/// # Examples
+///
+/// ```
+/// # #[macro_use] extern crate mm_errors;
+///
+/// # fn main() {
+/// let e = compact_error!(17);
+/// assert_eq!(e.code, 17);
+/// assert_eq!(e.location, line!() as u16 - 2);
+///
+/// let e = compact_error!(17, 3);
+/// assert_eq!(e.location, 3);
+/// # }
+/// ```
+///
+#[macro_export]
+macro_rules! compact_error {
+    ($code:expr) => ({
+        const LINE: u32 = line!();
+        const _: () = assert!(LINE <= u16::MAX as u32, "the line doesn't fit in a location index");
+        $crate::embedded::CompactError::new($code, LINE as u16)
+    });
+    ($code:expr, $location:expr) => ({
+        $crate::embedded::CompactError::new($code, $location)
+    });
+}
+
+/// Version of the output format.
+///
+/// It is written as the `schema` attribute of the outermost `<error>` element
+/// and is incremented whenever the format changes incompatibly.
+/// See [the module level document] for the format.
+///
+/// [the module level document]: index.html#output-format
+///
+pub const SCHEMA_VERSION: u32 = 2;
+
+/// Oldest version of the output format which can still be parsed.
+///
+/// `Error::from_xml`, `Error::from_json` and the other parsers accept every
+/// version from this one up to `SCHEMA_VERSION`.
+///
+pub const MIN_SCHEMA_VERSION: u32 = 1;
+
+/// Version of this crate.
+///
+/// It is written with the schema version of serialized errors, to tell which
+/// release produced an error read back from storage.
+///
+pub const CRATE_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Returns `true` if the parsers support the schema version `version`.
+#[cfg(feature = "std")]
+fn is_supported_schema(version: u32) -> bool {
+    (MIN_SCHEMA_VERSION..=SCHEMA_VERSION).contains(&version)
+}
+
 /// Alias for `Result`.
+#[cfg(feature = "std")]
 pub type Result<T> = result::Result<T, Error>;
@@ -0,0 +1,140 @@
+//! Error type for inner errors which are not thread-safe.
+
+use std::error;
+use std::fmt;
+
+use super::{xml_close, xml_open, xml_reason, Error, ErrorKind, Metadata};
+
+/// Holds error information with an inner error which is not `Send` or `Sync`.
+///
+/// `Error` can only wrap errors which are `Send + Sync`, so errors holding
+/// e.g. an `Rc` cannot be wrapped by it. `LocalError` accepts any inner error,
+/// but for that reason it is neither `Send` nor `Sync` itself and cannot be
+/// moved to another thread.
+///
+/// Convert it into an `Error` with `From` once the error has to cross a thread
+/// boundary. The inner error is kept if it is an `Error` and rendered into a
+/// message otherwise.
+///
+/// # Examples
+///
+/// ```
+/// # #[macro_use] extern crate mm_errors;
+///
+/// use std::error;
+/// use std::fmt;
+/// use std::rc::Rc;
+/// use std::result::Result;
+///
+/// use mm_errors::{Error, LocalError};
+///
+/// #[derive(Debug)]
+/// struct RcError(Rc<String>);
+///
+/// impl fmt::Display for RcError {
+///     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+///         write!(f, "{}", self.0)
+///     }
+/// }
+///
+/// impl error::Error for RcError {}
+///
+/// fn return_local() -> Result<(), LocalError> {
+///     let r: Result<(), RcError> = Err(RcError(Rc::new("not thread-safe".to_string())));
+///     try_wrap_local!(r);
+///     Ok(())
+/// }
+///
+/// # fn main() {
+///     let e = Error::from(return_local().unwrap_err());
+///     assert!(e.to_string().contains("<reason>not thread-safe</reason>"));
+/// # }
+/// ```
+///
+#[derive(Debug)]
+pub struct LocalError {
+    /// File where error occurred.
+    pub file: &'static str,
+
+    /// line number where error occurred.
+    pub line: u32,
+
+    /// Inner error.
+    pub inner: Box<dyn error::Error>,
+}
+
+impl LocalError {
+    /// Returns a new instance of `LocalError`
+    ///
+    /// The return value holds `e` as inner error.
+    ///
+    /// # Arguments
+    ///
+    /// * e - Inner error.
+    /// * file - File where error occurred.
+    /// * line - Line number where error occurred.
+    ///
+    pub fn wrap<T>(e: T, file: &'static str, line: u32) -> LocalError
+        where T: Into<Box<dyn error::Error>> {
+        LocalError {
+            file,
+            line,
+            inner: e.into(),
+        }
+    }
+
+    pub(crate) fn format_xml(&self, f: &mut fmt::Formatter, root: bool) -> fmt::Result {
+        xml_open(f, self.file, self.line, root)?;
+        xml_reason(f, &*self.inner)?;
+        xml_close(f, None)
+    }
+}
+
+impl error::Error for LocalError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        Some(&*self.inner)
+    }
+}
+
+impl fmt::Display for LocalError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.format_xml(f, true)
+    }
+}
+
+impl From<LocalError> for Error {
+    fn from(e: LocalError) -> Error {
+        let kind = match e.inner.downcast::<Error>() {
+            Ok(inner) => ErrorKind::Wrapped(inner),
+            Err(inner) => match inner.downcast::<LocalError>() {
+                Ok(inner) => ErrorKind::Wrapped(Box::new(Error::from(*inner))),
+                Err(inner) => ErrorKind::String(inner.to_string()),
+            },
+        };
+        Error {
+            file: e.file.into(),
+            line: e.line,
+            kind,
+            code: None,
+            metadata: Metadata::new(),
+        }
+    }
+}
+
+/// Similar to `try_wrap!` macro, but this returns a `LocalError` instance.
+///
+/// Use this when the internal error is not `Send` or `Sync`.
+///
+/// See [`LocalError`] for an example.
+///
+/// [`LocalError`]: struct.LocalError.html
+///
+#[macro_export]
+macro_rules! try_wrap_local {
+    ($exp:expr) => ({
+        match $exp {
+            Ok(x) => x,
+            Err(e) => return Err($crate::LocalError::wrap(e, file!(), line!())),
+        }
+    })
+}
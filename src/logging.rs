@@ -0,0 +1,254 @@
+//! Logging errors with the `log` crate.
+//!
+//! Records are written at the level of the severity of the error, with the
+//! module the error comes from as the target, so the per-module filters of
+//! the logger apply to errors as they do to other records. The target is
+//! `Error::module_path`, or `TARGET` for errors from the root module of a
+//! crate. Note that module paths derived from files are relative to their
+//! crate, e.g. `storage` rather than `myapp::storage`, while namespaces of
+//! codes created by `error_code!` are full paths. Records carry the file and
+//! line of the outermost error as rendered by `Display`.
+//!
+//! Without the `log-kv` feature, the message of a record is the `Display`
+//! output of the error. With it, the message is the first message in the
+//! chain, as in `Error::report`, and the details are attached as structured
+//! key-values instead:
+//!
+//! | Key | Value |
+//! |-----|-------|
+//! | `code` | Error code, only if the error has a code |
+//! | `file` | File of the outermost error |
+//! | `line` | Line of the outermost error |
+//! | `root_cause` | Message of the innermost error |
+//! | `sample_rate` | Sample rate, only if above 1 |
+//!
+//! Errors are filtered as set by `filter::set_filter`, and sampled as set by
+//! `rate::set_sampler`. Without the `log-kv` feature, the sample rate is
+//! appended to the message if it is above 1.
+//!
+//! This module is available with the `log` feature.
+//!
+//! # Examples
+//!
+//! ```
+//! use std::sync::Mutex;
+//!
+//! use log::{Log, Metadata, Record};
+//!
+//! use mm_errors::Error;
+//!
+//! /// Keeps the target, the message and the keys of the last record.
+//! struct Last(Mutex<(String, String, Vec<String>)>);
+//!
+//! impl Log for Last {
+//!     fn enabled(&self, _: &Metadata) -> bool {
+//!         true
+//!     }
+//!
+//!     fn log(&self, record: &Record) {
+//!         let keys = ["code", "file", "line", "root_cause"].iter()
+//!             .filter(|&&key| has_key(record, key))
+//!             .map(|key| key.to_string())
+//!             .collect();
+//!         *self.0.lock().unwrap() = (record.target().to_string(), record.args().to_string(), keys);
+//!     }
+//!
+//!     fn flush(&self) {}
+//! }
+//!
+//! # #[cfg(not(feature = "log-kv"))]
+//! # fn has_key(_: &Record, _: &str) -> bool { false }
+//! #[cfg(feature = "log-kv")]
+//! fn has_key(record: &Record, key: &str) -> bool {
+//!     record.key_values().get(log::kv::Key::from(key)).is_some()
+//! }
+//!
+//! static LAST: Last = Last(Mutex::new((String::new(), String::new(), Vec::new())));
+//! log::set_logger(&LAST).unwrap();
+//! log::set_max_level(log::LevelFilter::Warn);
+//!
+//! Error::new("disk full", "src/storage.rs", 7).with_code("STO001").log_err();
+//!
+//! let (target, message, keys) = LAST.0.lock().unwrap().clone();
+//! assert_eq!(target, "storage");
+//! if cfg!(feature = "log-kv") {
+//!     assert_eq!(message, "disk full");
+//!     assert_eq!(keys, ["code", "file", "line", "root_cause"]);
+//! } else {
+//!     assert!(message.contains("<code>STO001</code>"));
+//! }
+//! ```
+
+use std::error;
+use std::marker;
+use std::panic;
+use std::result;
+
+use log::{Level, Metadata, Record};
+
+use super::{filter, rate, render, retry, Error, Result, Severity};
+
+/// Target of the records of errors from the root module of a crate.
+pub const TARGET: &str = "mm_errors";
+
+impl Severity {
+    /// Returns the level at which errors of `self` are logged.
+    ///
+    /// `Info` is logged at the `Info` level, `Warning` at the `Warn` level,
+    /// and `Error` and `Critical` at the `Error` level.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mm_errors::Severity;
+    ///
+    /// assert_eq!(Severity::Warning.log_level(), log::Level::Warn);
+    /// assert_eq!(Severity::Critical.log_level(), log::Level::Error);
+    /// ```
+    ///
+    pub fn log_level(self) -> Level {
+        match self {
+            Severity::Info => Level::Info,
+            Severity::Warning => Level::Warn,
+            Severity::Error | Severity::Critical => Level::Error,
+        }
+    }
+}
+
+impl Error {
+    /// Writes `self` to the logger of the `log` crate at the level of its severity.
+    ///
+    /// The level is `Severity::log_level` of `Error::severity`, so changing
+    /// the severity of a code in the catalog changes how its errors are
+    /// logged everywhere. Use `log_err_at` to choose the level.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mm_errors::{Error, Severity};
+    ///
+    /// // Written to the logger installed by the application, e.g. `env_logger`.
+    /// Error::new("disk full", "storage.rs", 7).log_err();
+    ///
+    /// // Written at the `Warn` level.
+    /// Error::new("cache miss", "cache.rs", 3).with_severity(Severity::Warning).log_err();
+    /// ```
+    ///
+    pub fn log_err(&self) {
+        log_at(self, self.severity().log_level());
+    }
+
+    /// Writes `self` to the logger of the `log` crate at `level`.
+    ///
+    /// # Arguments
+    ///
+    /// * level - Level of the record, regardless of the severity of `self`.
+    ///
+    pub fn log_err_at(&self, level: Level) {
+        log_at(self, level);
+    }
+}
+
+/// Logs errors of `Result`s passing through.
+pub trait LogResultExt<T, E> {
+    /// Writes the error of `self`, if any, as `Error::log_err` does, and returns `self`.
+    ///
+    /// Errors which are not `Error`s are wrapped with the location where this
+    /// method is called.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mm_errors::logging::LogResultExt;
+    ///
+    /// let port = "XXX".parse::<u16>().log_err().unwrap_or(8080);
+    /// assert_eq!(port, 8080);
+    /// ```
+    ///
+    #[track_caller]
+    fn log_err(self) -> Result<T>;
+
+    /// Writes the error of `self`, if any, as `Error::log_err_at` does, and returns `self`.
+    ///
+    /// # Arguments
+    ///
+    /// * level - Level of the record, regardless of the severity of the error.
+    ///
+    #[track_caller]
+    fn log_err_at(self, level: Level) -> Result<T>;
+}
+
+impl<T, E> LogResultExt<T, E> for result::Result<T, E>
+    where E: Into<Box<dyn error::Error + marker::Send + marker::Sync>> {
+    #[track_caller]
+    fn log_err(self) -> Result<T> {
+        let location = panic::Location::caller();
+        self.map_err(|e| {
+            let e = retry::into_error(e, location);
+            e.log_err();
+            e
+        })
+    }
+
+    #[track_caller]
+    fn log_err_at(self, level: Level) -> Result<T> {
+        let location = panic::Location::caller();
+        self.map_err(|e| {
+            let e = retry::into_error(e, location);
+            e.log_err_at(level);
+            e
+        })
+    }
+}
+
+/// Writes `e` to the logger at `level`.
+pub(crate) fn log_at(e: &Error, level: Level) {
+    e.mark_reported();
+    if level > log::max_level() {
+        return;
+    }
+    let module = e.module_path();
+    let target = if module.is_empty() { TARGET } else { &module };
+    let logger = log::logger();
+    let metadata = Metadata::builder().level(level).target(target).build();
+    if !logger.enabled(&metadata) {
+        return;
+    }
+    if !filter::enabled(e) {
+        return;
+    }
+    let rate = match rate::sample(e) {
+        Some(rate) => rate,
+        None => return,
+    };
+    let (file, line) = render::location(&e.file, e.line);
+    let mut record = Record::builder();
+    record.metadata(metadata).file(Some(&file)).line(Some(line));
+
+    #[cfg(feature = "log-kv")]
+    {
+        use log::kv::Value;
+
+        let message = super::report::first_message(e);
+        let root_cause = super::report::root_message(e);
+        let mut values = Vec::with_capacity(5);
+        if let Some(ref code) = e.code {
+            values.push(("code", Value::from(&**code)));
+        }
+        values.push(("file", Value::from(&*file)));
+        values.push(("line", Value::from(line)));
+        values.push(("root_cause", Value::from(&*root_cause)));
+        if rate > 1 {
+            values.push(("sample_rate", Value::from(rate)));
+        }
+        let values = &values[..];
+        logger.log(&record.args(format_args!("{}", message)).key_values(&values).build());
+    }
+
+    #[cfg(not(feature = "log-kv"))]
+    if rate > 1 {
+        logger.log(&record.args(format_args!("{} (sampled 1 in {})", e, rate)).build());
+    } else {
+        logger.log(&record.args(format_args!("{}", e)).build());
+    }
+}
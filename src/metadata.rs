@@ -0,0 +1,400 @@
+//! Typed values attached to errors.
+
+use std::any::{self, Any};
+use std::backtrace::Backtrace;
+use std::borrow::Cow;
+use std::fmt;
+use std::slice;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+
+use super::hooks::ReportGuard;
+use super::render;
+
+/// Typed values attached to an error, for handlers to consume without parsing strings.
+///
+/// Each value is stored under a key, which is the name of its type unless
+/// given explicitly, and rendered with `Debug` when the value is inserted.
+/// The output holds the rendered values only, so values parsed back by
+/// `Error::from_xml` are `String`s: the original strings for values rendered
+/// from strings, and the rendered values for others.
+///
+/// # Examples
+///
+/// ```
+/// use std::path::PathBuf;
+/// use std::time::Duration;
+///
+/// use mm_errors::Error;
+///
+/// let e = Error::new("timed out", "net.rs", 1)
+///     .with_meta(Duration::from_secs(3))
+///     .with_meta_keyed("path", PathBuf::from("/tmp/cache"));
+///
+/// assert_eq!(e.metadata.get::<Duration>(), Some(&Duration::from_secs(3)));
+/// assert_eq!(e.metadata.get_keyed::<PathBuf>("path"), Some(&PathBuf::from("/tmp/cache")));
+/// assert_eq!(e.metadata.get_keyed::<String>("path"), None);
+///
+/// assert!(e.to_string().ends_with(
+///     "<meta key=\"core::time::Duration\">3s</meta>\
+///      <meta key=\"path\">&quot;/tmp/cache&quot;</meta></error>"));
+/// ```
+///
+#[derive(Clone, Default)]
+pub struct Metadata {
+    /// Values, and the hidden `ReportGuard` set by `hooks::set_must_report`
+    /// and backtrace captured at creation.
+    entries: Vec<Entry>,
+}
+
+#[derive(Clone)]
+struct Entry {
+    key: Cow<'static, str>,
+    value: Arc<dyn Any + Send + Sync>,
+    rendered: String,
+
+    /// Rendered value of a secret, written only by `Error::render_unredacted`.
+    secret: Option<String>,
+
+    /// Whether the value differs from run to run, and is normalized by deterministic output.
+    volatile: bool,
+}
+
+impl Entry {
+    /// Returns `true` if the entry is the `ReportGuard` or the backtrace, which are not values of the error.
+    fn is_hidden(&self) -> bool {
+        self.key == GUARD || self.key == BACKTRACE
+    }
+
+    /// Returns the value to write to the output.
+    fn output(&self, unredacted: bool) -> &str {
+        match self.secret {
+            _ if self.volatile && render::is_deterministic() => "[volatile]",
+            Some(ref secret) if unredacted => secret,
+            _ => &self.rendered,
+        }
+    }
+}
+
+/// Key of the hidden entry holding the `ReportGuard`.
+const GUARD: &str = "mm_errors::report_guard";
+
+/// Key of the hidden entry holding the backtrace.
+const BACKTRACE: &str = "mm_errors::backtrace";
+
+/// Key of the ID of the request being processed, attached by `Error::with_request_id`.
+pub const REQUEST_ID: &str = "request_id";
+
+/// Key of the distributed trace ID, attached by `Error::with_trace_id`.
+pub const TRACE_ID: &str = "trace_id";
+
+/// Key of the user on whose behalf the operation ran, attached by `Error::with_user`.
+pub const USER: &str = "user";
+
+/// Key of the `Duration` an operation ran before failing, attached by `timing::timed`.
+pub const ELAPSED: &str = "elapsed";
+
+/// Key of the `Duration` timeout of an operation which timed out, attached by `rt::with_timeout`.
+pub const TIMEOUT: &str = "timeout";
+
+/// Key of the `Severity` set by `Error::with_severity`.
+pub const SEVERITY: &str = "severity";
+
+/// Key of the number of attempts attached by `retry`.
+pub const ATTEMPTS: &str = "attempts";
+
+/// Key of the `Duration` set by `Error::with_retry_after`.
+pub const RETRY_AFTER: &str = "retry_after";
+
+/// Key of the `retry::RetryClass` set by `Error::with_retry_class`.
+pub const RETRY_CLASS: &str = "retry_class";
+
+/// Key of the command line arguments attached by `Error::with_args`.
+pub const ARGS: &str = "args";
+
+/// Key of the hint on how to fix the error, attached by `Error::with_help`.
+pub const HELP: &str = "help";
+
+/// Key of the command line argument an error is about, e.g. attached by the conversion of `clap::Error`.
+pub const ARGUMENT: &str = "argument";
+
+/// Key of the suggested replacement of a command line argument or value.
+pub const SUGGESTION: &str = "suggestion";
+
+/// Key of the `io::IoContext` attached by `Error::with_io`.
+///
+/// Values under this key are rendered as an `<io>` element instead of `<meta>`,
+/// and only if they are `IoContext`s.
+///
+pub const IO: &str = "io";
+
+type Provider = Arc<dyn Fn() -> Option<(&'static str, String)> + Send + Sync>;
+
+static PROVIDERS: RwLock<Vec<Provider>> = RwLock::new(Vec::new());
+
+/// Whether any provider is registered, so errors are created without locking otherwise.
+static HAS_PROVIDERS: AtomicBool = AtomicBool::new(false);
+
+/// Registers a provider of context attached to every new error.
+///
+/// `f` runs whenever an error is created by the constructors of `Error` or
+/// the macros, and the returned value, if any, is attached as a `String`
+/// under the returned key. Use it for cross-cutting context such as the
+/// current tenant or the deployment region. Providers run in the order they
+/// were registered, on the thread creating the error, so keep them cheap and
+/// don't create errors in them.
+///
+/// # Arguments
+///
+/// * f - Function returning the key and value to attach, or `None` to attach nothing.
+///
+/// # Examples
+///
+/// ```
+/// use mm_errors::{metadata, Error};
+///
+/// metadata::register_provider(|| Some(("region", "eu-west-1".to_string())));
+///
+/// let e = Error::here("upload failed");
+/// assert_eq!(e.metadata.get_keyed::<String>("region").map(|s| s.as_str()), Some("eu-west-1"));
+/// # metadata::clear_providers();
+/// ```
+///
+pub fn register_provider<F>(f: F)
+    where F: Fn() -> Option<(&'static str, String)> + Send + Sync + 'static {
+    PROVIDERS.write().unwrap_or_else(|e| e.into_inner()).push(Arc::new(f));
+    HAS_PROVIDERS.store(true, Ordering::Release);
+}
+
+/// Unregisters every provider registered by `register_provider`.
+pub fn clear_providers() {
+    PROVIDERS.write().unwrap_or_else(|e| e.into_inner()).clear();
+    HAS_PROVIDERS.store(false, Ordering::Release);
+}
+
+/// Attaches the context of every registered provider to `metadata`.
+pub(crate) fn provide(metadata: &mut Metadata) {
+    if !HAS_PROVIDERS.load(Ordering::Acquire) {
+        return;
+    }
+    let providers = PROVIDERS.read().unwrap_or_else(|e| e.into_inner()).clone();
+    for provider in providers {
+        if let Some((key, value)) = provider() {
+            metadata.insert_keyed(key, value);
+        }
+    }
+}
+
+/// Which value wins when a key is attached at several levels of a chain.
+///
+/// Used by `Error::all_context_by`.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Precedence {
+    /// The value attached closest to the root cause, i.e. the most specific one.
+    Innermost,
+
+    /// The value attached closest to the outermost error.
+    Outermost,
+}
+
+/// Text rendered in place of secrets.
+pub const REDACTED: &str = "***";
+
+/// Wrapper of a sensitive value, rendered as `***` by `Debug` and `Display`.
+///
+/// Attach secrets to errors with `Error::with_secret`, which also keeps the
+/// value for `Error::render_unredacted`.
+///
+#[derive(Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Redacted<T>(pub T);
+
+impl<T> fmt::Debug for Redacted<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(REDACTED)
+    }
+}
+
+impl<T> fmt::Display for Redacted<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(REDACTED)
+    }
+}
+
+impl Metadata {
+    /// Returns a new empty instance of `Metadata`.
+    pub fn new() -> Metadata {
+        Metadata { entries: Vec::new() }
+    }
+
+    /// Inserts `value` under the name of its type, replacing any value of the same type.
+    pub fn insert<T>(&mut self, value: T)
+        where T: Any + Send + Sync + fmt::Debug {
+        self.insert_keyed(any::type_name::<T>(), value);
+    }
+
+    /// Inserts `value` under `key`, replacing any value under `key`.
+    pub fn insert_keyed<K, T>(&mut self, key: K, value: T)
+        where K: Into<Cow<'static, str>>,
+              T: Any + Send + Sync + fmt::Debug {
+        let rendered = format!("{:?}", value);
+        self.insert_entry(key.into(), Arc::new(value), rendered, None);
+    }
+
+    /// Inserts `value` under `key` as a value rendered as `[volatile]` by deterministic output.
+    pub(crate) fn insert_volatile<T>(&mut self, key: &'static str, value: T)
+        where T: Any + Send + Sync + fmt::Debug {
+        let rendered = format!("{:?}", value);
+        self.insert_entry(Cow::Borrowed(key), Arc::new(value), rendered, None);
+        if let Some(e) = self.entries.iter_mut().find(|e| e.key == key) {
+            e.volatile = true;
+        }
+    }
+
+    /// Inserts the secret `value` under `key`, replacing any value under `key`.
+    ///
+    /// The value is stored as `Redacted<T>` and rendered as `***`.
+    ///
+    pub fn insert_secret<K, T>(&mut self, key: K, value: T)
+        where K: Into<Cow<'static, str>>,
+              T: Any + Send + Sync + fmt::Debug {
+        let secret = format!("{:?}", value);
+        self.insert_entry(key.into(), Arc::new(Redacted(value)), REDACTED.to_string(), Some(secret));
+    }
+
+    pub(crate) fn insert_entry(&mut self,
+                               key: Cow<'static, str>,
+                               value: Arc<dyn Any + Send + Sync>,
+                               rendered: String,
+                               secret: Option<String>) {
+        let entry = Entry { key, value, rendered, secret, volatile: false };
+        match self.entries.iter_mut().find(|e| e.key == entry.key) {
+            Some(e) => *e = entry,
+            None => self.entries.push(entry),
+        }
+    }
+
+    /// Returns the value of type `T` inserted by `insert`.
+    pub fn get<T: Any>(&self) -> Option<&T> {
+        self.get_keyed(any::type_name::<T>())
+    }
+
+    /// Returns the value under `key` if it is of type `T`.
+    pub fn get_keyed<T: Any>(&self, key: &str) -> Option<&T> {
+        self.entries.iter()
+            .find(|e| e.key == key && !e.is_hidden())
+            .and_then(|e| e.value.downcast_ref())
+    }
+
+    /// Returns the first value of type `T`, under any key.
+    pub fn find<T: Any>(&self) -> Option<&T> {
+        self.entries.iter().filter(|e| !e.is_hidden()).find_map(|e| e.value.downcast_ref())
+    }
+
+    /// Returns the secret under `key` inserted by `insert_secret`, if it is of type `T`.
+    pub fn get_secret<T: Any>(&self, key: &str) -> Option<&T> {
+        self.get_keyed::<Redacted<T>>(key).map(|r| &r.0)
+    }
+
+    /// Returns the rendered value under `key`.
+    ///
+    /// Secrets are rendered as `***`.
+    ///
+    pub fn rendered(&self, key: &str) -> Option<&str> {
+        self.entries.iter().find(|e| e.key == key && !e.is_hidden()).map(|e| e.rendered.as_str())
+    }
+
+    /// Returns the number of values.
+    pub fn len(&self) -> usize {
+        self.entries.iter().filter(|e| !e.is_hidden()).count()
+    }
+
+    /// Returns `true` if `self` holds no values.
+    pub fn is_empty(&self) -> bool {
+        self.entries.iter().all(Entry::is_hidden)
+    }
+
+    /// Returns an iterator over the keys and rendered values, in insertion order.
+    ///
+    /// Secrets are rendered as `***`.
+    ///
+    pub fn iter(&self) -> Iter<'_> {
+        Iter { inner: self.entries.iter() }
+    }
+
+    /// Starts tracking whether the error holding `self` is reported.
+    ///
+    /// The guard is kept as a hidden entry, so it is shared by the clones of
+    /// the error without making `Error` larger.
+    ///
+    pub(crate) fn guard(&mut self, description: String) {
+        let guard = Arc::new(ReportGuard::new(description));
+        self.insert_entry(Cow::Borrowed(GUARD), guard, String::new(), None);
+    }
+
+    /// Keeps the backtrace of the error holding `self` as a hidden entry.
+    pub(crate) fn set_backtrace(&mut self, backtrace: Backtrace) {
+        self.insert_entry(Cow::Borrowed(BACKTRACE), Arc::new(backtrace), String::new(), None);
+    }
+
+    /// Returns the backtrace kept by `set_backtrace`.
+    pub(crate) fn backtrace(&self) -> Option<&Backtrace> {
+        self.hidden(BACKTRACE)
+    }
+
+    /// Records that the error holding `self` was reported.
+    pub(crate) fn report(&self) {
+        if let Some(guard) = self.hidden::<ReportGuard>(GUARD) {
+            guard.report();
+        }
+    }
+
+    /// Returns the hidden entry under `key`, which the public getters skip.
+    fn hidden<T: Any>(&self, key: &str) -> Option<&T> {
+        self.entries.iter().find(|e| e.key == key).and_then(|e| e.value.downcast_ref())
+    }
+
+    /// Returns an iterator over the keys and values to write to the output.
+    ///
+    /// Secrets are unredacted if `unredacted`, and volatile values are
+    /// normalized if deterministic output is enabled.
+    ///
+    pub(crate) fn iter_rendered(&self, unredacted: bool) -> impl Iterator<Item = (&str, &str)> {
+        self.entries.iter().filter(|e| !e.is_hidden()).map(move |e| (&*e.key, e.output(unredacted)))
+    }
+}
+
+impl fmt::Debug for Metadata {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_map().entries(self.iter_rendered(false)).finish()
+    }
+}
+
+/// Iterator returned by `Metadata::iter`.
+#[derive(Clone)]
+pub struct Iter<'a> {
+    inner: slice::Iter<'a, Entry>,
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = (&'a str, &'a str);
+
+    fn next(&mut self) -> Option<(&'a str, &'a str)> {
+        self.inner.by_ref().find(|e| !e.is_hidden()).map(|e| (&*e.key, e.rendered.as_str()))
+    }
+}
+
+impl<'a> fmt::Debug for Iter<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Iter").finish()
+    }
+}
+
+impl<'a> IntoIterator for &'a Metadata {
+    type Item = (&'a str, &'a str);
+    type IntoIter = Iter<'a>;
+
+    fn into_iter(self) -> Iter<'a> {
+        self.iter()
+    }
+}
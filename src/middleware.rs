@@ -0,0 +1,144 @@
+//! Tower middleware turning errors of services into HTTP responses.
+//!
+//! `ErrorLayer` wraps services failing with `Error`, such as handlers of
+//! hyper, axum or tonic stacks. Each error is passed to `hooks::report`, and
+//! replaced with the response of `Error::to_response` with
+//! `Renderer::ProblemDetails`. The wrapped service never fails.
+//!
+//! This module is available with the `tower` feature.
+//!
+//! # Examples
+//!
+//! ```
+//! use tower::{service_fn, Layer, ServiceExt};
+//!
+//! use mm_errors::{hooks, Error};
+//! use mm_errors::catalog::{self, CodeInfo};
+//! use mm_errors::middleware::ErrorLayer;
+//!
+//! catalog::register(CodeInfo::new("MDW404", "Order not found").http_status(404)).unwrap();
+//! hooks::set_report_hook(|_: &Error| {});
+//!
+//! let handler = service_fn(|_: http::Request<String>| async {
+//!     Err::<http::Response<String>, _>(Error::here("no order 7").with_code("MDW404"))
+//! });
+//! let service = ErrorLayer::new().layer(handler);
+//!
+//! let response = futures::executor::block_on(service.oneshot(http::Request::new(String::new())))
+//!     .unwrap();
+//! assert_eq!(response.status(), 404);
+//! assert_eq!(response.headers()["content-type"], "application/problem+json");
+//! assert!(response.body().contains("\"detail\":\"no order 7\""));
+//! # hooks::reset_report_hook();
+//! ```
+
+use std::convert::Infallible;
+use std::future::Future;
+use std::pin::Pin;
+use std::task;
+
+use http::Response;
+use tower_layer::Layer;
+use tower_service::Service;
+
+use super::response::Renderer;
+use super::{hooks, Error};
+
+/// Layer wrapping services with `ErrorService`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ErrorLayer;
+
+impl ErrorLayer {
+    /// Returns a new instance of `ErrorLayer`.
+    pub fn new() -> ErrorLayer {
+        ErrorLayer
+    }
+}
+
+impl<S> Layer<S> for ErrorLayer {
+    type Service = ErrorService<S>;
+
+    fn layer(&self, inner: S) -> ErrorService<S> {
+        ErrorService { inner, failed: None }
+    }
+}
+
+/// Service replacing the errors of the inner service with responses.
+///
+/// An error of `poll_ready` of the inner service is replied to the next request.
+///
+#[derive(Debug, Clone)]
+pub struct ErrorService<S> {
+    inner: S,
+    failed: Option<Error>,
+}
+
+impl<S, Req, B> Service<Req> for ErrorService<S>
+    where S: Service<Req, Response = Response<B>, Error = Error>,
+          B: From<String> {
+    type Response = Response<B>;
+    type Error = Infallible;
+    type Future = ResponseFuture<S::Future>;
+
+    fn poll_ready(&mut self, cx: &mut task::Context) -> task::Poll<Result<(), Infallible>> {
+        if self.failed.is_some() {
+            return task::Poll::Ready(Ok(()));
+        }
+        match self.inner.poll_ready(cx) {
+            task::Poll::Pending => task::Poll::Pending,
+            task::Poll::Ready(Ok(())) => task::Poll::Ready(Ok(())),
+            task::Poll::Ready(Err(e)) => {
+                self.failed = Some(e);
+                task::Poll::Ready(Ok(()))
+            }
+        }
+    }
+
+    fn call(&mut self, request: Req) -> ResponseFuture<S::Future> {
+        match self.failed.take() {
+            Some(e) => ResponseFuture { state: State::Failed(Some(e)) },
+            None => ResponseFuture { state: State::Inner(self.inner.call(request)) },
+        }
+    }
+}
+
+/// Future returned by `ErrorService`.
+#[derive(Debug)]
+#[must_use = "futures do nothing unless polled"]
+pub struct ResponseFuture<Fut> {
+    state: State<Fut>,
+}
+
+#[derive(Debug)]
+enum State<Fut> {
+    Inner(Fut),
+    Failed(Option<Error>),
+}
+
+impl<Fut, B> Future for ResponseFuture<Fut>
+    where Fut: Future<Output = Result<Response<B>, Error>>,
+          B: From<String> {
+    type Output = Result<Response<B>, Infallible>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut task::Context) -> task::Poll<Self::Output> {
+        // SAFETY: the future in `state` is never moved out of `self`.
+        let this = unsafe { self.get_unchecked_mut() };
+        match this.state {
+            State::Inner(ref mut future) => match unsafe { Pin::new_unchecked(future) }.poll(cx) {
+                task::Poll::Pending => task::Poll::Pending,
+                task::Poll::Ready(Ok(response)) => task::Poll::Ready(Ok(response)),
+                task::Poll::Ready(Err(e)) => task::Poll::Ready(Ok(respond(&e))),
+            },
+            State::Failed(ref mut e) => {
+                let e = e.take().expect("`ResponseFuture` polled after completion");
+                task::Poll::Ready(Ok(respond(&e)))
+            }
+        }
+    }
+}
+
+/// Reports `e`, and returns the response replacing it.
+fn respond<B: From<String>>(e: &Error) -> Response<B> {
+    hooks::report(e);
+    e.to_response(Renderer::ProblemDetails)
+}
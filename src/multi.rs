@@ -0,0 +1,207 @@
+//! Error type holding several errors.
+
+use std::error;
+use std::fmt;
+use std::io;
+use std::slice;
+use std::vec;
+
+use super::{json, render, Error, Result, SCHEMA_VERSION};
+
+/// Holds several errors, e.g. every failed attempt of a retried operation.
+///
+/// `Display` renders the errors as `<error>` elements inside an `<errors>`
+/// element. A `MultiError` wrapped by an `Error` is rendered as a child
+/// `<errors>` element of `<reason>`.
+///
+/// # Examples
+///
+/// ```
+/// use mm_errors::{Error, MultiError};
+///
+/// let mut errors = MultiError::new();
+/// errors.push(Error::new("first", "a.rs", 1));
+/// errors.push(Error::new("second", "b.rs", 2));
+///
+/// assert_eq!(errors.len(), 2);
+/// assert_eq!(
+///     errors.to_string(),
+///     format!(
+///         "<errors schema=\"2\" version=\"{}\">\
+///          <error><file>a.rs</file><line>1</line><reason>first</reason></error>\
+///          <error><file>b.rs</file><line>2</line><reason>second</reason></error>\
+///          </errors>",
+///         mm_errors::CRATE_VERSION));
+/// ```
+///
+#[derive(Debug, Clone, Default)]
+pub struct MultiError {
+    /// Errors in the order they occurred.
+    pub errors: Vec<Error>,
+}
+
+impl MultiError {
+    /// Returns a new empty instance of `MultiError`.
+    pub fn new() -> MultiError {
+        MultiError { errors: Vec::new() }
+    }
+
+    /// Appends `e`.
+    pub fn push(&mut self, e: Error) {
+        self.errors.push(e);
+    }
+
+    /// Returns the number of errors.
+    pub fn len(&self) -> usize {
+        self.errors.len()
+    }
+
+    /// Returns `true` if `self` holds no errors.
+    pub fn is_empty(&self) -> bool {
+        self.errors.is_empty()
+    }
+
+    /// Returns an iterator over the errors.
+    pub fn iter(&self) -> slice::Iter<'_, Error> {
+        self.errors.iter()
+    }
+
+    /// Returns `self` as JSON.
+    ///
+    /// The output is an object with a `schema` member holding `SCHEMA_VERSION`,
+    /// a `version` member holding `CRATE_VERSION` and an `errors` member
+    /// holding an array of the errors, each written as by `Error::to_json`
+    /// without the `schema` and `version` members.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mm_errors::{Error, MultiError};
+    ///
+    /// let errors = MultiError::from(vec![Error::new("first", "a.rs", 1)]);
+    /// assert_eq!(
+    ///     errors.to_json(),
+    ///     format!(
+    ///         "{{\"schema\":2,\"version\":\"{}\",\"errors\":[{{\"file\":\"a.rs\",\"line\":1,\"reason\":\"first\"}}]}}",
+    ///         mm_errors::CRATE_VERSION));
+    /// assert_eq!(MultiError::from_json(&errors.to_json()).unwrap().to_json(), errors.to_json());
+    /// ```
+    ///
+    pub fn to_json(&self) -> String {
+        let mut out = String::new();
+        json::write_multi(&mut out, self).expect("writing to a `String` never fails");
+        out
+    }
+
+    /// Writes `self` as JSON to `w`.
+    ///
+    /// The output is the same as `to_json`, but the errors are written one at
+    /// a time instead of building the whole document in memory, so huge
+    /// aggregates, e.g. of batch imports, can be written straight to a file
+    /// or a socket. Small writes are passed to `w` as they are, so wrap it in
+    /// an `io::BufWriter` unless it buffers already. `w` is flushed at the end.
+    ///
+    /// # Arguments
+    ///
+    /// * w - Writer to write to.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of `w` if writing fails. The output written so far is
+    /// left in `w`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::io::BufWriter;
+    ///
+    /// use mm_errors::{Error, MultiError};
+    ///
+    /// let errors: MultiError = (0..10_000)
+    ///     .map(|i| Error::new("invalid row", "import.rs", i))
+    ///     .collect::<Vec<_>>()
+    ///     .into();
+    ///
+    /// let mut out = BufWriter::new(Vec::new());
+    /// errors.serialize_into(&mut out).unwrap();
+    ///
+    /// assert_eq!(out.into_inner().unwrap(), errors.to_json().into_bytes());
+    /// ```
+    ///
+    pub fn serialize_into<W: io::Write>(&self, w: W) -> io::Result<()> {
+        json::write_multi_into(w, self)
+    }
+
+    /// Parses the JSON written by `to_json` back into a `MultiError`.
+    ///
+    /// # Arguments
+    ///
+    /// * s - JSON written by `to_json`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `s` is not valid JSON in that format, or its schema
+    /// version is not supported.
+    ///
+    pub fn from_json(s: &str) -> Result<MultiError> {
+        json::parse_multi(s)
+    }
+
+    /// Records that the errors were reported, for `hooks::set_must_report`.
+    pub(crate) fn mark_reported(&self) {
+        for e in &self.errors {
+            e.mark_reported();
+        }
+    }
+
+    pub(crate) fn format_xml(&self, f: &mut fmt::Formatter, root: bool) -> fmt::Result {
+        if root {
+            write!(f, "<errors schema=\"{}\" version=\"{}\">", SCHEMA_VERSION, render::crate_version())?;
+        } else {
+            write!(f, "<errors>")?;
+        }
+        for e in &self.errors {
+            e.format_xml(f, false)?;
+        }
+        write!(f, "</errors>")
+    }
+}
+
+impl From<Vec<Error>> for MultiError {
+    fn from(errors: Vec<Error>) -> MultiError {
+        MultiError { errors }
+    }
+}
+
+impl IntoIterator for MultiError {
+    type Item = Error;
+    type IntoIter = vec::IntoIter<Error>;
+
+    fn into_iter(self) -> vec::IntoIter<Error> {
+        self.errors.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a MultiError {
+    type Item = &'a Error;
+    type IntoIter = slice::Iter<'a, Error>;
+
+    fn into_iter(self) -> slice::Iter<'a, Error> {
+        self.errors.iter()
+    }
+}
+
+impl Extend<Error> for MultiError {
+    fn extend<I: IntoIterator<Item = Error>>(&mut self, iter: I) {
+        self.errors.extend(iter);
+    }
+}
+
+impl error::Error for MultiError {}
+
+impl fmt::Display for MultiError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.mark_reported();
+        self.format_xml(f, true)
+    }
+}
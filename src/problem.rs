@@ -0,0 +1,122 @@
+//! Problem Details of errors for HTTP APIs, as defined by RFC 7807.
+
+use std::fmt;
+use std::fmt::Write;
+
+use super::{catalog, hooks, json, report, xml, Error};
+
+/// Members of Problem Details, which extension members must not replace.
+const MEMBERS: [&str; 6] = ["type", "title", "status", "detail", "instance", "code"];
+
+impl Error {
+    /// Returns `self` as an `application/problem+json` body, as defined by RFC 7807.
+    ///
+    /// The members are taken from the outermost code in the chain having one:
+    ///
+    /// | Member | Value |
+    /// |--------|-------|
+    /// | `type` | Documentation URL of the code, or else `about:blank` |
+    /// | `title` | Title of the code in the catalog, only if it is registered |
+    /// | `status` | `Error::http_status`, or else 500 |
+    /// | `detail` | First message in the chain, as in `Error::report` |
+    /// | `instance` | `Error::request_id`, only if there is one |
+    /// | `code` | Error code, only if there is one |
+    ///
+    /// followed by an extension member for each key of `Error::all_context`
+    /// chosen by `hooks::set_problem_extensions_hook`, unless it is one of the
+    /// above. Strings are written without quotes, and secrets as `***`. Files,
+    /// lines, inner messages and, by default, metadata are not written, so the
+    /// body can be returned to clients.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mm_errors::{hooks, Error};
+    /// use mm_errors::catalog::{self, CodeInfo};
+    ///
+    /// hooks::set_problem_extensions_hook(|key: &str| key == "request_id" || key == "order");
+    ///
+    /// catalog::register(CodeInfo::new("PRB404", "Order not found")
+    ///     .http_status(404)
+    ///     .docs_url("https://example.com/errors/PRB404")).unwrap();
+    ///
+    /// let e = Error::here("no order 7")
+    ///     .with_code("PRB404")
+    ///     .with_request_id("r-42")
+    ///     .with_meta_keyed("order", 7);
+    /// assert_eq!(
+    ///     e.to_problem_details(),
+    ///     "{\"type\":\"https://example.com/errors/PRB404\",\"title\":\"Order not found\",\
+    ///      \"status\":404,\"detail\":\"no order 7\",\"instance\":\"r-42\",\"code\":\"PRB404\",\
+    ///      \"request_id\":\"r-42\",\"order\":\"7\"}");
+    ///
+    /// assert_eq!(
+    ///     Error::here("disk full").to_problem_details(),
+    ///     "{\"type\":\"about:blank\",\"status\":500,\"detail\":\"disk full\"}");
+    /// # hooks::reset_problem_extensions_hook();
+    /// ```
+    ///
+    /// Without the hook, the environment, IO context and other metadata are
+    /// not written:
+    ///
+    /// ```
+    /// use mm_errors::Error;
+    /// use mm_errors::io::IoContext;
+    ///
+    /// let e = Error::here("read failed")
+    ///     .with_env(&["PATH"])
+    ///     .with_io(IoContext::new("read", "/srv/orders/7.json"))
+    ///     .with_user("alice");
+    /// assert_eq!(
+    ///     e.to_problem_details(),
+    ///     "{\"type\":\"about:blank\",\"status\":500,\"detail\":\"read failed\"}");
+    /// ```
+    ///
+    pub fn to_problem_details(&self) -> String {
+        self.mark_reported();
+        let code = self.chain()
+            .filter_map(|e| e.downcast_ref::<Error>())
+            .find_map(|e| e.code.as_ref());
+        let info = code.and_then(|code| catalog::lookup(code));
+        let mut out = String::new();
+        self.write_problem_details(&mut out, code.map(|code| &**code), info.as_ref())
+            .expect("writing to a `String` never fails");
+        out
+    }
+
+    fn write_problem_details(&self, w: &mut dyn Write, code: Option<&str>,
+                             info: Option<&catalog::CodeInfo>) -> fmt::Result {
+        w.write_str("{\"type\":")?;
+        let docs_url = code.and_then(catalog::docs_url);
+        json::write_string(w, docs_url.as_deref().unwrap_or("about:blank"))?;
+        if let Some(info) = info {
+            w.write_str(",\"title\":")?;
+            json::write_string(w, &info.title)?;
+        }
+        write!(w, ",\"status\":{}", self.http_status().unwrap_or(500))?;
+        w.write_str(",\"detail\":")?;
+        json::write_string(w, &report::first_message(self))?;
+        if let Some(id) = self.request_id() {
+            w.write_str(",\"instance\":")?;
+            json::write_string(w, id)?;
+        }
+        if let Some(code) = code {
+            w.write_str(",\"code\":")?;
+            json::write_string(w, code)?;
+        }
+        let extensions = match hooks::problem_extensions() {
+            Some(extensions) => extensions,
+            None => return w.write_char('}'),
+        };
+        for (key, value) in self.all_context() {
+            if MEMBERS.contains(&key) || !extensions(key) {
+                continue;
+            }
+            w.write_char(',')?;
+            json::write_string(w, key)?;
+            w.write_char(':')?;
+            json::write_string(w, &xml::unquote(value).unwrap_or_else(|| value.to_string()))?;
+        }
+        w.write_char('}')
+    }
+}
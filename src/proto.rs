@@ -0,0 +1,341 @@
+//! Protocol Buffers messages of errors.
+//!
+//! The messages are defined by `proto/mm_errors.proto` in the package, so
+//! services in other languages can decode errors sent by Rust services. They
+//! mirror the JSON format of `Error::to_json`, and are converted from and into
+//! the errors of this crate with `From` and `TryFrom`.
+//!
+//! This module is available with the `prost` feature.
+//!
+//! # Examples
+//!
+//! ```
+//! use std::convert::TryFrom;
+//!
+//! use prost::Message;
+//!
+//! use mm_errors::{proto, Error};
+//!
+//! let sent = Error::context(Error::new("disk full", "storage.rs", 7), "saving", "service.rs", 21);
+//! let bytes = proto::Error::from(&sent).encode_to_vec();
+//!
+//! let received = Error::try_from(proto::Error::decode(&*bytes).unwrap()).unwrap();
+//! assert_eq!(received.to_string(), sent.to_string());
+//! ```
+//!
+//! A `RemoteError` keeps its host and service:
+//!
+//! ```
+//! use std::convert::TryFrom;
+//!
+//! use prost::Message;
+//!
+//! use mm_errors::{proto, Error, RemoteError};
+//!
+//! let remote = RemoteError::new("db-1", "storage", Error::new("disk full", "storage.rs", 7));
+//! let sent = Error::context(remote, "saving the order", "orders.rs", 12);
+//! let bytes = proto::Error::from(&sent).encode_to_vec();
+//!
+//! let received = Error::try_from(proto::Error::decode(&*bytes).unwrap()).unwrap();
+//! assert_eq!(received.remote().unwrap().service, "storage");
+//! assert_eq!(received.to_string(), sent.to_string());
+//! ```
+
+use std::borrow::Cow;
+use std::convert::TryFrom;
+use std::error;
+use std::marker;
+use std::sync::Arc;
+
+use super::io::IoContext;
+use super::{metadata, render, xml, ErrorKind, LocalError, Metadata, MultiError, RemoteError, SCHEMA_VERSION};
+
+/// Level of a chain of errors.
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Error {
+    /// Version of the format, set only on the outermost error.
+    #[prost(uint32, tag = "1")]
+    pub schema: u32,
+
+    /// File where the error occurred.
+    #[prost(string, tag = "2")]
+    pub file: String,
+
+    /// Line where the error occurred.
+    #[prost(uint32, tag = "3")]
+    pub line: u32,
+
+    /// Reason of the error.
+    #[prost(oneof = "Reason", tags = "4, 5, 6, 13")]
+    pub reason: Option<Reason>,
+
+    /// Context message of `ErrorKind::Context`.
+    #[prost(string, optional, tag = "7")]
+    pub context: Option<String>,
+
+    /// Whether the error is an `ErrorKind::Cancelled`.
+    #[prost(bool, tag = "8")]
+    pub cancelled: bool,
+
+    /// Error code.
+    #[prost(string, optional, tag = "9")]
+    pub code: Option<String>,
+
+    /// Metadata, as rendered.
+    #[prost(message, repeated, tag = "10")]
+    pub meta: Vec<Meta>,
+
+    /// Context of a failed IO operation.
+    #[prost(message, optional, tag = "11")]
+    pub io: Option<Io>,
+
+    /// Version of the crate which wrote the error, set only on the outermost error.
+    #[prost(string, tag = "12")]
+    pub version: String,
+}
+
+/// Reason of an `Error`.
+#[derive(Clone, PartialEq, ::prost::Oneof)]
+pub enum Reason {
+    /// Message, or the text of an error which was not an `Error`.
+    #[prost(string, tag = "4")]
+    Text(String),
+
+    /// Inner error.
+    #[prost(message, tag = "5")]
+    Inner(Box<Error>),
+
+    /// Inner errors of a `MultiError`.
+    #[prost(message, tag = "6")]
+    Errors(Errors),
+
+    /// Inner `RemoteError`.
+    #[prost(message, tag = "13")]
+    Remote(Box<Remote>),
+}
+
+/// Error received from another process, and where it came from.
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Remote {
+    /// Name of the host where the error occurred.
+    #[prost(string, tag = "1")]
+    pub host: String,
+
+    /// Name of the process or service where the error occurred.
+    #[prost(string, tag = "2")]
+    pub service: String,
+
+    /// Chain of errors of the remote process.
+    #[prost(message, optional, tag = "3")]
+    pub error: Option<Error>,
+}
+
+/// Several errors.
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Errors {
+    /// Version of the format, set only if this is the outermost message.
+    #[prost(uint32, tag = "1")]
+    pub schema: u32,
+
+    /// Errors in the order they occurred.
+    #[prost(message, repeated, tag = "2")]
+    pub errors: Vec<Error>,
+
+    /// Version of the crate which wrote the errors, set only if this is the outermost message.
+    #[prost(string, tag = "3")]
+    pub version: String,
+}
+
+/// Value attached to an error.
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Meta {
+    /// Key of the value.
+    #[prost(string, tag = "1")]
+    pub key: String,
+
+    /// Value, as rendered.
+    #[prost(string, tag = "2")]
+    pub value: String,
+}
+
+/// Context of a failed IO operation.
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Io {
+    /// Operation, e.g. `"read"`.
+    #[prost(string, tag = "1")]
+    pub operation: String,
+
+    /// Path of the file or directory.
+    #[prost(string, tag = "2")]
+    pub path: String,
+
+    /// Number of bytes processed before the failure.
+    #[prost(uint64, optional, tag = "3")]
+    pub bytes: Option<u64>,
+}
+
+impl<'a> From<&'a super::Error> for Error {
+    fn from(e: &'a super::Error) -> Error {
+        e.mark_reported();
+        let mut message = level(e);
+        message.schema = SCHEMA_VERSION;
+        message.version = render::crate_version().to_string();
+        message
+    }
+}
+
+impl<'a> From<&'a MultiError> for Errors {
+    fn from(errors: &'a MultiError) -> Errors {
+        errors.mark_reported();
+        Errors {
+            schema: SCHEMA_VERSION,
+            errors: errors.iter().map(level).collect(),
+            version: render::crate_version().to_string(),
+        }
+    }
+}
+
+impl TryFrom<Error> for super::Error {
+    type Error = super::Error;
+
+    /// Converts `message` into an `Error`, as `Error::from_json` does.
+    ///
+    /// Fails if a reason is missing or the schema version is not supported.
+    fn try_from(message: Error) -> super::Result<super::Error> {
+        check_schema(message.schema)?;
+        to_error(message)
+    }
+}
+
+impl TryFrom<Errors> for MultiError {
+    type Error = super::Error;
+
+    fn try_from(message: Errors) -> super::Result<MultiError> {
+        check_schema(message.schema)?;
+        to_errors(message)
+    }
+}
+
+/// Returns the message of a level, without the schema and crate versions.
+fn level(e: &super::Error) -> Error {
+    let (file, line) = render::location(&e.file, e.line);
+    let reason = match e.kind {
+        ErrorKind::String(ref s) | ErrorKind::Cancelled(ref s) => Reason::Text(s.clone()),
+        ErrorKind::Wrapped(ref inner) | ErrorKind::Context(_, ref inner) => reason(&**inner),
+    };
+    let context = match e.kind {
+        ErrorKind::Context(ref context, _) => Some(context.clone()),
+        _ => None,
+    };
+    let meta = e.metadata.iter_rendered(render::is_unredacted())
+        .filter(|&(key, _)| key != metadata::IO)
+        .map(|(key, value)| Meta { key: key.to_string(), value: value.to_string() })
+        .collect();
+    let io = e.metadata.get_keyed::<IoContext>(metadata::IO).map(|io| Io {
+        operation: io.operation.to_string(),
+        path: io.path.to_string_lossy().into_owned(),
+        bytes: io.bytes,
+    });
+    Error {
+        schema: 0,
+        file: file.into_owned(),
+        line,
+        reason: Some(reason),
+        context,
+        cancelled: matches!(e.kind, ErrorKind::Cancelled(..)),
+        code: e.code.as_ref().map(|code| code.to_string()),
+        meta,
+        io,
+        version: String::new(),
+    }
+}
+
+fn reason(e: &(dyn error::Error + 'static)) -> Reason {
+    if let Some(inner) = e.downcast_ref::<super::Error>() {
+        Reason::Inner(Box::new(level(inner)))
+    } else if let Some(inner) = e.downcast_ref::<LocalError>() {
+        let (file, line) = render::location(inner.file, inner.line);
+        Reason::Inner(Box::new(Error {
+            file: file.into_owned(),
+            line,
+            reason: Some(reason(&*inner.inner)),
+            ..Error::default()
+        }))
+    } else if let Some(inner) = e.downcast_ref::<RemoteError>() {
+        Reason::Remote(Box::new(Remote {
+            host: inner.host.clone(),
+            service: inner.service.clone(),
+            error: Some(level(&inner.error)),
+        }))
+    } else if let Some(inner) = e.downcast_ref::<MultiError>() {
+        Reason::Errors(Errors { schema: 0, errors: inner.iter().map(level).collect(), version: String::new() })
+    } else {
+        Reason::Text(e.to_string())
+    }
+}
+
+fn check_schema(version: u32) -> super::Result<()> {
+    if super::is_supported_schema(version) {
+        Ok(())
+    } else {
+        Err(invalid(&format!("unsupported schema version `{}`", version)))
+    }
+}
+
+fn invalid(message: &str) -> super::Error {
+    super::Error::new(&format!("invalid error message: {}", message), file!(), line!())
+}
+
+fn to_error(message: Error) -> super::Result<super::Error> {
+    let mut metadata = Metadata::new();
+    for meta in message.meta {
+        let parsed = xml::unquote(&meta.value).unwrap_or_else(|| meta.value.clone());
+        metadata.insert_entry(Cow::Owned(meta.key), Arc::new(parsed), meta.value, None);
+    }
+    if let Some(io) = message.io {
+        let mut context = IoContext::new(io.operation, io.path);
+        context.bytes = io.bytes;
+        metadata.insert_keyed(metadata::IO, context);
+    }
+
+    let reason: Box<dyn error::Error + marker::Send + marker::Sync> = match message.reason {
+        Some(Reason::Text(s)) if message.context.is_some() => s.into(),
+        Some(Reason::Text(s)) => {
+            let kind = if message.cancelled { ErrorKind::Cancelled(s) } else { ErrorKind::String(s) };
+            return Ok(level_of(message.file, message.line, kind, message.code, metadata));
+        }
+        Some(Reason::Inner(inner)) => Box::new(to_error(*inner)?),
+        Some(Reason::Errors(errors)) => Box::new(to_errors(errors)?),
+        Some(Reason::Remote(remote)) => {
+            let Remote { host, service, error } = *remote;
+            let error = error.ok_or_else(|| invalid("missing remote error"))?;
+            Box::new(RemoteError::new(host, service, to_error(error)?))
+        }
+        None => return Err(invalid("missing reason")),
+    };
+    let kind = match message.context {
+        Some(context) => ErrorKind::Context(context, reason),
+        None => ErrorKind::Wrapped(reason),
+    };
+    Ok(level_of(message.file, message.line, kind, message.code, metadata))
+}
+
+fn to_errors(message: Errors) -> super::Result<MultiError> {
+    let errors = message.errors.into_iter().map(to_error).collect::<super::Result<Vec<_>>>()?;
+    Ok(MultiError::from(errors))
+}
+
+fn level_of(file: String,
+            line: u32,
+            kind: ErrorKind,
+            code: Option<String>,
+            metadata: Metadata)
+            -> super::Error {
+    super::Error {
+        file: Cow::Owned(file),
+        line,
+        kind,
+        code: code.map(Cow::Owned),
+        metadata,
+    }
+}
@@ -0,0 +1,287 @@
+//! Tracking how often errors occur.
+//!
+//! Errors are grouped by `Error::fingerprint`, so the same failure counts as
+//! one kind of error even if its messages contain varying values, such as ids.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+use super::Error;
+
+impl Error {
+    /// Returns a fingerprint identifying the kind of failure of `self`.
+    ///
+    /// The fingerprint is computed from the code, file and line of every
+    /// `Error` in the chain, but not from messages and metadata. It is stable
+    /// across runs and builds of the same source.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mm_errors::Error;
+    ///
+    /// let user = |id: u32| Error::new(&format!("user {} not found", id), "users.rs", 10);
+    /// assert_eq!(user(1).fingerprint(), user(2).fingerprint());
+    /// assert_ne!(user(1).fingerprint(), Error::new("user 1 not found", "users.rs", 20).fingerprint());
+    /// ```
+    ///
+    pub fn fingerprint(&self) -> u64 {
+        // FNV-1a, since the hashers of std may change between releases.
+        let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+        let mut write = |bytes: &[u8]| {
+            for &b in bytes {
+                hash ^= u64::from(b);
+                hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+            }
+        };
+        for e in self.chain().filter_map(|e| e.downcast_ref::<Error>()) {
+            write(e.code.as_deref().unwrap_or("").as_bytes());
+            write(&[0]);
+            write(e.file.as_bytes());
+            write(&[0]);
+            write(&e.line.to_le_bytes());
+        }
+        hash
+    }
+}
+
+/// Counts errors per fingerprint over a sliding window of time.
+///
+/// This decides whether an error happens often enough to escalate, e.g. to
+/// page someone only if it happens more than N times per minute.
+/// The rate can be shared between threads, e.g. in an `Arc`.
+///
+/// # Examples
+///
+/// ```
+/// use std::time::Duration;
+///
+/// use mm_errors::Error;
+/// use mm_errors::rate::ErrorRate;
+///
+/// let rate = ErrorRate::new(2, Duration::from_secs(60));
+/// let e = Error::new("connection refused", "db.rs", 1);
+///
+/// assert!(!rate.record(&e));
+/// assert!(!rate.record(&e));
+/// assert!(rate.record(&e));
+/// assert!(rate.should_escalate(&e));
+/// assert_eq!(rate.count(&e), 3);
+///
+/// assert!(!rate.should_escalate(&Error::new("disk full", "storage.rs", 1)));
+/// ```
+///
+#[derive(Debug)]
+pub struct ErrorRate {
+    threshold: usize,
+    window: Duration,
+    occurrences: Mutex<HashMap<u64, VecDeque<Instant>>>,
+}
+
+impl ErrorRate {
+    /// Returns a new instance of `ErrorRate`.
+    ///
+    /// # Arguments
+    ///
+    /// * threshold - Number of occurrences within `window` which is still fine.
+    /// * window - Length of the sliding window.
+    ///
+    pub fn new(threshold: usize, window: Duration) -> ErrorRate {
+        ErrorRate {
+            threshold,
+            window,
+            occurrences: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Records an occurrence of `e`, and returns whether it should be escalated.
+    pub fn record(&self, e: &Error) -> bool {
+        let now = Instant::now();
+        let mut occurrences = self.occurrences.lock().unwrap_or_else(|e| e.into_inner());
+        let times = occurrences.entry(e.fingerprint()).or_default();
+        times.push_back(now);
+        self.expire(times, now);
+        times.len() > self.threshold
+    }
+
+    /// Returns `true` if errors like `e` occurred more than the threshold
+    /// within the window.
+    pub fn should_escalate(&self, e: &Error) -> bool {
+        self.count(e) > self.threshold
+    }
+
+    /// Returns the number of occurrences of errors like `e` within the window.
+    pub fn count(&self, e: &Error) -> usize {
+        let mut occurrences = self.occurrences.lock().unwrap_or_else(|e| e.into_inner());
+        match occurrences.get_mut(&e.fingerprint()) {
+            Some(times) => {
+                self.expire(times, Instant::now());
+                times.len()
+            }
+            None => 0,
+        }
+    }
+
+    /// Forgets every occurrence.
+    pub fn reset(&self) {
+        self.occurrences.lock().unwrap_or_else(|e| e.into_inner()).clear();
+    }
+
+    fn expire(&self, times: &mut VecDeque<Instant>, now: Instant) {
+        while times.front().is_some_and(|&t| now.duration_since(t) > self.window) {
+            times.pop_front();
+        }
+    }
+}
+
+/// Decides which errors are emitted by the reporters and the log integrations.
+///
+/// Errors are sampled per fingerprint, so a noisy failure doesn't drown out
+/// rare ones. Errors emitted at a sample rate above 1 carry the rate, i.e.
+/// how many errors like them they stand for, e.g. as the `sample_rate`
+/// key-value of the `log` integration or the `SAMPLE_RATE` field of the
+/// journal, or the rate passed to `hooks::set_sampled_report_hook`. Set the
+/// sampler of the reporters with `set_sampler`.
+///
+/// # Examples
+///
+/// ```
+/// use mm_errors::Error;
+/// use mm_errors::rate::Sampler;
+///
+/// let sampler = Sampler::one_in(3);
+/// let e = Error::new("connection refused", "db.rs", 1);
+///
+/// let rates: Vec<_> = (0..6).map(|_| sampler.sample(&e)).collect();
+/// assert_eq!(rates, [Some(3), None, None, Some(3), None, None]);
+///
+/// // Other errors are sampled separately.
+/// assert_eq!(sampler.sample(&Error::new("disk full", "storage.rs", 1)), Some(3));
+/// ```
+///
+#[derive(Debug)]
+pub struct Sampler {
+    mode: Mode,
+    counts: Mutex<HashMap<u64, Count>>,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Mode {
+    OneIn(u64),
+    Adaptive { per_window: u64, window: Duration },
+}
+
+/// Occurrences of a fingerprint since `start`.
+#[derive(Debug)]
+struct Count {
+    start: Instant,
+    count: u64,
+}
+
+impl Sampler {
+    /// Returns a new instance of `Sampler` emitting the first and then every `n`th error.
+    ///
+    /// # Arguments
+    ///
+    /// * n - Sample rate, where `1` emits every error.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `n` is 0.
+    ///
+    pub fn one_in(n: u64) -> Sampler {
+        assert!(n > 0, "the sample rate must be positive");
+        Sampler::with_mode(Mode::OneIn(n))
+    }
+
+    /// Returns a new instance of `Sampler` backing off as errors get more frequent.
+    ///
+    /// Within each `window`, the first `per_window` errors like each other
+    /// are all emitted. Beyond that, the sample rate is the number of errors
+    /// so far divided by `per_window`, rounded up to a power of two, so about
+    /// `per_window / 2` more errors are emitted whenever the number doubles.
+    ///
+    /// # Arguments
+    ///
+    /// * per_window - Number of errors emitted at full rate within `window`.
+    /// * window - Length of the window, after which the rate resets.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `per_window` is 0.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::time::Duration;
+    ///
+    /// use mm_errors::Error;
+    /// use mm_errors::rate::Sampler;
+    ///
+    /// let sampler = Sampler::adaptive(2, Duration::from_secs(60));
+    /// let e = Error::new("connection refused", "db.rs", 1);
+    ///
+    /// let rates: Vec<_> = (0..8).filter_map(|_| sampler.sample(&e)).collect();
+    /// assert_eq!(rates, [1, 1, 2, 4]);
+    /// ```
+    ///
+    pub fn adaptive(per_window: u64, window: Duration) -> Sampler {
+        assert!(per_window > 0, "the number of errors per window must be positive");
+        Sampler::with_mode(Mode::Adaptive { per_window, window })
+    }
+
+    fn with_mode(mode: Mode) -> Sampler {
+        Sampler { mode, counts: Mutex::new(HashMap::new()) }
+    }
+
+    /// Records an occurrence of `e`, and returns the sample rate if it should be emitted.
+    pub fn sample(&self, e: &Error) -> Option<u64> {
+        let now = Instant::now();
+        let mut counts = self.counts.lock().unwrap_or_else(|e| e.into_inner());
+        let count = counts.entry(e.fingerprint()).or_insert(Count { start: now, count: 0 });
+        match self.mode {
+            Mode::OneIn(n) => {
+                count.count += 1;
+                if (count.count - 1).is_multiple_of(n) { Some(n) } else { None }
+            }
+            Mode::Adaptive { per_window, window } => {
+                if now.duration_since(count.start) > window {
+                    *count = Count { start: now, count: 0 };
+                }
+                count.count += 1;
+                let rate = ((count.count - 1) / per_window + 1).next_power_of_two();
+                if count.count.is_multiple_of(rate) || rate == 1 { Some(rate) } else { None }
+            }
+        }
+    }
+
+    /// Forgets every occurrence.
+    pub fn reset(&self) {
+        self.counts.lock().unwrap_or_else(|e| e.into_inner()).clear();
+    }
+}
+
+static SAMPLER: RwLock<Option<Arc<Sampler>>> = RwLock::new(None);
+
+/// Sets the sampler of the reporters and the log integrations.
+///
+/// By default, every error is emitted. The setting is global and applies to
+/// all threads.
+///
+/// # Arguments
+///
+/// * sampler - Sampler, or `None` to emit every error.
+///
+pub fn set_sampler(sampler: Option<Sampler>) {
+    *SAMPLER.write().unwrap_or_else(|e| e.into_inner()) = sampler.map(Arc::new);
+}
+
+/// Returns the sample rate of `e` if it should be emitted, as decided by the global sampler.
+pub(crate) fn sample(e: &Error) -> Option<u64> {
+    let sampler = SAMPLER.read().unwrap_or_else(|e| e.into_inner()).clone();
+    match sampler {
+        Some(sampler) => sampler.sample(e),
+        None => Some(1),
+    }
+}
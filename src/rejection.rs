@@ -0,0 +1,129 @@
+//! Rejecting requests of warp with errors.
+//!
+//! `Error` implements `warp::reject::Reject`, so it converts into
+//! `warp::Rejection` and handlers can return errors with `?`. Recover the
+//! rejections with `recover`, which passes each error to `hooks::report` and
+//! replies with its Problem Details, safe to return to clients. The whole
+//! chain, with files, lines, inner messages and metadata, is replied only by
+//! `recover_with_chain`, e.g. for internal services.
+//!
+//! This module is available with the `warp` feature.
+//!
+//! # Examples
+//!
+//! ```
+//! use warp::Filter;
+//!
+//! use mm_errors::{hooks, rejection, Error};
+//! use mm_errors::catalog::{self, CodeInfo};
+//!
+//! async fn order(id: u32) -> Result<String, warp::Rejection> {
+//!     Err(Error::here(&format!("no order {}", id)).with_code("WRP404"))?
+//! }
+//!
+//! # futures::executor::block_on(async {
+//! catalog::register(CodeInfo::new("WRP404", "Order not found").http_status(404)).unwrap();
+//! hooks::set_report_hook(|_: &Error| {});
+//!
+//! let api = warp::path!("orders" / u32).and_then(order).recover(rejection::recover);
+//!
+//! let response = warp::test::request().path("/orders/7").reply(&api).await;
+//! assert_eq!(response.status(), 404);
+//! assert_eq!(response.headers()["content-type"], "application/problem+json");
+//! let body = std::str::from_utf8(response.body()).unwrap();
+//! assert!(body.contains("\"code\":\"WRP404\""));
+//! assert!(!body.contains(file!()));
+//!
+//! let api = warp::path!("orders" / u32).and_then(order).recover(rejection::recover_with_chain);
+//!
+//! let response = warp::test::request().path("/orders/7").reply(&api).await;
+//! assert_eq!(response.headers()["content-type"], "application/json");
+//! let e = Error::from_json(std::str::from_utf8(response.body()).unwrap()).unwrap();
+//! assert_eq!(e.code.as_deref(), Some("WRP404"));
+//! # hooks::reset_report_hook();
+//! # });
+//! ```
+
+use warp::http::{header, StatusCode};
+use warp::reject::Reject;
+use warp::reply::{with_header, with_status, Reply, Response};
+use warp::Rejection;
+
+use super::{hooks, Error};
+
+/// Makes `Error` a custom rejection, which warp also converts into `Rejection` with `?`.
+impl Reject for Error {}
+
+/// Replies to a rejection holding an `Error`, for `warp::Filter::recover`.
+///
+/// The error is passed to `hooks::report`, and replied as by `reply`. Other
+/// rejections are returned as they are, to be handled by warp.
+///
+/// # Arguments
+///
+/// * rejection - Rejection of the filter.
+///
+/// # Errors
+///
+/// Returns `rejection` if it doesn't hold an `Error`.
+///
+pub async fn recover(rejection: Rejection) -> Result<Response, Rejection> {
+    match rejection.find::<Error>() {
+        Some(e) => Ok(reply(e)),
+        None => Err(rejection),
+    }
+}
+
+/// Replies to a rejection holding an `Error` with the whole chain, as `reply_with_chain` does.
+///
+/// Use it only for trusted clients, as the chain holds internal details.
+///
+/// # Errors
+///
+/// Returns `rejection` if it doesn't hold an `Error`.
+///
+pub async fn recover_with_chain(rejection: Rejection) -> Result<Response, Rejection> {
+    match rejection.find::<Error>() {
+        Some(e) => Ok(reply_with_chain(e)),
+        None => Err(rejection),
+    }
+}
+
+/// Reports `e`, and returns its reply, for handlers replying to errors themselves.
+///
+/// The body is written by `Error::to_problem_details`, and the status is
+/// `Error::http_status`, or 500 if the codes of the chain have none.
+///
+/// # Examples
+///
+/// ```
+/// use mm_errors::{hooks, rejection, Error};
+///
+/// # hooks::set_report_hook(|_: &Error| {});
+/// let response = rejection::reply(&Error::here("disk full"));
+/// assert_eq!(response.status(), 500);
+/// assert_eq!(response.headers()["content-type"], "application/problem+json");
+/// # hooks::reset_report_hook();
+/// ```
+///
+pub fn reply(e: &Error) -> Response {
+    hooks::report(e);
+    respond(e, e.to_problem_details(), "application/problem+json")
+}
+
+/// Reports `e`, and returns a reply with the whole chain, written by `Error::to_json`.
+///
+/// The status is as in `reply`. Use it only for trusted clients, as the chain
+/// holds internal details.
+///
+pub fn reply_with_chain(e: &Error) -> Response {
+    hooks::report(e);
+    respond(e, e.to_json(), "application/json")
+}
+
+fn respond(e: &Error, body: String, content_type: &'static str) -> Response {
+    let status = e.http_status()
+        .and_then(|status| StatusCode::from_u16(status).ok())
+        .unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+    with_status(with_header(body, header::CONTENT_TYPE, content_type), status).into_response()
+}
@@ -0,0 +1,118 @@
+//! Error type for errors received from other processes.
+
+use std::error;
+use std::fmt;
+use std::fmt::Write;
+
+use super::{render, Error, Result, XmlText, SCHEMA_VERSION};
+
+/// Holds an error received from another process, and where it came from.
+///
+/// Wrap a deserialized error in a `RemoteError` before wrapping it with local
+/// errors, so the chain shows where the process boundary is. `Display`
+/// renders it as a `<remote>` element with `host` and `service` attributes,
+/// holding the `<error>` element of the remote chain. `Error::to_json` writes
+/// it as an object with `host`, `service` and `error` members.
+///
+/// # Examples
+///
+/// ```
+/// use mm_errors::{Error, RemoteError};
+///
+/// let sent = Error::new("disk full", "storage.rs", 7).to_json();
+///
+/// let remote = RemoteError::from_json("db-1", "storage", &sent).unwrap();
+/// let e = Error::context(remote, "saving the order", "orders.rs", 12);
+///
+/// assert!(e.to_string().contains("<reason><remote host=\"db-1\" service=\"storage\"><error>"));
+/// assert_eq!(e.remote().unwrap().service, "storage");
+/// assert_eq!(Error::from_xml(&e.to_string()).unwrap().to_string(), e.to_string());
+/// assert_eq!(Error::from_json(&e.to_json()).unwrap().to_json(), e.to_json());
+/// ```
+///
+#[derive(Debug, Clone)]
+pub struct RemoteError {
+    /// Name of the host where the error occurred.
+    pub host: String,
+
+    /// Name of the process or service where the error occurred.
+    pub service: String,
+
+    /// Chain of errors of the remote process.
+    pub error: Error,
+}
+
+impl RemoteError {
+    /// Returns a new instance of `RemoteError`.
+    ///
+    /// # Arguments
+    ///
+    /// * host - Name of the host where the error occurred.
+    /// * service - Name of the process or service where the error occurred.
+    /// * error - Chain of errors of the remote process.
+    ///
+    pub fn new<H, S>(host: H, service: S, error: Error) -> RemoteError
+        where H: Into<String>, S: Into<String> {
+        RemoteError {
+            host: host.into(),
+            service: service.into(),
+            error,
+        }
+    }
+
+    /// Parses the JSON written by `Error::to_json` in the remote process.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `Error::from_json` fails to parse `s`.
+    ///
+    pub fn from_json<H, S>(host: H, service: S, s: &str) -> Result<RemoteError>
+        where H: Into<String>, S: Into<String> {
+        Ok(RemoteError::new(host, service, Error::from_json(s)?))
+    }
+
+    /// Parses the XML written by `Display` in the remote process.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `Error::from_xml` fails to parse `s`.
+    ///
+    pub fn from_xml<H, S>(host: H, service: S, s: &str) -> Result<RemoteError>
+        where H: Into<String>, S: Into<String> {
+        Ok(RemoteError::new(host, service, Error::from_xml(s)?))
+    }
+
+    pub(crate) fn format_xml(&self, f: &mut fmt::Formatter, root: bool) -> fmt::Result {
+        if root {
+            write!(f, "<remote schema=\"{}\" version=\"{}\" host=\"", SCHEMA_VERSION, render::crate_version())?;
+        } else {
+            write!(f, "<remote host=\"")?;
+        }
+        write!(XmlText(f), "{}", self.host)?;
+        write!(f, "\" service=\"")?;
+        write!(XmlText(f), "{}", self.service)?;
+        write!(f, "\">")?;
+        self.error.format_xml(f, false)?;
+        write!(f, "</remote>")
+    }
+}
+
+impl error::Error for RemoteError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+impl fmt::Display for RemoteError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.error.mark_reported();
+        self.format_xml(f, true)
+    }
+}
+
+impl Error {
+    /// Returns the outermost `RemoteError` in the chain, if any.
+    pub fn remote(&self) -> Option<&RemoteError> {
+        self.chain().find_map(|e| e.downcast_ref::<RemoteError>())
+    }
+}
@@ -0,0 +1,255 @@
+//! Settings for rendering errors.
+
+use std::borrow::Cow;
+use std::cell::Cell;
+use std::fmt;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::RwLock;
+
+/// How control characters (newlines, tabs, ANSI escape sequences, ...) in
+/// messages and file names are rendered.
+///
+/// Passing control characters through untouched allows a message to forge
+/// additional log lines or terminal output, so they are escaped by default.
+///
+/// `Debug` output always escapes control characters, regardless of this setting.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ControlChars {
+    /// Replaces control characters with escape sequences such as `\n` or `\u{1b}`.
+    Escape,
+
+    /// Removes control characters.
+    Strip,
+
+    /// Writes control characters as they are.
+    Keep,
+}
+
+static CONTROL_CHARS: AtomicUsize = AtomicUsize::new(0);
+
+/// Sets how control characters are rendered.
+///
+/// The setting is global and applies to all threads.
+///
+/// # Examples
+///
+/// ```
+/// use mm_errors::Error;
+/// use mm_errors::render::{self, ControlChars};
+///
+/// let e = Error::new("line1\nline2", "lib.rs", 1);
+/// assert!(e.to_string().contains("<reason>line1\\nline2</reason>"));
+///
+/// render::set_control_chars(ControlChars::Strip);
+/// assert!(e.to_string().contains("<reason>line1line2</reason>"));
+/// # render::set_control_chars(ControlChars::Escape);
+/// ```
+///
+pub fn set_control_chars(policy: ControlChars) {
+    let v = match policy {
+        ControlChars::Escape => 0,
+        ControlChars::Strip => 1,
+        ControlChars::Keep => 2,
+    };
+    CONTROL_CHARS.store(v, Ordering::Relaxed);
+}
+
+/// Returns how control characters are rendered.
+pub fn control_chars() -> ControlChars {
+    match CONTROL_CHARS.load(Ordering::Relaxed) {
+        0 => ControlChars::Escape,
+        1 => ControlChars::Strip,
+        _ => ControlChars::Keep,
+    }
+}
+
+/// Writes the control character `c` according to the current setting.
+pub(crate) fn write_control(w: &mut dyn fmt::Write, c: char) -> fmt::Result {
+    match control_chars() {
+        ControlChars::Escape => write!(w, "{}", c.escape_debug()),
+        ControlChars::Strip => Ok(()),
+        ControlChars::Keep => w.write_char(c),
+    }
+}
+
+static DETERMINISTIC: AtomicBool = AtomicBool::new(false);
+
+thread_local! {
+    /// Whether deterministic output is enabled for the current thread only.
+    static DETERMINISTIC_HERE: Cell<bool> = const { Cell::new(false) };
+
+    /// Whether secrets are written on the current thread.
+    static UNREDACTED: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Sets whether volatile values are normalized in the output.
+///
+/// When enabled, every file is rendered as `[file]` and every line number as `0`,
+/// in both `Display` and `Debug` output, so snapshot tests of error messages
+/// don't break whenever code moves. The crate version is rendered as
+/// `[version]`, and other volatile values, such as durations attached by
+/// `timing::timed`, as `[volatile]`.
+///
+/// The setting is global and applies to all threads, so enable it once for the
+/// whole test binary rather than per test.
+///
+/// # Examples
+///
+/// ```
+/// use mm_errors::Error;
+/// use mm_errors::render;
+///
+/// render::set_deterministic(true);
+///
+/// let e = Error::new("Stack underflow!", file!(), line!());
+/// assert_eq!(
+///     e.to_string(),
+///     "<error schema=\"2\" version=\"[version]\"><file>[file]</file><line>0</line><reason>Stack underflow!</reason></error>");
+/// # render::set_deterministic(false);
+/// ```
+///
+pub fn set_deterministic(enabled: bool) {
+    DETERMINISTIC.store(enabled, Ordering::Relaxed);
+}
+
+/// Returns whether volatile values are normalized in the output.
+pub fn is_deterministic() -> bool {
+    DETERMINISTIC.load(Ordering::Relaxed) || DETERMINISTIC_HERE.with(Cell::get)
+}
+
+/// Runs `f` with deterministic output enabled for the current thread only.
+pub(crate) fn deterministic<F, T>(f: F) -> T
+    where F: FnOnce() -> T {
+    struct Restore(bool);
+
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            DETERMINISTIC_HERE.with(|d| d.set(self.0));
+        }
+    }
+
+    let _restore = Restore(DETERMINISTIC_HERE.with(|d| d.replace(true)));
+    f()
+}
+
+static PATH_ROOT: RwLock<Option<String>> = RwLock::new(None);
+
+/// Sets the directory which rendered file paths are relative to.
+///
+/// `file!()` yields paths relative to the directory where the compiler was
+/// invoked, which differs between local builds, CI and vendored builds, and
+/// can be absolute. With a root set, paths are rendered as follows:
+///
+/// * Absolute paths inside `root` are rendered relative to `root`.
+/// * Relative paths starting with the trailing components of `root` have those
+///   components removed, e.g. `crates/app/src/main.rs` is rendered as
+///   `src/main.rs` for the root `/work/crates/app`, and is left as it is for
+///   the workspace root `/work`.
+/// * Other paths are rendered as they are.
+///
+/// Backslashes are rendered as slashes. `Error::file` itself is not changed.
+///
+/// Pass `env!("CARGO_MANIFEST_DIR")` for paths relative to the package, or the
+/// workspace root for paths relative to the workspace. The setting is global
+/// and applies to all threads.
+///
+/// # Arguments
+///
+/// * root - Root directory, or `None` to render paths as they are.
+///
+/// # Examples
+///
+/// ```
+/// use mm_errors::Error;
+/// use mm_errors::render;
+///
+/// render::set_path_root(Some("/work/crates/app"));
+///
+/// let e = Error::new("failed", "/work/crates/app/src/main.rs", 1);
+/// assert!(e.to_string().contains("<file>src/main.rs</file>"));
+///
+/// let e = Error::new("failed", "crates/app/src/lib.rs", 1);
+/// assert!(e.to_string().contains("<file>src/lib.rs</file>"));
+/// # render::set_path_root(None::<&str>);
+/// ```
+///
+pub fn set_path_root<P: AsRef<Path>>(root: Option<P>) {
+    let root = root.map(|p| normalize_separators(&p.as_ref().to_string_lossy()).trim_end_matches('/').to_string());
+    *PATH_ROOT.write().unwrap_or_else(|e| e.into_inner()) = root;
+}
+
+/// Returns the directory which rendered file paths are relative to.
+pub fn path_root() -> Option<String> {
+    PATH_ROOT.read().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+fn normalize_separators(path: &str) -> Cow<'_, str> {
+    if path.contains('\\') {
+        Cow::Owned(path.replace('\\', "/"))
+    } else {
+        Cow::Borrowed(path)
+    }
+}
+
+/// Returns `file` relative to `root`, as described in `set_path_root`.
+fn relative_path<'a>(file: &'a str, root: &str) -> Cow<'a, str> {
+    let file = normalize_separators(file);
+    if let Some(rest) = file.strip_prefix(root).and_then(|rest| rest.strip_prefix('/')) {
+        return Cow::Owned(rest.to_string());
+    }
+    if !file.starts_with('/') && !file.contains(':') {
+        // The longest trailing components of `root` which `file` starts with.
+        let mut suffix = root;
+        while let Some(i) = suffix.find('/') {
+            suffix = &suffix[i + 1..];
+            if let Some(rest) = file.strip_prefix(suffix).and_then(|rest| rest.strip_prefix('/')) {
+                if !suffix.is_empty() {
+                    return Cow::Owned(rest.to_string());
+                }
+            }
+        }
+    }
+    file
+}
+
+/// Returns the file and line to render, normalized if deterministic output is enabled.
+pub(crate) fn location(file: &str, line: u32) -> (Cow<'_, str>, u32) {
+    if is_deterministic() {
+        return (Cow::Borrowed("[file]"), 0);
+    }
+    match *PATH_ROOT.read().unwrap_or_else(|e| e.into_inner()) {
+        Some(ref root) => (relative_path(file, root), line),
+        None => (Cow::Borrowed(file), line),
+    }
+}
+
+/// Returns the crate version to write, normalized if deterministic output is enabled.
+pub(crate) fn crate_version() -> &'static str {
+    if is_deterministic() {
+        "[version]"
+    } else {
+        super::CRATE_VERSION
+    }
+}
+
+/// Returns whether secrets are written in place of `***`.
+pub(crate) fn is_unredacted() -> bool {
+    UNREDACTED.with(Cell::get)
+}
+
+/// Runs `f` with secrets written on the current thread.
+pub(crate) fn unredacted<F, T>(f: F) -> T
+    where F: FnOnce() -> T {
+    struct Restore(bool);
+
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            UNREDACTED.with(|u| u.set(self.0));
+        }
+    }
+
+    let _restore = Restore(UNREDACTED.with(|u| u.replace(true)));
+    f()
+}
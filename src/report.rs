@@ -0,0 +1,257 @@
+//! Human-readable rendering of errors.
+
+use std::fmt;
+use std::fmt::Write;
+
+use super::catalog::{self, Label};
+use super::{metadata, render, Error, MultiError, RemoteError};
+
+/// Human-readable rendering of an error, returned by `Error::report`.
+///
+/// The first line holds the message of the outermost error, prefixed with
+/// `error` and its code, and each following line the message of an inner
+/// error, prefixed with `caused by`. Levels of `ErrorKind::Wrapped` have no
+/// message of their own and are skipped. Messages and the labels of
+/// `catalog::Label` are translated to the locale set by `catalog::set_locale`
+/// where a translation is registered. If the outermost error having a code
+/// has a documentation URL, a last line points to it.
+///
+/// Control characters are handled as configured by `render::set_control_chars`.
+///
+/// # Examples
+///
+/// ```
+/// use mm_errors::Error;
+///
+/// let inner = "XXX".parse::<u32>().unwrap_err();
+/// let e = Error::wrap(Error::context(inner, "reading the port number", "config.rs", 3), "main.rs", 9);
+///
+/// assert_eq!(
+///     e.report().to_string(),
+///     "error: reading the port number\n\
+///      caused by: invalid digit found in string");
+/// ```
+///
+#[derive(Debug, Clone, Copy)]
+pub struct Report<'a> {
+    error: &'a Error,
+}
+
+impl<'a> fmt::Display for Report<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.error.mark_reported();
+        for (i, level) in levels(self.error).iter().enumerate() {
+            if i == 0 {
+                write!(f, "{}", catalog::label(Label::Error))?;
+                write_code(f, self.error)?;
+                write!(f, ": ")?;
+            } else {
+                write!(f, "\n{}: ", catalog::label(Label::CausedBy))?;
+            }
+            write!(PlainText(f), "{}", level.message)?;
+            for inner in &level.inner {
+                write!(f, "\n  - ")?;
+                write!(PlainText(f), "{}", inner)?;
+            }
+        }
+        if let Some(url) = docs_url(self.error) {
+            write!(f, "\n{} ", catalog::label(Label::MoreInformation))?;
+            write!(PlainText(f), "{}", url)?;
+        }
+        Ok(())
+    }
+}
+
+/// Rendering of an error for terminals, written by `cli::report`.
+///
+/// This is `Report` in the style of cargo, down to the depth of `verbosity`
+/// as described in `cli::render_at`: the `caused by` lines are indented,
+/// `error` is red if `color`, `Error::help` and the documentation URL are
+/// `help` lines, and the backtrace or a hint to enable it follows.
+pub(crate) struct CliReport<'a> {
+    pub(crate) error: &'a Error,
+    pub(crate) color: bool,
+    pub(crate) verbosity: u8,
+    pub(crate) backtrace_hint: bool,
+}
+
+impl<'a> fmt::Display for CliReport<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.error.mark_reported();
+        let levels = levels(self.error);
+        let shown = if self.verbosity == 0 { 1 } else { levels.len() };
+        for (i, level) in levels.iter().take(shown).enumerate() {
+            let indent = if i == 0 { "  " } else { "    " };
+            if i == 0 {
+                if self.color {
+                    write!(f, "\x1b[1;31m{}\x1b[0m", catalog::label(Label::Error))?;
+                } else {
+                    write!(f, "{}", catalog::label(Label::Error))?;
+                }
+                write_code(f, self.error)?;
+                write!(f, ": ")?;
+            } else {
+                write!(f, "\n  {}: ", catalog::label(Label::CausedBy))?;
+            }
+            write!(PlainText(f), "{}", level.message)?;
+            if self.verbosity >= 2 {
+                if let Some((ref file, line)) = level.location {
+                    write!(f, "\n{}at ", indent)?;
+                    write!(PlainText(f), "{}:{}", file, line)?;
+                }
+                for (key, value) in &level.context {
+                    write!(f, "\n{}with ", indent)?;
+                    write!(PlainText(f), "{} = {}", key, value)?;
+                }
+            }
+            for inner in &level.inner {
+                write!(f, "\n{}- ", indent)?;
+                write!(PlainText(f), "{}", inner)?;
+            }
+        }
+        for help in self.error.help().into_iter().flat_map(str::lines) {
+            write!(f, "\n{}: ", catalog::label(Label::Help))?;
+            write!(PlainText(f), "{}", help)?;
+        }
+        if let Some(url) = docs_url(self.error) {
+            write!(f, "\n{}: {} ", catalog::label(Label::Help), catalog::label(Label::MoreInformation))?;
+            write!(PlainText(f), "{}", url)?;
+        }
+        if self.verbosity >= 3 {
+            match self.error.backtrace() {
+                Some(backtrace) => write!(f, "\n\nstack backtrace:\n{}", backtrace)?,
+                None if self.backtrace_hint => {
+                    write!(f, "\nnote: run with `RUST_BACKTRACE=1` environment variable to display a backtrace")?
+                }
+                None => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Level of the chain of an error, as rendered by `Report`.
+struct Level {
+    message: String,
+
+    /// First messages of the errors of a `MultiError`.
+    inner: Vec<String>,
+
+    /// File and line, if the level is an `Error`.
+    location: Option<(String, u32)>,
+
+    /// Keys and rendered values of the metadata.
+    context: Vec<(String, String)>,
+}
+
+/// Returns each level of the chain of `e` having a message.
+///
+/// Levels of `ErrorKind::Wrapped` have no message of their own, so their
+/// location is given to the next level if it is not an `Error`.
+fn levels(e: &Error) -> Vec<Level> {
+    let mut wrapper = None;
+    e.chain()
+        .filter_map(|e| {
+            let (message, located) = match e.downcast_ref::<Error>() {
+                Some(e) => match (e.localized_message(), e.message()) {
+                    (Some(message), _) => (message, Some(e)),
+                    (None, Some(message)) => (message.to_string(), Some(e)),
+                    (None, None) => {
+                        wrapper = Some(e);
+                        return None;
+                    }
+                },
+                None => match (e.downcast_ref::<MultiError>(), e.downcast_ref::<RemoteError>()) {
+                    (Some(errors), _) => (format!("{} errors occurred", errors.len()), None),
+                    (None, Some(remote)) => (format!("error in {} on {}", remote.service, remote.host), None),
+                    (None, None) => (e.to_string(), None),
+                },
+            };
+            let located = located.or(wrapper);
+            wrapper = None;
+            let inner = match e.downcast_ref::<MultiError>() {
+                Some(errors) => errors.iter().map(first_message).collect(),
+                None => Vec::new(),
+            };
+            let location = located.map(|e| {
+                let (file, line) = render::location(&e.file, e.line);
+                (file.into_owned(), line)
+            });
+            let context = located
+                .map(|e| {
+                    e.metadata.iter()
+                        .filter(|&(key, _)| key != metadata::HELP)
+                        .map(|(key, value)| (key.to_string(), value.to_string()))
+                        .collect()
+                })
+                .unwrap_or_default();
+            Some(Level { message, inner, location, context })
+        })
+        .collect()
+}
+
+/// Writes the code of `e` in brackets, if it has one.
+fn write_code(f: &mut fmt::Formatter, e: &Error) -> fmt::Result {
+    if let Some(code) = e.code.as_ref() {
+        write!(f, "[")?;
+        write!(PlainText(f), "{}", code)?;
+        write!(f, "]")?;
+    }
+    Ok(())
+}
+
+/// Returns the first message in the chain of `e`.
+pub(crate) fn first_message(e: &Error) -> String {
+    e.chain()
+        .map(|e| match e.downcast_ref::<Error>() {
+            Some(e) => e.message().map(str::to_string),
+            None => Some(e.to_string()),
+        })
+        .find_map(|m| m)
+        .unwrap_or_default()
+}
+
+/// Returns the message of the innermost error of the chain of `e`.
+#[cfg(any(feature = "slog", feature = "log-kv"))]
+pub(crate) fn root_message(e: &Error) -> String {
+    let root = e.root_cause();
+    match root.downcast_ref::<Error>().and_then(Error::message) {
+        Some(message) => message.to_string(),
+        None => root.to_string(),
+    }
+}
+
+/// Returns the documentation URL of the outermost error in the chain of `e` having a code.
+fn docs_url(e: &Error) -> Option<String> {
+    e.chain()
+        .filter_map(|e| e.downcast_ref::<Error>())
+        .find_map(|e| e.code.as_ref())
+        .and_then(|code| catalog::docs_url(code))
+}
+
+/// Handles control characters written through it as configured by `render::set_control_chars`.
+struct PlainText<'a, 'b: 'a>(&'a mut fmt::Formatter<'b>);
+
+impl<'a, 'b> fmt::Write for PlainText<'a, 'b> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let mut start = 0;
+        for (i, c) in s.char_indices() {
+            if c.is_control() {
+                self.0.write_str(&s[start..i])?;
+                render::write_control(self.0, c)?;
+                start = i + c.len_utf8();
+            }
+        }
+        self.0.write_str(&s[start..])
+    }
+}
+
+impl Error {
+    /// Returns a human-readable rendering of `self`, for end users.
+    ///
+    /// See `Report` for the format.
+    ///
+    pub fn report(&self) -> Report<'_> {
+        Report { error: self }
+    }
+}
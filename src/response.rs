@@ -0,0 +1,83 @@
+//! HTTP responses of errors, for servers without a framework, e.g. on raw hyper.
+//!
+//! This module is available with the `http` feature.
+
+use http::{header, HeaderValue, Response, StatusCode};
+
+use super::Error;
+
+/// Format of the bodies of error responses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Renderer {
+    /// `Error::to_problem_details`, as `application/problem+json`.
+    #[default]
+    ProblemDetails,
+
+    /// `Error::error_body`, as `application/json`.
+    ErrorBody,
+
+    /// `Error::to_json`, as `application/json`.
+    ///
+    /// This writes the whole chain with files and lines, so use it for
+    /// internal services only.
+    Json,
+}
+
+impl Renderer {
+    /// Returns the content type of the bodies.
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Renderer::ProblemDetails => "application/problem+json",
+            Renderer::ErrorBody | Renderer::Json => "application/json",
+        }
+    }
+
+    /// Returns the body of `e`.
+    pub fn render(self, e: &Error) -> String {
+        match self {
+            Renderer::ProblemDetails => e.to_problem_details(),
+            Renderer::ErrorBody => e.error_body().to_json(),
+            Renderer::Json => e.to_json(),
+        }
+    }
+}
+
+impl Error {
+    /// Returns an HTTP response of `self`.
+    ///
+    /// The status is `Error::http_status`, or 500 if the codes of the chain
+    /// have none, and the body is written by `renderer`. Any body type built
+    /// from a `String` can be used, e.g. `http_body_util::Full<Bytes>` of
+    /// hyper.
+    ///
+    /// # Arguments
+    ///
+    /// * renderer - Format of the body.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mm_errors::Error;
+    /// use mm_errors::catalog::{self, CodeInfo};
+    /// use mm_errors::response::Renderer;
+    ///
+    /// catalog::register(CodeInfo::new("RSP404", "Order not found").http_status(404)).unwrap();
+    ///
+    /// let e = Error::here("no order 7").with_code("RSP404");
+    /// let response: http::Response<String> = e.to_response(Renderer::ErrorBody);
+    ///
+    /// assert_eq!(response.status(), 404);
+    /// assert_eq!(response.headers()["content-type"], "application/json");
+    /// assert!(response.body().starts_with("{\"error\":{\"code\":\"RSP404\""));
+    /// ```
+    ///
+    pub fn to_response<B: From<String>>(&self, renderer: Renderer) -> Response<B> {
+        let status = self.http_status()
+            .and_then(|status| StatusCode::from_u16(status).ok())
+            .unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+        let mut response = Response::new(B::from(renderer.render(self)));
+        *response.status_mut() = status;
+        response.headers_mut().insert(header::CONTENT_TYPE, HeaderValue::from_static(renderer.content_type()));
+        response
+    }
+}
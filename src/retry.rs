@@ -0,0 +1,776 @@
+//! Policies for retrying fallible operations.
+
+use std::collections::hash_map::RandomState;
+use std::error;
+use std::fmt;
+use std::hash::{BuildHasher, Hasher};
+use std::io;
+use std::marker;
+use std::panic;
+use std::result;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::{catalog, metadata, Error, MultiError, Result};
+
+/// Whether a failed operation may succeed if retried.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RetryClass {
+    /// The failure is temporary, e.g. a timeout, and a retry may succeed.
+    Transient,
+
+    /// The failure will happen again, e.g. invalid input, so don't retry.
+    Permanent,
+
+    /// The caller is being rate limited, so retry only after backing off.
+    Throttled,
+}
+
+impl fmt::Display for RetryClass {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            RetryClass::Transient => "transient",
+            RetryClass::Permanent => "permanent",
+            RetryClass::Throttled => "throttled",
+        })
+    }
+}
+
+impl Error {
+    /// Returns `self` with its retry class set.
+    ///
+    /// The class is attached under `metadata::RETRY_CLASS`, and takes precedence
+    /// over the class of any code in the chain.
+    ///
+    pub fn with_retry_class(self, class: RetryClass) -> Error {
+        self.with_meta_keyed(metadata::RETRY_CLASS, class)
+    }
+
+    /// Returns `self` marked as transient, so retrying may succeed.
+    ///
+    /// This is `with_retry_class(RetryClass::Transient)`. The marker is kept
+    /// when `self` is wrapped, so mark the error where the cause is known.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mm_errors::Error;
+    /// use mm_errors::retry::RetryClass;
+    ///
+    /// let e = Error::wrap(Error::here("lock timeout").mark_transient(), "service.rs", 1);
+    /// assert_eq!(e.retry_class(), Some(RetryClass::Transient));
+    /// ```
+    ///
+    pub fn mark_transient(self) -> Error {
+        self.with_retry_class(RetryClass::Transient)
+    }
+
+    /// Returns `self` marked as permanent, so retrying won't succeed.
+    ///
+    /// This is `with_retry_class(RetryClass::Permanent)`, and is kept when
+    /// `self` is wrapped like `mark_transient`.
+    ///
+    pub fn mark_permanent(self) -> Error {
+        self.with_retry_class(RetryClass::Permanent)
+    }
+
+    /// Returns the retry class of `self`.
+    ///
+    /// This is the class set by `with_retry_class` on the outermost error in
+    /// the chain having one, or else the class registered for the outermost
+    /// code in the chain having one.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mm_errors::Error;
+    /// use mm_errors::catalog::{self, CodeInfo};
+    /// use mm_errors::retry::RetryClass;
+    ///
+    /// catalog::register(CodeInfo::new("API429", "Too many requests").retry_class(RetryClass::Throttled)).unwrap();
+    ///
+    /// let e = Error::wrap(Error::here("rate limited").with_code("API429"), "client.rs", 1);
+    /// assert_eq!(e.retry_class(), Some(RetryClass::Throttled));
+    ///
+    /// let e = e.with_retry_class(RetryClass::Permanent);
+    /// assert_eq!(e.retry_class(), Some(RetryClass::Permanent));
+    /// ```
+    ///
+    pub fn retry_class(&self) -> Option<RetryClass> {
+        let errors = || self.chain().filter_map(|e| e.downcast_ref::<Error>());
+        errors()
+            .find_map(|e| e.metadata.get_keyed::<RetryClass>(metadata::RETRY_CLASS).cloned())
+            .or_else(|| {
+                errors()
+                    .filter_map(|e| e.code.as_ref())
+                    .find_map(|code| catalog::lookup(code).and_then(|info| info.retry_class))
+            })
+    }
+
+    /// Returns `self` with the time to wait before retrying set.
+    ///
+    /// This is the backpressure hint of the failed service, such as the
+    /// `Retry-After` header of an HTTP 429 response; see `parse_retry_after`.
+    /// The `Duration` is attached under `metadata::RETRY_AFTER`, and `retry`
+    /// waits at least that long before the next attempt.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::time::Duration;
+    ///
+    /// use mm_errors::Error;
+    /// use mm_errors::retry;
+    ///
+    /// let header = "120";
+    /// let e = Error::here("too many requests");
+    /// let e = match retry::parse_retry_after(header) {
+    ///     Some(after) => e.with_retry_after(after),
+    ///     None => e,
+    /// };
+    ///
+    /// let e = Error::wrap(e, "client.rs", 1);
+    /// assert_eq!(e.retry_after(), Some(Duration::from_secs(120)));
+    /// ```
+    ///
+    pub fn with_retry_after(self, after: Duration) -> Error {
+        self.with_meta_keyed(metadata::RETRY_AFTER, after)
+    }
+
+    /// Returns the time to wait before retrying, set on the outermost error in
+    /// the chain having one.
+    pub fn retry_after(&self) -> Option<Duration> {
+        self.chain()
+            .filter_map(|e| e.downcast_ref::<Error>())
+            .find_map(|e| e.metadata.get_keyed::<Duration>(metadata::RETRY_AFTER).cloned())
+    }
+
+    /// Returns `true` if the operation failing with `self` may succeed if retried.
+    ///
+    /// The retry class returned by `retry_class` decides, if there is one.
+    /// Otherwise cancelled errors are not retryable, and I/O errors in the
+    /// chain are retryable if their kind is one of `TimedOut`, `Interrupted`,
+    /// `WouldBlock`, `ConnectionReset`, `ConnectionAborted` or `BrokenPipe`.
+    /// Any other error is not retryable.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::io;
+    ///
+    /// use mm_errors::Error;
+    /// use mm_errors::retry::RetryClass;
+    ///
+    /// let e = Error::wrap(io::Error::from(io::ErrorKind::TimedOut), "client.rs", 1);
+    /// assert!(e.is_retryable());
+    /// assert!(!e.with_retry_class(RetryClass::Permanent).is_retryable());
+    ///
+    /// let e = Error::wrap(io::Error::from(io::ErrorKind::NotFound), "client.rs", 1);
+    /// assert!(!e.is_retryable());
+    /// ```
+    ///
+    pub fn is_retryable(&self) -> bool {
+        if let Some(class) = self.retry_class() {
+            return class != RetryClass::Permanent;
+        }
+        if self.is_cancelled() {
+            return false;
+        }
+        self.chain()
+            .filter_map(|e| e.downcast_ref::<io::Error>())
+            .any(|e| is_transient_io(e.kind()))
+    }
+}
+
+fn is_transient_io(kind: io::ErrorKind) -> bool {
+    matches!(kind,
+             io::ErrorKind::TimedOut
+             | io::ErrorKind::Interrupted
+             | io::ErrorKind::WouldBlock
+             | io::ErrorKind::ConnectionReset
+             | io::ErrorKind::ConnectionAborted
+             | io::ErrorKind::BrokenPipe)
+}
+
+/// Decides whether and when a failed operation is retried.
+///
+/// Any `FnMut(u32) -> Option<Duration>` is a `Backoff`.
+///
+/// # Examples
+///
+/// ```
+/// use std::time::Duration;
+///
+/// use mm_errors::retry::Backoff;
+///
+/// // Three attempts, 100ms apart.
+/// let mut backoff = |attempt: u32| if attempt < 3 { Some(Duration::from_millis(100)) } else { None };
+///
+/// assert_eq!(backoff.next_delay(1), Some(Duration::from_millis(100)));
+/// assert_eq!(backoff.next_delay(3), None);
+/// ```
+///
+pub trait Backoff {
+    /// Returns the delay before the next attempt, or `None` to give up.
+    ///
+    /// # Arguments
+    ///
+    /// * attempt - Number of the attempt which just failed, starting from 1.
+    ///
+    fn next_delay(&mut self, attempt: u32) -> Option<Duration>;
+}
+
+impl<F> Backoff for F
+    where F: FnMut(u32) -> Option<Duration> {
+    fn next_delay(&mut self, attempt: u32) -> Option<Duration> {
+        self(attempt)
+    }
+}
+
+/// Parses the value of a `Retry-After` header given in seconds.
+///
+/// Returns `None` if `value` is not a number of seconds, e.g. an HTTP date,
+/// which needs a clock to be converted into a delay.
+///
+/// # Arguments
+///
+/// * value - Value of the header.
+///
+/// # Examples
+///
+/// ```
+/// use std::time::Duration;
+///
+/// use mm_errors::retry;
+///
+/// assert_eq!(retry::parse_retry_after(" 30 "), Some(Duration::from_secs(30)));
+/// assert_eq!(retry::parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT"), None);
+/// ```
+///
+pub fn parse_retry_after(value: &str) -> Option<Duration> {
+    value.trim().parse::<u64>().ok().map(Duration::from_secs)
+}
+
+/// Policy retrying after the same delay every time.
+///
+/// # Examples
+///
+/// ```
+/// use std::time::Duration;
+///
+/// use mm_errors::retry::{Backoff, Fixed};
+///
+/// let mut backoff = Fixed::new(Duration::from_millis(100), 3);
+/// assert_eq!(backoff.next_delay(2), Some(Duration::from_millis(100)));
+/// assert_eq!(backoff.next_delay(3), None);
+/// ```
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Fixed {
+    delay: Duration,
+    attempts: u32,
+}
+
+impl Fixed {
+    /// Returns a new instance of `Fixed`.
+    ///
+    /// # Arguments
+    ///
+    /// * delay - Delay between attempts.
+    /// * attempts - Maximum number of attempts, including the first one.
+    ///
+    pub fn new(delay: Duration, attempts: u32) -> Fixed {
+        Fixed { delay, attempts }
+    }
+}
+
+impl Backoff for Fixed {
+    fn next_delay(&mut self, attempt: u32) -> Option<Duration> {
+        if attempt < self.attempts { Some(self.delay) } else { None }
+    }
+}
+
+/// Policy increasing the delay by the same step after every attempt.
+///
+/// # Examples
+///
+/// ```
+/// use std::time::Duration;
+///
+/// use mm_errors::retry::{Backoff, Linear};
+///
+/// let mut backoff = Linear::new(Duration::from_millis(100), Duration::from_millis(50), 4);
+/// assert_eq!(backoff.next_delay(1), Some(Duration::from_millis(100)));
+/// assert_eq!(backoff.next_delay(3), Some(Duration::from_millis(200)));
+/// assert_eq!(backoff.next_delay(4), None);
+/// ```
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Linear {
+    initial: Duration,
+    step: Duration,
+    attempts: u32,
+}
+
+impl Linear {
+    /// Returns a new instance of `Linear`.
+    ///
+    /// # Arguments
+    ///
+    /// * initial - Delay after the first attempt.
+    /// * step - Amount the delay grows by after every further attempt.
+    /// * attempts - Maximum number of attempts, including the first one.
+    ///
+    pub fn new(initial: Duration, step: Duration, attempts: u32) -> Linear {
+        Linear { initial, step, attempts }
+    }
+}
+
+impl Backoff for Linear {
+    fn next_delay(&mut self, attempt: u32) -> Option<Duration> {
+        if attempt >= self.attempts {
+            return None;
+        }
+        let step = self.step.checked_mul(attempt - 1).unwrap_or(Duration::MAX);
+        Some(self.initial.saturating_add(step))
+    }
+}
+
+/// Random variation of the delays of `Exponential`.
+///
+/// Without jitter, clients failing at the same time retry at the same time,
+/// and keep overloading the service they wait for.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Jitter {
+    /// Uses the delays as they are.
+    None,
+
+    /// Uses random delays between zero and the delay.
+    Full,
+
+    /// Uses random delays between half the delay and the delay.
+    Equal,
+}
+
+/// Policy doubling the delay after every attempt, up to a maximum.
+///
+/// The policy is configured by chaining the methods below, and can randomize
+/// the delays with `jitter`.
+///
+/// # Examples
+///
+/// ```
+/// use std::time::Duration;
+///
+/// use mm_errors::retry::{Backoff, Exponential, Jitter};
+///
+/// let mut backoff = Exponential::new(Duration::from_millis(100), 5).max_delay(Duration::from_millis(300));
+/// assert_eq!(backoff.next_delay(1), Some(Duration::from_millis(100)));
+/// assert_eq!(backoff.next_delay(2), Some(Duration::from_millis(200)));
+/// assert_eq!(backoff.next_delay(3), Some(Duration::from_millis(300)));
+/// assert_eq!(backoff.next_delay(5), None);
+///
+/// let mut backoff = Exponential::new(Duration::from_millis(100), 5).jitter(Jitter::Equal);
+/// let delay = backoff.next_delay(2).unwrap();
+/// assert!(delay >= Duration::from_millis(100) && delay <= Duration::from_millis(200));
+/// ```
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Exponential {
+    initial: Duration,
+    max_delay: Duration,
+    attempts: u32,
+    jitter: Jitter,
+
+    /// State of the random number generator, 0 if not seeded yet.
+    rng: u64,
+}
+
+impl Exponential {
+    /// Returns a new instance of `Exponential` without a maximum delay and jitter.
+    ///
+    /// # Arguments
+    ///
+    /// * initial - Delay after the first attempt.
+    /// * attempts - Maximum number of attempts, including the first one.
+    ///
+    pub fn new(initial: Duration, attempts: u32) -> Exponential {
+        Exponential {
+            initial,
+            max_delay: Duration::MAX,
+            attempts,
+            jitter: Jitter::None,
+            rng: 0,
+        }
+    }
+
+    /// Returns `self` with the delay limited to `max_delay`.
+    ///
+    /// The limit applies before the jitter.
+    ///
+    pub fn max_delay(mut self, max_delay: Duration) -> Exponential {
+        self.max_delay = max_delay;
+        self
+    }
+
+    /// Returns `self` with the delays randomized by `jitter`.
+    pub fn jitter(mut self, jitter: Jitter) -> Exponential {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Returns `self` with the random number generator seeded with `seed`.
+    ///
+    /// Without a seed, a random one is used. Seed it to make the delays reproducible.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::time::Duration;
+    ///
+    /// use mm_errors::retry::{Backoff, Exponential, Jitter};
+    ///
+    /// let backoff = Exponential::new(Duration::from_secs(1), 5).jitter(Jitter::Full);
+    /// let delays = |seed| {
+    ///     let mut backoff = backoff.seed(seed);
+    ///     (1..5).map(|attempt| backoff.next_delay(attempt)).collect::<Vec<_>>()
+    /// };
+    /// assert_eq!(delays(42), delays(42));
+    /// assert_ne!(delays(42), delays(43));
+    /// ```
+    ///
+    pub fn seed(mut self, seed: u64) -> Exponential {
+        self.rng = mix_seed(seed);
+        self
+    }
+
+    /// Returns a random number in `[0, 1)`.
+    fn random(&mut self) -> f64 {
+        if self.rng == 0 {
+            self.rng = RandomState::new().build_hasher().finish() | 1;
+        }
+        let mut x = self.rng;
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.rng = x;
+        (x >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+/// Returns the state of a xorshift generator seeded with `seed`.
+///
+/// The seed goes through a step of splitmix64 first, so close seeds give
+/// unrelated sequences, and the state is never 0, where xorshift gets stuck.
+///
+pub(crate) fn mix_seed(seed: u64) -> u64 {
+    let mut x = seed.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    match x ^ (x >> 31) {
+        0 => 0x9e37_79b9_7f4a_7c15,
+        x => x,
+    }
+}
+
+impl Backoff for Exponential {
+    fn next_delay(&mut self, attempt: u32) -> Option<Duration> {
+        if attempt >= self.attempts {
+            return None;
+        }
+        let delay = 2u32.checked_pow(attempt - 1)
+            .and_then(|factor| self.initial.checked_mul(factor))
+            .unwrap_or(Duration::MAX)
+            .min(self.max_delay);
+        Some(match self.jitter {
+            Jitter::None => delay,
+            Jitter::Full => scale(delay, self.random()),
+            Jitter::Equal => delay / 2 + scale(delay / 2, self.random()),
+        })
+    }
+}
+
+/// Returns `delay` multiplied by `factor` in `[0, 1)`, never more than `delay`.
+fn scale(delay: Duration, factor: f64) -> Duration {
+    // Large delays round up when converted to `f64`.
+    Duration::try_from_secs_f64(delay.as_secs_f64() * factor).map_or(delay, |d| d.min(delay))
+}
+
+/// Calls `f` until it succeeds or `backoff` gives up, sleeping between attempts.
+///
+/// Errors classified as `RetryClass::Permanent` are not retried, and the delay
+/// is extended to the `Error::retry_after` of the error if that is longer.
+/// The last error is returned with the context `attempt N failed; giving up`,
+/// located where this function is called. The number of attempts is attached under
+/// `metadata::ATTEMPTS`, and the total time under `metadata::ELAPSED`.
+///
+/// This blocks the current thread while sleeping; use `rt::retry` in async code.
+///
+/// # Arguments
+///
+/// * backoff - Policy deciding the delays between attempts.
+/// * f - Operation to run.
+///
+/// # Examples
+///
+/// ```
+/// use std::time::Duration;
+///
+/// use mm_errors::metadata;
+/// use mm_errors::retry::Fixed;
+///
+/// let e = mm_errors::retry(Fixed::new(Duration::from_millis(1), 3), || "XXX".parse::<u32>()).unwrap_err();
+/// assert_eq!(e.metadata.get_keyed::<u32>(metadata::ATTEMPTS), Some(&3));
+/// assert!(e.to_string().contains("<context>attempt 3 failed; giving up</context>"));
+///
+/// let mut attempts = 0;
+/// let n = mm_errors::retry(Fixed::new(Duration::from_millis(1), 3), || {
+///     attempts += 1;
+///     if attempts < 2 { "XXX".parse::<u32>() } else { "2".parse::<u32>() }
+/// });
+/// assert_eq!(n.unwrap(), 2);
+/// ```
+///
+#[track_caller]
+pub fn retry<B, F, T, E>(backoff: B, f: F) -> Result<T>
+    where B: Backoff,
+          F: FnMut() -> result::Result<T, E>,
+          E: Into<Box<dyn error::Error + marker::Send + marker::Sync>> {
+    retry_observed(backoff, |_: u32, _: &Error, _: Duration| {}, f)
+}
+
+/// Same as `retry`, but calls `observer` for every failed attempt which is retried.
+///
+/// # Arguments
+///
+/// * backoff - Policy deciding the delays between attempts.
+/// * observer - Observer of the failed attempts, e.g. for logging or metrics.
+/// * f - Operation to run.
+///
+/// # Examples
+///
+/// ```
+/// use std::time::Duration;
+///
+/// use mm_errors::Error;
+/// use mm_errors::retry::{self, Fixed};
+///
+/// let mut retried = Vec::new();
+/// let observer = |attempt: u32, _: &Error, delay: Duration| retried.push((attempt, delay));
+/// let r = retry::retry_observed(Fixed::new(Duration::from_millis(1), 3), observer, || "XXX".parse::<u32>());
+///
+/// assert!(r.is_err());
+/// assert_eq!(retried, vec![(1, Duration::from_millis(1)), (2, Duration::from_millis(1))]);
+/// ```
+///
+#[track_caller]
+pub fn retry_observed<B, O, F, T, E>(mut backoff: B, mut observer: O, mut f: F) -> Result<T>
+    where B: Backoff,
+          O: RetryObserver,
+          F: FnMut() -> result::Result<T, E>,
+          E: Into<Box<dyn error::Error + marker::Send + marker::Sync>> {
+    let location = panic::Location::caller();
+    let start = Instant::now();
+    let mut attempt = 1;
+    loop {
+        let e = match f() {
+            Ok(x) => return Ok(x),
+            Err(e) => into_error(e, location),
+        };
+        match next_delay(&mut backoff, &e, attempt) {
+            Some(delay) => {
+                observer.on_retry(attempt, &e, delay);
+                thread::sleep(delay);
+            }
+            None => return Err(gave_up(e, attempt, start.elapsed(), location)),
+        }
+        attempt += 1;
+    }
+}
+
+/// Observes the failed attempts of the retry helpers.
+///
+/// Any `FnMut(u32, &Error, Duration)` is a `RetryObserver`.
+///
+pub trait RetryObserver {
+    /// Called when an attempt failed and is about to be retried.
+    ///
+    /// # Arguments
+    ///
+    /// * attempt - Number of the attempt which just failed, starting from 1.
+    /// * e - Failure of the attempt.
+    /// * delay - Delay before the next attempt.
+    ///
+    fn on_retry(&mut self, attempt: u32, e: &Error, delay: Duration);
+}
+
+impl<F> RetryObserver for F
+    where F: FnMut(u32, &Error, Duration) {
+    fn on_retry(&mut self, attempt: u32, e: &Error, delay: Duration) {
+        self(attempt, e, delay)
+    }
+}
+
+/// Limit on the attempts and time spent retrying an operation.
+///
+/// A budget is created once per operation and passed, typically as
+/// `&mut RetryBudget`, to every place retrying a part of it, so all of them
+/// draw from the same limits. Every failure is recorded along with its
+/// attempt number, and the error returned when the budget is exhausted holds
+/// all of them.
+///
+/// # Examples
+///
+/// ```
+/// use mm_errors::MultiError;
+/// use mm_errors::metadata;
+/// use mm_errors::retry::RetryBudget;
+///
+/// let mut budget = RetryBudget::new(3);
+/// let e = loop {
+///     match "XXX".parse::<u32>() {
+///         Ok(n) => break Ok(n),
+///         Err(e) => {
+///             if let Err(e) = budget.record(e) {
+///                 break Err(e);
+///             }
+///         }
+///     }
+/// }.unwrap_err();
+///
+/// assert_eq!(e.metadata.get_keyed::<u32>(metadata::ATTEMPTS), Some(&3));
+/// let attempts = e.chain().find_map(|e| e.downcast_ref::<MultiError>()).unwrap();
+/// assert_eq!(attempts.len(), 3);
+/// ```
+///
+#[derive(Debug)]
+pub struct RetryBudget {
+    max_attempts: u32,
+    max_elapsed: Option<Duration>,
+    start: Instant,
+    errors: MultiError,
+}
+
+impl RetryBudget {
+    /// Returns a new instance of `RetryBudget` starting now.
+    ///
+    /// # Arguments
+    ///
+    /// * max_attempts - Maximum number of attempts, including the first one.
+    ///
+    pub fn new(max_attempts: u32) -> RetryBudget {
+        RetryBudget {
+            max_attempts,
+            max_elapsed: None,
+            start: Instant::now(),
+            errors: MultiError::new(),
+        }
+    }
+
+    /// Returns `self` with the time spent limited to `max_elapsed`.
+    pub fn max_elapsed(mut self, max_elapsed: Duration) -> RetryBudget {
+        self.max_elapsed = Some(max_elapsed);
+        self
+    }
+
+    /// Returns the number of failed attempts recorded.
+    pub fn attempts(&self) -> u32 {
+        self.errors.len() as u32
+    }
+
+    /// Returns the time spent since the budget was created.
+    pub fn elapsed(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    /// Returns `true` if no further attempt may be made.
+    pub fn is_exhausted(&self) -> bool {
+        self.attempts() >= self.max_attempts || self.max_elapsed.is_some_and(|max| self.elapsed() >= max)
+    }
+
+    /// Returns the failed attempts recorded so far.
+    pub fn errors(&self) -> &MultiError {
+        &self.errors
+    }
+
+    /// Records the failure `e` of an attempt.
+    ///
+    /// The failure is recorded with the context `attempt N failed`, located
+    /// where this method is called.
+    ///
+    /// # Arguments
+    ///
+    /// * e - Failure of the attempt.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the budget is exhausted by this attempt. The error
+    /// wraps a `MultiError` holding every recorded failure, and carries the
+    /// number of attempts under `metadata::ATTEMPTS` and the time spent under
+    /// `metadata::ELAPSED`. The recorded failures are moved into the error.
+    ///
+    #[track_caller]
+    pub fn record<E>(&mut self, e: E) -> Result<()>
+        where E: Into<Box<dyn error::Error + marker::Send + marker::Sync>> {
+        let location = panic::Location::caller();
+        let message = format!("attempt {} failed", self.attempts() + 1);
+        self.errors.push(Error::context(e, &message, location.file(), location.line()));
+        if !self.is_exhausted() {
+            // The failure is handled by retrying, and is reported only if the budget runs out.
+            if let Some(e) = self.errors.errors.last() {
+                e.mark_handled();
+            }
+            return Ok(());
+        }
+
+        let attempts = self.attempts();
+        let message = format!("retry budget exhausted after {} attempts", attempts);
+        let errors = std::mem::take(&mut self.errors);
+        let mut e = Error::context(errors, &message, location.file(), location.line())
+            .with_meta_keyed(metadata::ATTEMPTS, attempts);
+        e.metadata.insert_volatile(metadata::ELAPSED, self.elapsed());
+        Err(e)
+    }
+}
+
+/// Converts `e` into an `Error`, wrapping it at `location` unless it is one.
+pub(crate) fn into_error<E>(e: E, location: &'static panic::Location<'static>) -> Error
+    where E: Into<Box<dyn error::Error + marker::Send + marker::Sync>> {
+    match e.into().downcast::<Error>() {
+        Ok(e) => *e,
+        Err(e) => Error::wrap(e, location.file(), location.line()),
+    }
+}
+
+/// Returns the delay before retrying after `e`, or `None` to give up.
+pub(crate) fn next_delay<B: Backoff>(backoff: &mut B, e: &Error, attempt: u32) -> Option<Duration> {
+    if e.retry_class() == Some(RetryClass::Permanent) {
+        return None;
+    }
+    let delay = backoff.next_delay(attempt)?;
+    // Retrying handles the error.
+    e.mark_handled();
+    Some(e.retry_after().map_or(delay, |after| after.max(delay)))
+}
+
+/// Returns the error reported when retrying `e` gave up.
+pub(crate) fn gave_up(e: Error,
+                      attempts: u32,
+                      elapsed: Duration,
+                      location: &'static panic::Location<'static>)
+                      -> Error {
+    let message = attempt_message(attempts, None);
+    let mut e = Error::context(e, &message, location.file(), location.line())
+        .with_meta_keyed(metadata::ATTEMPTS, attempts);
+    e.metadata.insert_volatile(metadata::ELAPSED, elapsed);
+    e
+}
+
+/// Returns the context message recorded for a failed attempt.
+pub(crate) fn attempt_message(attempt: u32, delay: Option<Duration>) -> String {
+    match delay {
+        Some(delay) => format!("attempt {} failed; retrying after {:?}", attempt, delay),
+        None => format!("attempt {} failed; giving up", attempt),
+    }
+}
@@ -0,0 +1,513 @@
+//! Helpers for async runtimes.
+//!
+//! This module is available with the `tokio` or the `async-std` feature.
+//! The helpers run on whichever runtime is enabled; if both are enabled, tokio is used.
+
+use std::any::Any;
+use std::error;
+use std::future::Future;
+use std::marker;
+use std::panic;
+use std::pin::Pin;
+use std::result;
+use std::task;
+use std::time::{Duration, Instant};
+
+use super::retry::{self, attempt_message, Backoff, RetryObserver};
+use super::{metadata, Error, MultiError, Result};
+
+/// Spawns `future` on the async runtime, converting its failures into `Error`.
+///
+/// The returned handle resolves to an `Error` located at the spawn site when
+/// the task returns an error, panics, or is cancelled, so the chain records
+/// where the background task came from.
+///
+/// # Arguments
+///
+/// * future - Task to run.
+///
+/// # Panics
+///
+/// With tokio, panics if called outside of a tokio runtime.
+///
+/// # Examples
+///
+/// ```
+/// use mm_errors::rt;
+///
+/// # #[cfg(feature = "tokio")]
+/// # fn block_on<F: std::future::Future>(f: F) -> F::Output {
+/// #     tokio::runtime::Builder::new_current_thread().enable_time().build().unwrap().block_on(f)
+/// # }
+/// # #[cfg(not(feature = "tokio"))]
+/// # fn block_on<F: std::future::Future>(f: F) -> F::Output {
+/// #     async_std::task::block_on(f)
+/// # }
+/// #
+/// # fn main() {
+/// block_on(async {
+///     let ok = rt::spawn_wrapped(async { "1".parse::<u32>() });
+///     assert_eq!(ok.await.unwrap(), 1);
+///
+///     let panicked = rt::spawn_wrapped(async {
+///         if true {
+///             panic!("boom");
+///         }
+///         Ok::<u32, std::num::ParseIntError>(1)
+///     });
+///     let e = panicked.await.unwrap_err();
+///     assert!(e.to_string().contains("spawned task panicked: boom"));
+/// });
+/// # }
+/// ```
+///
+#[track_caller]
+pub fn spawn_wrapped<F, T, E>(future: F) -> WrappedJoinHandle<T, E>
+    where F: Future<Output = result::Result<T, E>> + marker::Send + 'static,
+          T: marker::Send + 'static,
+          E: Into<Box<dyn error::Error + marker::Send + marker::Sync>> + marker::Send + 'static {
+    WrappedJoinHandle {
+        handle: imp::spawn(future),
+        location: panic::Location::caller(),
+    }
+}
+
+/// Handle returned by `spawn_wrapped`.
+///
+/// Dropping the handle detaches the task.
+#[derive(Debug)]
+#[must_use = "dropping the handle detaches the task"]
+pub struct WrappedJoinHandle<T, E> {
+    handle: imp::Handle<result::Result<T, E>>,
+    location: &'static panic::Location<'static>,
+}
+
+impl<T, E> WrappedJoinHandle<T, E> {
+    /// Cancels the task.
+    ///
+    /// Awaiting the handle then returns an error.
+    pub fn abort(&self) {
+        self.handle.abort();
+    }
+}
+
+impl<T, E> Future for WrappedJoinHandle<T, E>
+    where E: Into<Box<dyn error::Error + marker::Send + marker::Sync>> {
+    type Output = Result<T>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut task::Context) -> task::Poll<Result<T>> {
+        let location = self.location;
+        let (file, line) = (location.file(), location.line());
+        self.handle.poll_join(cx).map(|joined| match joined {
+            Joined::Done(Ok(x)) => Ok(x),
+            Joined::Done(Err(e)) => Err(Error::wrap(e, file, line)),
+            Joined::Panicked(message) => {
+                let message = format!("spawned task panicked: {}", message);
+                Err(Error::new(&message, file, line))
+            }
+            Joined::Cancelled => Err(Error::cancelled("spawned task was cancelled", file, line)),
+        })
+    }
+}
+
+/// Runs `future` with a time limit of `duration`.
+///
+/// When the time limit is exceeded, the returned error records the configured
+/// duration in its message and under `metadata::TIMEOUT`, and how long the
+/// operation actually ran under `metadata::ELAPSED`, as `Duration`s.
+/// The location of the returned errors is where this function is called.
+///
+/// # Arguments
+///
+/// * duration - Time limit.
+/// * future - Operation to run.
+///
+/// # Panics
+///
+/// With tokio, panics if polled outside of a tokio runtime with time enabled.
+///
+/// # Examples
+///
+/// ```
+/// use std::time::Duration;
+///
+/// use mm_errors::{metadata, rt};
+///
+/// # #[cfg(feature = "tokio")]
+/// # fn block_on<F: std::future::Future>(f: F) -> F::Output {
+/// #     tokio::runtime::Builder::new_current_thread().enable_time().build().unwrap().block_on(f)
+/// # }
+/// # #[cfg(not(feature = "tokio"))]
+/// # fn block_on<F: std::future::Future>(f: F) -> F::Output {
+/// #     async_std::task::block_on(f)
+/// # }
+/// #
+/// # fn main() {
+/// block_on(async {
+///     let slow = async {
+///         std::future::pending::<()>().await;
+///         "1".parse::<u32>()
+///     };
+///     let e = rt::with_timeout(Duration::from_millis(10), slow).await.unwrap_err();
+///     assert!(e.to_string().contains("<reason>operation timed out (timeout: 10ms)</reason>"));
+///     assert_eq!(e.metadata.get_keyed::<Duration>(metadata::TIMEOUT), Some(&Duration::from_millis(10)));
+///     assert!(e.metadata.get_keyed::<Duration>(metadata::ELAPSED).is_some());
+/// });
+/// # }
+/// ```
+///
+#[track_caller]
+pub fn with_timeout<F, T, E>(duration: Duration, future: F) -> impl Future<Output = Result<T>>
+    where F: Future<Output = result::Result<T, E>>,
+          E: Into<Box<dyn error::Error + marker::Send + marker::Sync>> {
+    let location = panic::Location::caller();
+    async move {
+        let (file, line) = (location.file(), location.line());
+        let start = Instant::now();
+        match imp::timeout(duration, future).await {
+            Some(Ok(x)) => Ok(x),
+            Some(Err(e)) => Err(Error::wrap(e, file, line)),
+            None => {
+                let message = format!("operation timed out (timeout: {:?})", duration);
+                let mut e = Error::new(&message, file, line);
+                e.metadata.insert_volatile(metadata::ELAPSED, start.elapsed());
+                e.metadata.insert_keyed(metadata::TIMEOUT, duration);
+                Err(e)
+            }
+        }
+    }
+}
+
+/// Runs the future returned by `f` until it succeeds or `backoff` gives up.
+///
+/// Every failed attempt is recorded, with its attempt number and the delay
+/// before the next attempt, in the returned `MultiError`.
+/// The location of the recorded errors is where this function is called.
+/// As with `mm_errors::retry`, errors classed `RetryClass::Permanent` are not
+/// retried, `Error::retry_after` lengthens the delay, and retried errors are
+/// marked as handled.
+///
+/// # Arguments
+///
+/// * backoff - Policy deciding the delays between attempts.
+/// * f - Function starting an attempt.
+///
+/// # Panics
+///
+/// With tokio, panics if polled outside of a tokio runtime with time enabled.
+///
+/// # Examples
+///
+/// ```
+/// use std::time::Duration;
+///
+/// use mm_errors::{rt, Error};
+///
+/// # #[cfg(feature = "tokio")]
+/// # fn block_on<F: std::future::Future>(f: F) -> F::Output {
+/// #     tokio::runtime::Builder::new_current_thread().enable_time().build().unwrap().block_on(f)
+/// # }
+/// # #[cfg(not(feature = "tokio"))]
+/// # fn block_on<F: std::future::Future>(f: F) -> F::Output {
+/// #     async_std::task::block_on(f)
+/// # }
+/// #
+/// # fn main() {
+/// block_on(async {
+///     let backoff = |attempt: u32| if attempt < 3 { Some(Duration::from_millis(1)) } else { None };
+///     let errors = rt::retry_async(backoff, || async { "XXX".parse::<u32>() }).await.unwrap_err();
+///
+///     assert_eq!(errors.len(), 3);
+///     assert!(errors.to_string().contains("<context>attempt 3 failed; giving up</context>"));
+///
+///     // Permanent errors are not retried.
+///     let errors = rt::retry_async(backoff, || async {
+///         Err::<(), _>(Error::here("invalid request").mark_permanent())
+///     }).await.unwrap_err();
+///     assert_eq!(errors.len(), 1);
+///
+///     // Hints of the error delay the next attempt beyond the backoff.
+///     let mut attempts = 0;
+///     let errors = rt::retry_async(backoff, || {
+///         attempts += 1;
+///         let attempt = attempts;
+///         async move { Err::<(), _>(Error::here("busy").with_retry_after(Duration::from_millis(20 * attempt))) }
+///     }).await.unwrap_err();
+///     assert!(errors.to_string().contains("<context>attempt 1 failed; retrying after 20ms</context>"));
+///     assert!(errors.to_string().contains("<context>attempt 2 failed; retrying after 40ms</context>"));
+/// });
+/// # }
+/// ```
+///
+#[track_caller]
+pub fn retry_async<B, F, Fut, T, E>(mut backoff: B, mut f: F)
+    -> impl Future<Output = result::Result<T, MultiError>>
+    where B: Backoff,
+          F: FnMut() -> Fut,
+          Fut: Future<Output = result::Result<T, E>>,
+          E: Into<Box<dyn error::Error + marker::Send + marker::Sync>> {
+    let location = panic::Location::caller();
+    async move {
+        let mut errors = MultiError::new();
+        for attempt in 1.. {
+            let e = match f().await {
+                Ok(x) => {
+                    // The earlier failures were handled by retrying.
+                    errors.mark_reported();
+                    return Ok(x);
+                }
+                Err(e) => retry::into_error(e, location),
+            };
+            let delay = retry::next_delay(&mut backoff, &e, attempt);
+            let message = attempt_message(attempt, delay);
+            errors.push(Error::context(e, &message, location.file(), location.line()));
+            match delay {
+                Some(delay) => imp::sleep(delay).await,
+                None => break,
+            }
+        }
+        Err(errors)
+    }
+}
+
+/// Runs the future returned by `f` until it succeeds or `backoff` gives up.
+///
+/// This is the asynchronous version of `mm_errors::retry`. Unlike
+/// `retry_async`, only the last error is returned, with the number of
+/// attempts and the total time attached.
+///
+/// # Arguments
+///
+/// * backoff - Policy deciding the delays between attempts.
+/// * f - Function starting an attempt.
+///
+/// # Panics
+///
+/// With tokio, panics if polled outside of a tokio runtime with time enabled.
+///
+/// # Examples
+///
+/// ```
+/// use std::time::Duration;
+///
+/// use mm_errors::{metadata, rt};
+/// use mm_errors::retry::Exponential;
+///
+/// # #[cfg(feature = "tokio")]
+/// # fn block_on<F: std::future::Future>(f: F) -> F::Output {
+/// #     tokio::runtime::Builder::new_current_thread().enable_time().build().unwrap().block_on(f)
+/// # }
+/// # #[cfg(not(feature = "tokio"))]
+/// # fn block_on<F: std::future::Future>(f: F) -> F::Output {
+/// #     async_std::task::block_on(f)
+/// # }
+/// #
+/// # fn main() {
+/// block_on(async {
+///     let backoff = Exponential::new(Duration::from_millis(1), 3);
+///     let e = rt::retry(backoff, || async { "XXX".parse::<u32>() }).await.unwrap_err();
+///
+///     assert_eq!(e.metadata.get_keyed::<u32>(metadata::ATTEMPTS), Some(&3));
+///     assert!(e.metadata.get_keyed::<Duration>(metadata::ELAPSED).is_some());
+/// });
+/// # }
+/// ```
+///
+#[track_caller]
+pub fn retry<B, F, Fut, T, E>(backoff: B, f: F) -> impl Future<Output = Result<T>>
+    where B: Backoff,
+          F: FnMut() -> Fut,
+          Fut: Future<Output = result::Result<T, E>>,
+          E: Into<Box<dyn error::Error + marker::Send + marker::Sync>> {
+    retry_observed(backoff, |_: u32, _: &Error, _: Duration| {}, f)
+}
+
+/// Same as `retry`, but calls `observer` for every failed attempt which is retried.
+///
+/// This is the asynchronous version of `retry::retry_observed`.
+///
+/// # Arguments
+///
+/// * backoff - Policy deciding the delays between attempts.
+/// * observer - Observer of the failed attempts, e.g. for logging or metrics.
+/// * f - Function starting an attempt.
+///
+/// # Panics
+///
+/// With tokio, panics if polled outside of a tokio runtime with time enabled.
+///
+#[track_caller]
+pub fn retry_observed<B, O, F, Fut, T, E>(mut backoff: B, mut observer: O, mut f: F)
+    -> impl Future<Output = Result<T>>
+    where B: Backoff,
+          O: RetryObserver,
+          F: FnMut() -> Fut,
+          Fut: Future<Output = result::Result<T, E>>,
+          E: Into<Box<dyn error::Error + marker::Send + marker::Sync>> {
+    let location = panic::Location::caller();
+    async move {
+        let start = Instant::now();
+        let mut attempt = 1;
+        loop {
+            let e = match f().await {
+                Ok(x) => return Ok(x),
+                Err(e) => retry::into_error(e, location),
+            };
+            match retry::next_delay(&mut backoff, &e, attempt) {
+                Some(delay) => {
+                    observer.on_retry(attempt, &e, delay);
+                    imp::sleep(delay).await;
+                }
+                None => return Err(retry::gave_up(e, attempt, start.elapsed(), location)),
+            }
+            attempt += 1;
+        }
+    }
+}
+
+/// Returns the message of a panic payload.
+fn panic_message(payload: &(dyn Any + marker::Send)) -> String {
+    if let Some(s) = payload.downcast_ref::<&str>() {
+        s.to_string()
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        s.clone()
+    } else {
+        "<non-string panic payload>".to_string()
+    }
+}
+
+/// Outcome of a spawned task.
+enum Joined<T> {
+    Done(T),
+    Panicked(String),
+    Cancelled,
+}
+
+#[cfg(feature = "tokio")]
+mod imp {
+    use std::future::Future;
+    use std::marker;
+    use std::pin::Pin;
+    use std::task;
+    use std::time::Duration;
+
+    use super::{panic_message, Joined};
+
+    #[derive(Debug)]
+    pub struct Handle<T>(::tokio::task::JoinHandle<T>);
+
+    impl<T> Handle<T> {
+        pub fn poll_join(&mut self, cx: &mut task::Context) -> task::Poll<Joined<T>> {
+            Pin::new(&mut self.0).poll(cx).map(|joined| match joined {
+                Ok(x) => Joined::Done(x),
+                Err(e) if e.is_panic() => Joined::Panicked(panic_message(&*e.into_panic())),
+                Err(_) => Joined::Cancelled,
+            })
+        }
+
+        pub fn abort(&self) {
+            self.0.abort();
+        }
+    }
+
+    pub fn spawn<F>(future: F) -> Handle<F::Output>
+        where F: Future + marker::Send + 'static, F::Output: marker::Send + 'static {
+        Handle(::tokio::spawn(future))
+    }
+
+    pub async fn sleep(duration: Duration) {
+        ::tokio::time::sleep(duration).await
+    }
+
+    pub async fn timeout<F: Future>(duration: Duration, future: F) -> Option<F::Output> {
+        ::tokio::time::timeout(duration, future).await.ok()
+    }
+}
+
+#[cfg(all(feature = "async-std", not(feature = "tokio")))]
+mod imp {
+    use std::fmt;
+    use std::future::Future;
+    use std::marker;
+    use std::panic::{self, AssertUnwindSafe};
+    use std::pin::Pin;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::task;
+    use std::time::Duration;
+
+    use super::{panic_message, Joined};
+
+    /// State shared between a task and its handle, for aborting the task.
+    #[derive(Default)]
+    struct Shared {
+        aborted: AtomicBool,
+        waker: Mutex<Option<task::Waker>>,
+    }
+
+    /// Runs `future`, catching panics and stopping when aborted.
+    struct Task<F> {
+        future: Pin<Box<F>>,
+        shared: Arc<Shared>,
+    }
+
+    impl<F: Future> Future for Task<F> {
+        type Output = Joined<F::Output>;
+
+        fn poll(mut self: Pin<&mut Self>, cx: &mut task::Context) -> task::Poll<Self::Output> {
+            // Store the waker before checking the flag, so no abort is missed.
+            *self.shared.waker.lock().unwrap_or_else(|e| e.into_inner()) = Some(cx.waker().clone());
+            if self.shared.aborted.load(Ordering::SeqCst) {
+                return task::Poll::Ready(Joined::Cancelled);
+            }
+            let future = self.future.as_mut();
+            match panic::catch_unwind(AssertUnwindSafe(|| future.poll(cx))) {
+                Ok(task::Poll::Pending) => task::Poll::Pending,
+                Ok(task::Poll::Ready(x)) => task::Poll::Ready(Joined::Done(x)),
+                Err(payload) => task::Poll::Ready(Joined::Panicked(panic_message(&*payload))),
+            }
+        }
+    }
+
+    pub struct Handle<T> {
+        handle: ::async_std::task::JoinHandle<Joined<T>>,
+        shared: Arc<Shared>,
+    }
+
+    impl<T> Handle<T> {
+        pub fn poll_join(&mut self, cx: &mut task::Context) -> task::Poll<Joined<T>> {
+            Pin::new(&mut self.handle).poll(cx)
+        }
+
+        pub fn abort(&self) {
+            self.shared.aborted.store(true, Ordering::SeqCst);
+            if let Some(waker) = self.shared.waker.lock().unwrap_or_else(|e| e.into_inner()).take() {
+                waker.wake();
+            }
+        }
+    }
+
+    impl<T> fmt::Debug for Handle<T> {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.debug_struct("Handle").field("task", &self.handle.task().id()).finish()
+        }
+    }
+
+    pub fn spawn<F>(future: F) -> Handle<F::Output>
+        where F: Future + marker::Send + 'static, F::Output: marker::Send + 'static {
+        let shared = Arc::new(Shared::default());
+        let task = Task { future: Box::pin(future), shared: shared.clone() };
+        Handle {
+            handle: ::async_std::task::spawn(task),
+            shared,
+        }
+    }
+
+    pub async fn sleep(duration: Duration) {
+        ::async_std::task::sleep(duration).await
+    }
+
+    pub async fn timeout<F: Future>(duration: Duration, future: F) -> Option<F::Output> {
+        ::async_std::future::timeout(duration, future).await.ok()
+    }
+}
@@ -0,0 +1,142 @@
+//! Extensions for streams of `Result`s.
+//!
+//! This module is available with the `stream` feature.
+
+use std::error;
+use std::marker;
+use std::panic;
+use std::pin::Pin;
+use std::result;
+use std::task;
+
+use futures_core::Stream;
+
+use super::{Error, Result};
+
+/// Wraps errors of streams yielding `Result`s.
+///
+/// The location of the returned errors is where the method is called.
+/// The stream keeps going after an error; every item is mapped on its own.
+///
+/// # Examples
+///
+/// ```
+/// use futures::executor::block_on;
+/// use futures::stream::{self, StreamExt};
+///
+/// use mm_errors::ErrorKind;
+/// use mm_errors::stream::ResultStreamExt;
+///
+/// # fn main() {
+/// let lines = stream::iter(vec!["1", "X", "3"]).map(|s| s.parse::<u32>());
+/// let parsed: Vec<_> = block_on(lines.context_items(|i| format!("parsing line {}", i + 1)).collect());
+///
+/// assert_eq!(parsed[0].as_ref().unwrap(), &1);
+/// match parsed[1].as_ref().unwrap_err().kind {
+///     ErrorKind::Context(ref message, _) => assert_eq!(message, "parsing line 2"),
+///     _ => panic!("the error should have context"),
+/// }
+/// # }
+/// ```
+///
+pub trait ResultStreamExt<T, E>: Stream<Item = result::Result<T, E>> + Sized
+    where E: Into<Box<dyn error::Error + marker::Send + marker::Sync>> {
+    /// Wraps each error of `self` into an `Error`.
+    #[track_caller]
+    fn map_err_wrap(self) -> MapErrWrap<Self> {
+        MapErrWrap {
+            stream: self,
+            location: panic::Location::caller(),
+        }
+    }
+
+    /// Wraps each error of `self` with the message returned by `f`.
+    ///
+    /// `f` receives the index of the failed item in the stream, and is called
+    /// only for errors.
+    ///
+    /// # Arguments
+    ///
+    /// * f - Function returning the context message.
+    ///
+    #[track_caller]
+    fn context_items<F, M>(self, f: F) -> ContextItems<Self, F>
+        where F: FnMut(usize) -> M, M: AsRef<str> {
+        ContextItems {
+            stream: self,
+            f,
+            index: 0,
+            location: panic::Location::caller(),
+        }
+    }
+}
+
+impl<T, E, S> ResultStreamExt<T, E> for S
+    where S: Stream<Item = result::Result<T, E>>,
+          E: Into<Box<dyn error::Error + marker::Send + marker::Sync>> {}
+
+/// Stream returned by `ResultStreamExt::map_err_wrap`.
+#[derive(Debug)]
+#[must_use = "streams do nothing unless polled"]
+pub struct MapErrWrap<St> {
+    stream: St,
+    location: &'static panic::Location<'static>,
+}
+
+impl<St, T, E> Stream for MapErrWrap<St>
+    where St: Stream<Item = result::Result<T, E>>,
+          E: Into<Box<dyn error::Error + marker::Send + marker::Sync>> {
+    type Item = Result<T>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut task::Context) -> task::Poll<Option<Result<T>>> {
+        // SAFETY: `stream` is never moved out of `self`, and no other field is pinned.
+        let this = unsafe { self.get_unchecked_mut() };
+        let stream = unsafe { Pin::new_unchecked(&mut this.stream) };
+        let location = this.location;
+        stream.poll_next(cx).map(|item| item.map(|r| {
+            r.map_err(|e| Error::wrap(e, location.file(), location.line()))
+        }))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.stream.size_hint()
+    }
+}
+
+/// Stream returned by `ResultStreamExt::context_items`.
+#[derive(Debug)]
+#[must_use = "streams do nothing unless polled"]
+pub struct ContextItems<St, F> {
+    stream: St,
+    f: F,
+    index: usize,
+    location: &'static panic::Location<'static>,
+}
+
+impl<St, F, M, T, E> Stream for ContextItems<St, F>
+    where St: Stream<Item = result::Result<T, E>>,
+          E: Into<Box<dyn error::Error + marker::Send + marker::Sync>>,
+          F: FnMut(usize) -> M,
+          M: AsRef<str> {
+    type Item = Result<T>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut task::Context) -> task::Poll<Option<Result<T>>> {
+        // SAFETY: `stream` is never moved out of `self`, and no other field is pinned.
+        let this = unsafe { self.get_unchecked_mut() };
+        let stream = unsafe { Pin::new_unchecked(&mut this.stream) };
+        let item = match stream.poll_next(cx) {
+            task::Poll::Pending => return task::Poll::Pending,
+            task::Poll::Ready(item) => item,
+        };
+        let index = this.index;
+        this.index += 1;
+        task::Poll::Ready(item.map(|r| r.map_err(|e| {
+            let message = (this.f)(index);
+            Error::context(e, message.as_ref(), this.location.file(), this.location.line())
+        })))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.stream.size_hint()
+    }
+}
@@ -0,0 +1,127 @@
+//! Channels recording where messages are produced and consumed.
+//!
+//! These wrap `std::sync::mpsc`. When a channel is closed, the returned error
+//! holds both the location of the failed call and the location on the other
+//! side of the channel, so "channel closed" errors tell where to look.
+
+use std::fmt;
+use std::panic;
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+
+use super::{Error, Result};
+
+type Location = &'static panic::Location<'static>;
+
+#[derive(Debug)]
+struct Shared {
+    /// Location of the last successful send, or of the channel creation.
+    produced: Mutex<Location>,
+
+    /// Location where the receiver was created.
+    receiver: Location,
+}
+
+/// Creates a new channel.
+///
+/// # Examples
+///
+/// ```
+/// use mm_errors::sync;
+///
+/// let (tx, rx) = sync::channel::<u32>();
+/// tx.send(1).unwrap();
+/// drop(tx);
+///
+/// assert_eq!(rx.recv().unwrap(), 1);
+///
+/// // The error holds the location of `recv` and, inside, the location of the last `send`.
+/// let e = rx.recv().unwrap_err();
+/// assert!(e.to_string().contains("<context>the last message was sent here</context>"));
+/// ```
+///
+#[track_caller]
+pub fn channel<T>() -> (Sender<T>, Receiver<T>) {
+    let location = panic::Location::caller();
+    let (tx, rx) = mpsc::channel();
+    let shared = Arc::new(Shared {
+        produced: Mutex::new(location),
+        receiver: location,
+    });
+    (Sender { inner: tx, shared: shared.clone() }, Receiver { inner: rx, shared })
+}
+
+/// Sending half of a channel created by `channel`.
+pub struct Sender<T> {
+    inner: mpsc::Sender<T>,
+    shared: Arc<Shared>,
+}
+
+impl<T> Sender<T> {
+    /// Sends `t` to the receiver.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the receiver has been dropped. The error holds the
+    /// location where the receiver was created, and `t` is dropped.
+    ///
+    #[track_caller]
+    pub fn send(&self, t: T) -> Result<()> {
+        let location = panic::Location::caller();
+        match self.inner.send(t) {
+            Ok(()) => {
+                *self.shared.produced.lock().unwrap_or_else(|e| e.into_inner()) = location;
+                Ok(())
+            }
+            Err(_) => {
+                let receiver = self.shared.receiver;
+                let inner = Error::new("the receiver was created here", receiver.file(), receiver.line());
+                Err(Error::context(inner, "sending to a closed channel", location.file(), location.line()))
+            }
+        }
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        Sender { inner: self.inner.clone(), shared: self.shared.clone() }
+    }
+}
+
+impl<T> fmt::Debug for Sender<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Sender").field("shared", &self.shared).finish()
+    }
+}
+
+/// Receiving half of a channel created by `channel`.
+pub struct Receiver<T> {
+    inner: mpsc::Receiver<T>,
+    shared: Arc<Shared>,
+}
+
+impl<T> Receiver<T> {
+    /// Waits for a message.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if all senders have been dropped. The error holds the
+    /// location of the last successful send, or of the channel creation if
+    /// nothing was sent.
+    ///
+    #[track_caller]
+    pub fn recv(&self) -> Result<T> {
+        let location = panic::Location::caller();
+        self.inner.recv().map_err(|e| {
+            let produced = *self.shared.produced.lock().unwrap_or_else(|e| e.into_inner());
+            let inner = Error::context(e, "the last message was sent here", produced.file(), produced.line());
+            Error::context(inner, "receiving from a closed channel", location.file(), location.line())
+        })
+    }
+}
+
+impl<T> fmt::Debug for Receiver<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Receiver").field("shared", &self.shared).finish()
+    }
+}
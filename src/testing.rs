@@ -0,0 +1,571 @@
+//! Helpers for testing code which returns errors.
+
+use std::any;
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::env;
+use std::error;
+use std::fmt;
+use std::fs;
+use std::marker;
+use std::path::Path;
+use std::result;
+
+use super::{render, Error, ErrorKind, Metadata};
+
+#[cfg(feature = "proptest")]
+pub mod strategies;
+
+/// Builds errors with explicit locations, for fixtures of unit tests.
+///
+/// Unlike the macros, nothing is taken from the call site: the file is
+/// `test.rs` and the line is 1 unless set otherwise.
+///
+/// # Examples
+///
+/// ```
+/// use mm_errors::ErrorKind;
+/// use mm_errors::testing::ErrorBuilder;
+///
+/// let e = ErrorBuilder::new("loading the config")
+///     .file("src/config.rs")
+///     .line(42)
+///     .code("E001")
+///     .source(ErrorBuilder::new("file not found").file("src/fs.rs").line(7).build())
+///     .build();
+///
+/// assert_eq!(e.file, "src/config.rs");
+/// assert_eq!(e.line, 42);
+/// assert_eq!(e.code.as_deref(), Some("E001"));
+/// assert_eq!(e.chain().count(), 2);
+/// match e.kind {
+///     ErrorKind::Context(ref message, _) => assert_eq!(message, "loading the config"),
+///     _ => panic!("the error should have context"),
+/// }
+/// ```
+///
+#[derive(Debug)]
+pub struct ErrorBuilder {
+    file: Cow<'static, str>,
+    line: u32,
+    code: Option<Cow<'static, str>>,
+    message: Option<String>,
+    cancelled: bool,
+    source: Option<Box<dyn error::Error + marker::Send + marker::Sync>>,
+    metadata: Metadata,
+}
+
+impl ErrorBuilder {
+    /// Returns a builder of an error with `message`.
+    ///
+    /// # Arguments
+    ///
+    /// * message - Error message, or context message if a source is set.
+    ///
+    pub fn new<M: Into<String>>(message: M) -> ErrorBuilder {
+        ErrorBuilder {
+            file: Cow::Borrowed("test.rs"),
+            line: 1,
+            code: None,
+            message: Some(message.into()),
+            cancelled: false,
+            source: None,
+            metadata: Metadata::new(),
+        }
+    }
+
+    /// Returns a builder of an error wrapping `e` without a message.
+    ///
+    /// # Arguments
+    ///
+    /// * e - Inner error.
+    ///
+    pub fn wrap<E>(e: E) -> ErrorBuilder
+        where E: Into<Box<dyn error::Error + marker::Send + marker::Sync>> {
+        ErrorBuilder {
+            message: None,
+            source: Some(e.into()),
+            ..ErrorBuilder::new("")
+        }
+    }
+
+    /// Sets the file.
+    pub fn file<F: Into<Cow<'static, str>>>(mut self, file: F) -> ErrorBuilder {
+        self.file = file.into();
+        self
+    }
+
+    /// Sets the line.
+    pub fn line(mut self, line: u32) -> ErrorBuilder {
+        self.line = line;
+        self
+    }
+
+    /// Sets the error code.
+    pub fn code<C: Into<Cow<'static, str>>>(mut self, code: C) -> ErrorBuilder {
+        self.code = Some(code.into());
+        self
+    }
+
+    /// Attaches `value` under `key`.
+    pub fn meta<K, T>(mut self, key: K, value: T) -> ErrorBuilder
+        where K: Into<Cow<'static, str>>,
+              T: any::Any + marker::Send + marker::Sync + fmt::Debug {
+        self.metadata.insert_keyed(key, value);
+        self
+    }
+
+    /// Sets the inner error, making the message a context message.
+    ///
+    /// # Arguments
+    ///
+    /// * e - Inner error.
+    ///
+    pub fn source<E>(mut self, e: E) -> ErrorBuilder
+        where E: Into<Box<dyn error::Error + marker::Send + marker::Sync>> {
+        self.source = Some(e.into());
+        self
+    }
+
+    /// Makes the error an `ErrorKind::Cancelled` error.
+    ///
+    /// Ignored if a source is set.
+    ///
+    pub fn cancelled(mut self) -> ErrorBuilder {
+        self.cancelled = true;
+        self
+    }
+
+    /// Sets a chain of inner errors with `messages`, outermost first.
+    ///
+    /// Each message becomes an `ErrorKind::String` error at the same file as
+    /// `self`, with increasing line numbers, wrapped by the previous one.
+    ///
+    /// # Arguments
+    ///
+    /// * messages - Messages of the inner errors.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mm_errors::testing::ErrorBuilder;
+    ///
+    /// let e = ErrorBuilder::new("request failed").chain(&["connection reset", "timed out"]).build();
+    /// assert_eq!(e.chain().count(), 3);
+    /// assert!(e.root_cause().to_string().contains("<reason>timed out</reason>"));
+    /// ```
+    ///
+    pub fn chain<I>(self, messages: I) -> ErrorBuilder
+        where I: IntoIterator,
+              I::Item: AsRef<str> {
+        let messages: Vec<String> = messages.into_iter().map(|m| m.as_ref().to_string()).collect();
+        let mut source: Option<Error> = None;
+        for (i, message) in messages.iter().enumerate().rev() {
+            let builder = ErrorBuilder::new(message.as_str())
+                .file(self.file.clone())
+                .line(self.line + 1 + i as u32);
+            source = Some(match source {
+                Some(inner) => builder.source(inner).build(),
+                None => builder.build(),
+            });
+        }
+        match source {
+            Some(inner) => self.source(inner),
+            None => self,
+        }
+    }
+
+    /// Returns the error.
+    pub fn build(self) -> Error {
+        let kind = match (self.message, self.source) {
+            (Some(message), Some(e)) => ErrorKind::Context(message, e),
+            (None, Some(e)) => ErrorKind::Wrapped(e),
+            (Some(message), None) if self.cancelled => ErrorKind::Cancelled(message),
+            (message, None) => ErrorKind::String(message.unwrap_or_default()),
+        };
+        let e = Error {
+            file: self.file,
+            line: self.line,
+            kind,
+            code: self.code,
+            metadata: self.metadata,
+        };
+        created(e)
+    }
+}
+
+impl From<ErrorBuilder> for Error {
+    fn from(builder: ErrorBuilder) -> Error {
+        builder.build()
+    }
+}
+
+thread_local! {
+    /// Errors recorded by each active `capture_errors` call, innermost last.
+    static CAPTURES: RefCell<Vec<Vec<Error>>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Runs `f`, recording every `Error` created inside it.
+///
+/// Errors created by the constructors of `Error`, the macros and
+/// `ErrorBuilder` are recorded, even if `f` swallows them. Each recorded
+/// error is a copy taken when the error is created, so methods applied
+/// afterwards, such as `Error::with_code`, are not reflected.
+///
+/// Only errors created on the current thread are recorded. Calls can be
+/// nested, and an error is recorded by every active call.
+///
+/// # Arguments
+///
+/// * f - Code under test.
+///
+/// # Examples
+///
+/// ```
+/// # #[macro_use] extern crate mm_errors;
+///
+/// use mm_errors::testing;
+///
+/// fn parse_or_default(s: &str) -> u32 {
+///     let r: mm_errors::Result<u32> = wrap_result!(s.parse::<u32>());
+///     r.unwrap_or(0)
+/// }
+///
+/// # fn main() {
+///     let (value, errors) = testing::capture_errors(|| parse_or_default("XXX"));
+///     assert_eq!(value, 0);
+///     assert_eq!(errors.len(), 1);
+///     assert!(testing::err_matcher().message_contains("invalid digit").matches(&errors[0]));
+///
+///     let (_, errors) = testing::capture_errors(|| parse_or_default("1"));
+///     assert!(errors.is_empty());
+/// # }
+/// ```
+///
+pub fn capture_errors<F, T>(f: F) -> (T, Vec<Error>)
+    where F: FnOnce() -> T {
+    struct Scope;
+
+    impl Drop for Scope {
+        fn drop(&mut self) {
+            CAPTURES.with(|c| c.borrow_mut().pop());
+        }
+    }
+
+    CAPTURES.with(|c| c.borrow_mut().push(Vec::new()));
+    let scope = Scope;
+    let value = f();
+    let errors = CAPTURES.with(|c| c.borrow_mut().last_mut().map(std::mem::take).unwrap_or_default());
+    drop(scope);
+    (value, errors)
+}
+
+/// Records `e` in every active `capture_errors` call, and returns it.
+pub(crate) fn created(e: Error) -> Error {
+    // `try_with` fails only while the thread is being torn down, when nothing is captured.
+    let _ = CAPTURES.try_with(|c| {
+        if let Ok(mut captures) = c.try_borrow_mut() {
+            for errors in captures.iter_mut() {
+                errors.push(e.clone());
+            }
+        }
+    });
+    e
+}
+
+/// Returns a matcher matching any error.
+///
+/// Add expectations with the methods of `ErrorMatcher`.
+///
+/// # Examples
+///
+/// ```
+/// use mm_errors::testing::{self, ErrorBuilder};
+///
+/// let e = ErrorBuilder::new("request failed").code("E042").chain(&["timeout"]).build();
+///
+/// let matcher = testing::err_matcher().code("E042").message_contains("timeout").chain_depth(2);
+/// assert!(matcher.matches(&e));
+/// matcher.assert(&e);
+///
+/// let mismatch = testing::err_matcher().code("E001").chain_depth(3).check(&e).unwrap_err();
+/// assert_eq!(
+///     mismatch.to_string(),
+///     "error doesn't match:\n  \
+///      expected code \"E001\", got \"E042\"\n  \
+///      expected a chain of 3 errors, got 2");
+/// ```
+///
+pub fn err_matcher() -> ErrorMatcher {
+    ErrorMatcher { expectations: Vec::new() }
+}
+
+/// Expectations on an error, created by `err_matcher`.
+///
+/// For mock frameworks taking a predicate, use `move |e: &Error| matcher.matches(e)`.
+///
+#[derive(Debug, Clone)]
+pub struct ErrorMatcher {
+    expectations: Vec<Expectation>,
+}
+
+#[derive(Debug, Clone)]
+enum Expectation {
+    Code(String),
+    MessageContains(String),
+    ChainDepth(usize),
+    Cancelled,
+}
+
+impl ErrorMatcher {
+    /// Expects the error code to be `code`.
+    pub fn code(mut self, code: &str) -> ErrorMatcher {
+        self.expectations.push(Expectation::Code(code.to_string()));
+        self
+    }
+
+    /// Expects the message of the error, or of any error in its chain, to contain `s`.
+    ///
+    /// The message of an `Error` is its message or context message, and the
+    /// message of any other error is its `Display` output.
+    ///
+    pub fn message_contains(mut self, s: &str) -> ErrorMatcher {
+        self.expectations.push(Expectation::MessageContains(s.to_string()));
+        self
+    }
+
+    /// Expects the chain of the error, as returned by `Error::chain`, to have `depth` errors.
+    pub fn chain_depth(mut self, depth: usize) -> ErrorMatcher {
+        self.expectations.push(Expectation::ChainDepth(depth));
+        self
+    }
+
+    /// Expects the error to be an `ErrorKind::Cancelled` error.
+    pub fn cancelled(mut self) -> ErrorMatcher {
+        self.expectations.push(Expectation::Cancelled);
+        self
+    }
+
+    /// Returns `true` if `e` meets every expectation.
+    pub fn matches(&self, e: &Error) -> bool {
+        self.check(e).is_ok()
+    }
+
+    /// Checks `e` against every expectation.
+    ///
+    /// # Errors
+    ///
+    /// Returns the unmet expectations.
+    ///
+    pub fn check(&self, e: &Error) -> result::Result<(), Mismatch> {
+        let failures: Vec<String> = self.expectations.iter().filter_map(|x| x.check(e)).collect();
+        if failures.is_empty() {
+            Ok(())
+        } else {
+            Err(Mismatch { failures })
+        }
+    }
+
+    /// Asserts that `e` meets every expectation.
+    ///
+    /// # Panics
+    ///
+    /// Panics with the unmet expectations and `e` if `e` doesn't match.
+    ///
+    #[track_caller]
+    pub fn assert(&self, e: &Error) {
+        if let Err(mismatch) = self.check(e) {
+            panic!("{}\nerror: {}", mismatch, e);
+        }
+    }
+}
+
+impl Expectation {
+    fn check(&self, e: &Error) -> Option<String> {
+        match *self {
+            Expectation::Code(ref code) => match e.code {
+                Some(ref actual) if actual == code => None,
+                Some(ref actual) => Some(format!("expected code {:?}, got {:?}", code, actual)),
+                None => Some(format!("expected code {:?}, got no code", code)),
+            },
+            Expectation::MessageContains(ref s) => {
+                if e.chain().any(|e| message(e).contains(s.as_str())) {
+                    None
+                } else {
+                    Some(format!("expected a message containing {:?}", s))
+                }
+            }
+            Expectation::ChainDepth(depth) => {
+                let actual = e.chain().count();
+                if actual == depth {
+                    None
+                } else {
+                    Some(format!("expected a chain of {} errors, got {}", depth, actual))
+                }
+            }
+            Expectation::Cancelled => {
+                if e.is_cancelled() {
+                    None
+                } else {
+                    Some("expected a cancelled error".to_string())
+                }
+            }
+        }
+    }
+}
+
+fn message<'a>(e: &'a (dyn error::Error + 'static)) -> Cow<'a, str> {
+    match e.downcast_ref::<Error>() {
+        Some(e) => Cow::Borrowed(e.message().unwrap_or("")),
+        None => Cow::Owned(e.to_string()),
+    }
+}
+
+/// Unmet expectations of an `ErrorMatcher`.
+#[derive(Debug, Clone)]
+pub struct Mismatch {
+    /// Descriptions of the unmet expectations.
+    pub failures: Vec<String>,
+}
+
+impl error::Error for Mismatch {}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "error doesn't match:")?;
+        for failure in &self.failures {
+            write!(f, "\n  {}", failure)?;
+        }
+        Ok(())
+    }
+}
+
+/// Format of a golden file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// `Display` output, with one element per line.
+    Xml,
+
+    /// `{:#?}` output.
+    Debug,
+}
+
+/// Name of the environment variable which makes `assert_golden` write golden files.
+pub const UPDATE_GOLDEN_VAR: &str = "MM_ERRORS_UPDATE_GOLDEN";
+
+/// Renders `e` in `format` with volatile values normalized, as written to golden files.
+///
+/// Volatile values are normalized as with `render::set_deterministic`, but only
+/// while rendering, and without affecting other threads.
+///
+/// # Arguments
+///
+/// * e - Error to render.
+/// * format - Format of the output.
+///
+/// # Examples
+///
+/// ```
+/// use mm_errors::Error;
+/// use mm_errors::testing::{self, Format};
+///
+/// let e = Error::wrap(Error::new("disk full", file!(), line!()), file!(), line!());
+/// assert_eq!(
+///     testing::render_golden(&e, Format::Xml),
+///     "<error schema=\"2\" version=\"[version]\">\n<file>[file]</file>\n<line>0</line>\n<reason>\n\
+///      <error>\n<file>[file]</file>\n<line>0</line>\n<reason>disk full</reason>\n</error>\n\
+///      </reason>\n</error>\n");
+/// ```
+///
+pub fn render_golden(e: &Error, format: Format) -> String {
+    let mut s = render::deterministic(|| match format {
+        Format::Xml => e.to_string().replace("><", ">\n<"),
+        Format::Debug => format!("{:?}", e),
+    });
+    s.push('\n');
+    s
+}
+
+/// Asserts that `e`, rendered by `render_golden`, equals the content of the golden file at `path`.
+///
+/// If the environment variable `MM_ERRORS_UPDATE_GOLDEN` is set, the golden
+/// file is written instead, so golden files are created or updated with e.g.
+/// `MM_ERRORS_UPDATE_GOLDEN=1 cargo test`. Relative paths are relative to the
+/// current directory, which is the package root under `cargo test`.
+///
+/// # Arguments
+///
+/// * e - Error to compare.
+/// * format - Format of the golden file.
+/// * path - Path to the golden file.
+///
+/// # Panics
+///
+/// Panics with a line diff if the output differs from the golden file, or
+/// if the golden file can't be read or written.
+///
+/// # Examples
+///
+/// ```
+/// use mm_errors::Error;
+/// use mm_errors::testing::{self, Format};
+///
+/// let path = std::env::temp_dir().join("mm-errors-golden-example.xml");
+/// let e = Error::context(Error::new("disk full", file!(), line!()), "saving", file!(), line!());
+/// std::fs::write(&path, testing::render_golden(&e, Format::Xml)).unwrap();
+///
+/// testing::assert_golden(&e, Format::Xml, &path);
+/// ```
+///
+#[track_caller]
+pub fn assert_golden<P: AsRef<Path>>(e: &Error, format: Format, path: P) {
+    let path = path.as_ref();
+    let actual = render_golden(e, format);
+    if env::var_os(UPDATE_GOLDEN_VAR).is_some() {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)
+                .unwrap_or_else(|e| panic!("creating {}: {}", dir.display(), e));
+        }
+        fs::write(path, &actual).unwrap_or_else(|e| panic!("writing {}: {}", path.display(), e));
+        return;
+    }
+    let expected = fs::read_to_string(path).unwrap_or_else(|e| {
+        panic!("reading {}: {} (set {} to create it)", path.display(), e, UPDATE_GOLDEN_VAR)
+    });
+    if actual != expected {
+        panic!("error doesn't match golden file {} (set {} to update it):\n{}",
+               path.display(), UPDATE_GOLDEN_VAR, diff(&expected, &actual));
+    }
+}
+
+/// Returns a line diff from `expected` to `actual`, prefixing removed lines
+/// with `-`, added lines with `+` and others with a space.
+fn diff(expected: &str, actual: &str) -> String {
+    let a: Vec<&str> = expected.lines().collect();
+    let b: Vec<&str> = actual.lines().collect();
+
+    // lcs[i][j] is the length of the longest common subsequence of a[i..] and b[j..].
+    let mut lcs = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            lcs[i][j] = if a[i] == b[j] { lcs[i + 1][j + 1] + 1 } else { lcs[i + 1][j].max(lcs[i][j + 1]) };
+        }
+    }
+
+    let mut out = String::new();
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() || j < b.len() {
+        if i < a.len() && j < b.len() && a[i] == b[j] {
+            out.push_str(&format!(" {}\n", a[i]));
+            i += 1;
+            j += 1;
+        } else if j < b.len() && (i == a.len() || lcs[i][j + 1] >= lcs[i + 1][j]) {
+            out.push_str(&format!("+{}\n", b[j]));
+            j += 1;
+        } else {
+            out.push_str(&format!("-{}\n", a[i]));
+            i += 1;
+        }
+    }
+    out
+}
@@ -0,0 +1,128 @@
+//! `proptest` strategies generating errors.
+//!
+//! This module is available with the `proptest` feature.
+//!
+//! # Examples
+//!
+//! ```
+//! use proptest::prelude::*;
+//! use proptest::test_runner::TestRunner;
+//!
+//! use mm_errors::Error;
+//! use mm_errors::testing::strategies;
+//!
+//! let mut runner = TestRunner::default();
+//! runner.run(&strategies::error_chain(1..=4), |e| {
+//!     let depth = e.chain().count();
+//!     prop_assert!((1..=4).contains(&depth));
+//!     prop_assert_eq!(Error::from_xml(&e.to_string()).unwrap().to_string(), e.to_string());
+//!     Ok(())
+//! }).unwrap();
+//! ```
+
+use std::borrow::Cow;
+use std::ops::RangeInclusive;
+
+use proptest::collection;
+use proptest::option;
+use proptest::prelude::*;
+
+use super::super::{Error, ErrorKind, Metadata, MultiError};
+
+/// Returns a strategy generating file names.
+pub fn file() -> BoxedStrategy<String> {
+    "[a-z]{1,8}(/[a-z]{1,8}){0,2}\\.rs".boxed()
+}
+
+/// Returns a strategy generating messages.
+///
+/// Messages include characters which must be escaped in the output.
+///
+pub fn message() -> BoxedStrategy<String> {
+    "[a-zA-Z0-9<>&'\"]([ a-zA-Z0-9.,:!?<>&'\"-]{0,30}[a-zA-Z0-9<>&'\"])?".boxed()
+}
+
+/// Returns a strategy generating error codes, e.g. `"E042"`.
+pub fn code() -> BoxedStrategy<String> {
+    "E[0-9]{3}".boxed()
+}
+
+/// Returns a strategy generating errors without inner errors.
+///
+/// The kind of the generated errors is `ErrorKind::String` or `ErrorKind::Cancelled`.
+/// Errors generated by the strategies of this module have up to two `String`
+/// values in their metadata.
+///
+pub fn leaf() -> BoxedStrategy<Error> {
+    let kind = prop_oneof![
+        3 => message().prop_map(ErrorKind::String),
+        1 => message().prop_map(ErrorKind::Cancelled),
+    ];
+    located(kind.boxed())
+}
+
+/// Returns a strategy generating chains of errors.
+///
+/// The number of errors in a generated chain, as returned by `Error::chain`,
+/// is in `depth`. Every error except the last one wraps the next one, either
+/// with `ErrorKind::Wrapped` or `ErrorKind::Context`.
+///
+/// # Arguments
+///
+/// * depth - Range of the number of errors in the chain.
+///
+/// # Panics
+///
+/// Panics if `depth` is empty or starts at 0.
+///
+pub fn error_chain(depth: RangeInclusive<usize>) -> BoxedStrategy<Error> {
+    assert!(!depth.is_empty() && *depth.start() > 0, "the depth must be at least 1");
+    depth.prop_flat_map(chain_of).boxed()
+}
+
+/// Returns a strategy generating `MultiError`s of chains.
+///
+/// # Arguments
+///
+/// * len - Range of the number of errors.
+/// * depth - Range of the number of errors in each chain.
+///
+/// # Panics
+///
+/// Panics if `depth` is empty or starts at 0.
+///
+pub fn multi_error(len: RangeInclusive<usize>, depth: RangeInclusive<usize>) -> BoxedStrategy<MultiError> {
+    collection::vec(error_chain(depth), len).prop_map(MultiError::from).boxed()
+}
+
+fn chain_of(depth: usize) -> BoxedStrategy<Error> {
+    if depth <= 1 {
+        return leaf();
+    }
+    let kind = (option::of(message()), chain_of(depth - 1)).prop_map(|(message, inner)| {
+        match message {
+            Some(message) => ErrorKind::Context(message, Box::new(inner)),
+            None => ErrorKind::Wrapped(Box::new(inner)),
+        }
+    });
+    located(kind.boxed())
+}
+
+fn located(kind: BoxedStrategy<ErrorKind>) -> BoxedStrategy<Error> {
+    let metadata = collection::vec(("[a-z]{1,8}", message()), 0..=2).prop_map(|entries| {
+        let mut metadata = Metadata::new();
+        for (key, value) in entries {
+            metadata.insert_keyed(key, value);
+        }
+        metadata
+    });
+    (file(), 1..10_000u32, option::of(code()), metadata, kind)
+        .prop_map(|(file, line, code, metadata, kind)| Error {
+            file: Cow::Owned(file),
+            line,
+            kind,
+            code: code.map(Cow::Owned),
+            metadata,
+        })
+        .boxed()
+}
@@ -0,0 +1,53 @@
+//! Measuring how long failed operations ran.
+
+use std::error;
+use std::marker;
+use std::panic;
+use std::result;
+use std::time::{Duration, Instant};
+
+use super::{metadata, Error, Result};
+
+/// Runs `f`, attaching how long it ran to the error if it fails.
+///
+/// The `Duration` is attached under `metadata::ELAPSED`. If `f` fails with an
+/// `Error`, it is attached to that error; otherwise the error is wrapped by
+/// an `Error` located where this function is called.
+///
+/// # Arguments
+///
+/// * f - Operation to run.
+///
+/// # Examples
+///
+/// ```
+/// use std::time::Duration;
+///
+/// use mm_errors::{metadata, timing};
+///
+/// let e = timing::timed(|| "XXX".parse::<u32>()).unwrap_err();
+/// assert!(e.metadata.get_keyed::<Duration>(metadata::ELAPSED).is_some());
+/// assert!(e.to_string().contains("<meta key=\"elapsed\">"));
+///
+/// assert_eq!(timing::timed(|| "1".parse::<u32>()).unwrap(), 1);
+/// ```
+///
+#[track_caller]
+pub fn timed<F, T, E>(f: F) -> Result<T>
+    where F: FnOnce() -> result::Result<T, E>,
+          E: Into<Box<dyn error::Error + marker::Send + marker::Sync>> {
+    let location = panic::Location::caller();
+    let start = Instant::now();
+    f().map_err(|e| with_elapsed(e, start.elapsed(), location))
+}
+
+/// Attaches `elapsed` to `e`, wrapping it at `location` unless it is an `Error`.
+pub(crate) fn with_elapsed<E>(e: E, elapsed: Duration, location: &'static panic::Location<'static>) -> Error
+    where E: Into<Box<dyn error::Error + marker::Send + marker::Sync>> {
+    let mut e = match e.into().downcast::<Error>() {
+        Ok(e) => *e,
+        Err(e) => Error::wrap(e, location.file(), location.line()),
+    };
+    e.metadata.insert_volatile(metadata::ELAPSED, elapsed);
+    e
+}
@@ -0,0 +1,166 @@
+//! Opaque tokens of errors for support tickets.
+
+use super::{Error, Result};
+
+/// Prefix of tokens written by `Error::to_token`.
+const PLAIN: &str = "mme1.";
+
+/// Prefix of tokens written by `Error::to_encrypted_token`.
+#[cfg(feature = "encrypt")]
+const ENCRYPTED: &str = "mme1e.";
+
+const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+impl Error {
+    /// Returns `self` as a short opaque token, e.g. for users to paste into support tickets.
+    ///
+    /// The token is the JSON of `to_json`, compressed with gzip if the `gzip`
+    /// feature is enabled, and encoded as URL-safe base64 after the prefix
+    /// `mme1.`. It is not encrypted, so it discloses whatever the JSON holds;
+    /// see `to_encrypted_token`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mm_errors::Error;
+    ///
+    /// let e = Error::context(Error::new("disk full", "storage.rs", 7), "saving", "service.rs", 21);
+    /// let token = e.to_token();
+    /// assert!(token.starts_with("mme1."));
+    ///
+    /// assert_eq!(Error::from_token(&token).unwrap().to_json(), e.to_json());
+    /// ```
+    ///
+    pub fn to_token(&self) -> String {
+        let mut token = PLAIN.to_string();
+        encode(&payload(self), &mut token);
+        token
+    }
+
+    /// Parses a token written by `to_token`.
+    ///
+    /// Whitespace in `token` is ignored, since pasted tokens are often broken into lines.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `token` is not a token written by `to_token`.
+    ///
+    pub fn from_token(token: &str) -> Result<Error> {
+        let token: String = token.split_whitespace().collect();
+        let data = token.strip_prefix(PLAIN).ok_or_else(|| Error::here("not an error token"))?;
+        from_payload(&decode(data)?)
+    }
+
+    /// Returns `self` as a token encrypted with `key`.
+    ///
+    /// The payload of `to_token` is encrypted with ChaCha20-Poly1305 and a
+    /// random nonce, and the token starts with `mme1e.`. Only holders of
+    /// `key` can read it.
+    ///
+    /// This method is available with the `encrypt` feature.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mm_errors::Error;
+    ///
+    /// let key = [7u8; 32];
+    /// let e = Error::new("disk full", "storage.rs", 7);
+    /// let token = e.to_encrypted_token(&key);
+    ///
+    /// assert_eq!(Error::from_encrypted_token(&token, &key).unwrap().to_json(), e.to_json());
+    /// assert!(Error::from_encrypted_token(&token, &[8u8; 32]).is_err());
+    /// ```
+    ///
+    #[cfg(feature = "encrypt")]
+    pub fn to_encrypted_token(&self, key: &[u8; 32]) -> String {
+        use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+        use chacha20poly1305::ChaCha20Poly1305;
+
+        let cipher = ChaCha20Poly1305::new(key.into());
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let mut data = nonce.to_vec();
+        data.extend(cipher.encrypt(&nonce, &*payload(self)).expect("encrypting into a `Vec` never fails"));
+        let mut token = ENCRYPTED.to_string();
+        encode(&data, &mut token);
+        token
+    }
+
+    /// Parses a token written by `to_encrypted_token` with the same `key`.
+    ///
+    /// This method is available with the `encrypt` feature.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `token` is not an encrypted token, or was not
+    /// encrypted with `key`.
+    ///
+    #[cfg(feature = "encrypt")]
+    pub fn from_encrypted_token(token: &str, key: &[u8; 32]) -> Result<Error> {
+        use chacha20poly1305::aead::{Aead, KeyInit};
+        use chacha20poly1305::{ChaCha20Poly1305, Nonce};
+
+        let token: String = token.split_whitespace().collect();
+        let data = token.strip_prefix(ENCRYPTED).ok_or_else(|| Error::here("not an encrypted error token"))?;
+        let data = decode(data)?;
+        if data.len() < 12 {
+            return Err(Error::here("encrypted error token is too short"));
+        }
+        let (nonce, ciphertext) = data.split_at(12);
+        let cipher = ChaCha20Poly1305::new(key.into());
+        let payload = cipher.decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| Error::here("failed to decrypt the error token"))?;
+        from_payload(&payload)
+    }
+}
+
+/// Returns the bytes encoded in a token of `e`.
+fn payload(e: &Error) -> Vec<u8> {
+    #[cfg(feature = "gzip")]
+    return super::compress::compress(e.to_json().as_bytes(), super::compress::Method::Gzip);
+
+    #[cfg(not(feature = "gzip"))]
+    return e.to_json().into_bytes();
+}
+
+fn from_payload(payload: &[u8]) -> Result<Error> {
+    #[cfg(any(feature = "gzip", feature = "zstd"))]
+    return Error::from_json_compressed(payload);
+
+    #[cfg(not(any(feature = "gzip", feature = "zstd")))]
+    {
+        let json = std::str::from_utf8(payload)
+            .map_err(|e| Error::context(e, "decoding the error token", file!(), line!()))?;
+        Error::from_json(json)
+    }
+}
+
+/// Appends `data` to `out` as URL-safe base64 without padding.
+fn encode(data: &[u8], out: &mut String) {
+    for chunk in data.chunks(3) {
+        let n = chunk.iter().enumerate().fold(0u32, |n, (i, &b)| n | u32::from(b) << (16 - 8 * i));
+        for i in 0..=chunk.len() {
+            out.push(ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+        }
+    }
+}
+
+/// Decodes URL-safe base64 without padding.
+fn decode(s: &str) -> Result<Vec<u8>> {
+    let mut out = Vec::with_capacity(s.len() * 3 / 4);
+    for chunk in s.as_bytes().chunks(4) {
+        if chunk.len() == 1 {
+            return Err(Error::here("invalid length of the error token"));
+        }
+        let mut n = 0u32;
+        for (i, &c) in chunk.iter().enumerate() {
+            let v = ALPHABET.iter().position(|&a| a == c)
+                .ok_or_else(|| Error::here("invalid character in the error token"))?;
+            n |= (v as u32) << (18 - 6 * i);
+        }
+        for i in 0..chunk.len() - 1 {
+            out.push((n >> (16 - 8 * i)) as u8);
+        }
+    }
+    Ok(out)
+}
@@ -0,0 +1,186 @@
+//! Limiting the size of serialized errors.
+//!
+//! Deep chains, e.g. of errors wrapped at every level of a recursive
+//! operation, can exceed the size limits of log lines or message queues.
+//! `Limits` removes levels from the middle of such chains, keeping the
+//! outermost levels, which tell what was being done, and the innermost ones,
+//! which tell what went wrong. The removed levels are replaced by a single
+//! level with the context `N levels omitted`, located at `[omitted]` line 0,
+//! so the output stays parseable by `Error::from_xml` and `Error::from_json`.
+
+use std::borrow::Cow;
+
+use super::{Error, ErrorKind, Metadata};
+
+/// File of the level replacing the omitted levels.
+pub const OMITTED_FILE: &str = "[omitted]";
+
+/// Budget of levels and bytes for serialized errors.
+///
+/// Only the chain of nested `Error`s counts as levels; the errors inside a
+/// `MultiError` or a `RemoteError` are kept as they are.
+///
+/// # Examples
+///
+/// ```
+/// use mm_errors::Error;
+/// use mm_errors::truncate::Limits;
+///
+/// let mut e = Error::new("disk full", "storage.rs", 1);
+/// for line in 2..=10 {
+///     e = Error::context(e, "retrying", "retry.rs", line);
+/// }
+///
+/// let limits = Limits::new().max_levels(4);
+/// let e = limits.truncate(&e);
+/// assert_eq!(e.chain().count(), 5);
+/// assert!(e.to_string().contains("<context>6 levels omitted</context>"));
+/// assert!(e.root_cause().to_string().contains("disk full"));
+///
+/// let json = Limits::new().max_bytes(400).to_json(&e);
+/// assert!(json.len() <= 400);
+/// ```
+///
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Limits {
+    max_levels: Option<usize>,
+    max_bytes: Option<usize>,
+}
+
+impl Limits {
+    /// Returns a new instance of `Limits` without any limit.
+    pub fn new() -> Limits {
+        Limits::default()
+    }
+
+    /// Returns `self` with the number of levels kept limited to `max_levels`.
+    ///
+    /// At least the outermost level and the root cause are always kept, so
+    /// values below 2 are treated as 2.
+    ///
+    pub fn max_levels(mut self, max_levels: usize) -> Limits {
+        self.max_levels = Some(max_levels.max(2));
+        self
+    }
+
+    /// Returns `self` with the size of the output limited to `max_bytes`.
+    ///
+    /// Levels are removed until the output fits. If it still doesn't fit with
+    /// only the outermost level and the root cause, it is written as it is.
+    ///
+    pub fn max_bytes(mut self, max_bytes: usize) -> Limits {
+        self.max_bytes = Some(max_bytes);
+        self
+    }
+
+    /// Returns a copy of `e` with levels removed to meet the level limit.
+    pub fn truncate(&self, e: &Error) -> Error {
+        match self.max_levels {
+            Some(max) => keep_levels(e, max),
+            None => e.clone(),
+        }
+    }
+
+    /// Returns `e` as XML, as written by `Display`, within the limits.
+    pub fn to_xml(&self, e: &Error) -> String {
+        self.fit(e, |e| e.to_string())
+    }
+
+    /// Returns `e` as JSON, as written by `Error::to_json`, within the limits.
+    pub fn to_json(&self, e: &Error) -> String {
+        self.fit(e, Error::to_json)
+    }
+
+    fn fit<F>(&self, e: &Error, write: F) -> String
+        where F: Fn(&Error) -> String {
+        let most = self.max_levels.unwrap_or(usize::MAX).min(depth(e));
+        let out = write(&keep_levels(e, most));
+        let max = match self.max_bytes {
+            Some(max) if out.len() > max && most > 2 => max,
+            _ => return out,
+        };
+        let mut fitting = write(&keep_levels(e, 2));
+        if fitting.len() > max {
+            return fitting;
+        }
+
+        // The output grows with the levels kept, so binary search the most
+        // levels which fit, between 2, which fit, and `most`, which don't.
+        let (mut low, mut high) = (2, most - 1);
+        while low < high {
+            let mid = low + (high - low).div_ceil(2);
+            let out = write(&keep_levels(e, mid));
+            if out.len() <= max {
+                low = mid;
+                fitting = out;
+            } else {
+                high = mid - 1;
+            }
+        }
+        fitting
+    }
+}
+
+/// Returns the nested `Error`s of the chain of `e`, outermost first.
+fn levels(e: &Error) -> Vec<&Error> {
+    let mut levels = vec![e];
+    let mut current = e;
+    while let ErrorKind::Wrapped(ref inner) | ErrorKind::Context(_, ref inner) = current.kind {
+        match inner.downcast_ref::<Error>() {
+            Some(inner) => {
+                levels.push(inner);
+                current = inner;
+            }
+            None => break,
+        }
+    }
+    levels
+}
+
+fn depth(e: &Error) -> usize {
+    levels(e).len()
+}
+
+/// Returns a copy of `e` keeping `max` levels of the chain, at least 2.
+fn keep_levels(e: &Error, max: usize) -> Error {
+    let levels = levels(e);
+    let max = max.max(2);
+    if levels.len() <= max {
+        return e.clone();
+    }
+
+    let inner = max / 2;
+    let outer = max - inner;
+    let omitted = levels.len() - max;
+
+    let mut acc = levels[levels.len() - 1].clone();
+    for level in levels[levels.len() - inner..levels.len() - 1].iter().rev() {
+        acc = with_inner(level, acc);
+    }
+    acc = Error {
+        file: Cow::Borrowed(OMITTED_FILE),
+        line: 0,
+        kind: ErrorKind::Context(format!("{} levels omitted", omitted), Box::new(acc)),
+        code: None,
+        metadata: Metadata::new(),
+    };
+    for level in levels[..outer].iter().rev() {
+        acc = with_inner(level, acc);
+    }
+    acc
+}
+
+/// Returns a copy of the level `e` holding `inner` as its inner error.
+fn with_inner(e: &Error, inner: Error) -> Error {
+    let kind = match e.kind {
+        ErrorKind::Context(ref context, _) => ErrorKind::Context(context.clone(), Box::new(inner)),
+        _ => ErrorKind::Wrapped(Box::new(inner)),
+    };
+    Error {
+        file: e.file.clone(),
+        line: e.line,
+        kind,
+        code: e.code.clone(),
+        metadata: e.metadata.clone(),
+    }
+}
@@ -0,0 +1,316 @@
+//! Parser for the XML format written by `Display`.
+
+use std::borrow::Cow;
+use std::error;
+use std::marker;
+use std::sync::Arc;
+
+use super::io::IoContext;
+use super::{metadata, Error, ErrorKind, Metadata, MultiError, RemoteError, Result};
+
+/// Parses `s` as an `<error>` element, including nested elements.
+pub(crate) fn parse(s: &str) -> Result<Error> {
+    let mut parser = Parser { s, pos: 0 };
+    let e = parser.error(0)?;
+    parser.skip_ws();
+    if parser.pos != s.len() {
+        return Err(parser.fail("unexpected content after `</error>`"));
+    }
+    Ok(e)
+}
+
+/// Maximum nesting depth of elements, so malicious input can't overflow the stack.
+const MAX_DEPTH: usize = 512;
+
+struct Parser<'a> {
+    s: &'a str,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn rest(&self) -> &'a str {
+        &self.s[self.pos..]
+    }
+
+    fn fail(&self, message: &str) -> Error {
+        Error::new(&format!("invalid error XML at offset {}: {}", self.pos, message),
+                   file!(), line!())
+    }
+
+    fn skip_ws(&mut self) {
+        let rest = self.rest();
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    fn eat(&mut self, token: &str) -> bool {
+        if self.rest().starts_with(token) {
+            self.pos += token.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, token: &str) -> Result<()> {
+        if self.eat(token) {
+            Ok(())
+        } else {
+            Err(self.fail(&format!("expected `{}`", token)))
+        }
+    }
+
+    /// Reads text up to the next `end` and unescapes it.
+    fn until(&mut self, end: char) -> Result<String> {
+        let len = match self.rest().find(end) {
+            Some(len) => len,
+            None => return Err(self.fail(&format!("expected `{}`", end))),
+        };
+        let raw = &self.rest()[..len];
+        let text = unescape(raw).ok_or_else(|| self.fail("invalid character reference"))?;
+        self.pos += len;
+        Ok(text)
+    }
+
+    fn errors(&mut self, depth: usize) -> Result<MultiError> {
+        self.expect("<errors>")?;
+        let mut errors = MultiError::new();
+        loop {
+            self.skip_ws();
+            if self.eat("</errors>") {
+                return Ok(errors);
+            }
+            errors.push(self.error(depth + 1)?);
+        }
+    }
+
+    fn remote(&mut self, depth: usize) -> Result<RemoteError> {
+        self.expect("<remote")?;
+        let (mut host, mut service) = (None, None);
+        loop {
+            self.skip_ws();
+            if self.eat(">") {
+                break;
+            }
+            let name = self.until('=')?;
+            self.expect("=\"")?;
+            let value = self.until('"')?;
+            self.expect("\"")?;
+            match name.trim() {
+                "host" => host = Some(value),
+                "service" => service = Some(value),
+                _ => {}
+            }
+        }
+        let error = self.error(depth + 1)?;
+        self.skip_ws();
+        self.expect("</remote>")?;
+        match (host, service) {
+            (Some(host), Some(service)) => Ok(RemoteError::new(host, service, error)),
+            _ => Err(self.fail("expected `host` and `service` attributes")),
+        }
+    }
+
+    fn error(&mut self, depth: usize) -> Result<Error> {
+        if depth > MAX_DEPTH {
+            return Err(self.fail("nesting too deep"));
+        }
+        let (file, line) = self.location(depth == 0)?;
+        let (reason, text) = self.reason(depth)?;
+        self.rest_of_error(file, line, reason, text)
+    }
+
+    /// Reads the start of an `<error>` element up to `<reason>`, returning the file and line.
+    fn location(&mut self, root: bool) -> Result<(String, u32)> {
+        self.skip_ws();
+        self.expect("<error")?;
+        loop {
+            self.skip_ws();
+            if self.eat(">") {
+                break;
+            }
+            let name = self.until('=')?;
+            self.expect("=\"")?;
+            let value = self.until('"')?;
+            self.expect("\"")?;
+            if root && name.trim() == "schema" && !value.parse().is_ok_and(super::is_supported_schema) {
+                return Err(self.fail(&format!("unsupported schema version `{}`", value)));
+            }
+        }
+
+        self.skip_ws();
+        self.expect("<file>")?;
+        let file = self.until('<')?;
+        self.expect("</file>")?;
+
+        self.skip_ws();
+        self.expect("<line>")?;
+        let line = self.until('<')?;
+        let line = line.trim().parse().map_err(|_| self.fail("invalid line number"))?;
+        self.expect("</line>")?;
+
+        self.skip_ws();
+        self.expect("<reason>")?;
+        Ok((file, line))
+    }
+
+    /// Reads the content of `<reason>`, returning whether it is text.
+    ///
+    /// Kept apart from the rest of `error`, so the frames of nested errors stay small.
+    ///
+    fn reason(&mut self, depth: usize) -> Result<(Box<dyn error::Error + marker::Send + marker::Sync>, bool)> {
+        let start = self.pos;
+        self.skip_ws();
+        let reason: (Box<dyn error::Error + marker::Send + marker::Sync>, bool) =
+            if self.rest().starts_with("<errors") {
+                let inner = self.errors(depth + 1)?;
+                self.skip_ws();
+                (Box::new(inner), false)
+            } else if self.rest().starts_with("<remote") {
+                let inner = self.remote(depth + 1)?;
+                self.skip_ws();
+                (Box::new(inner), false)
+            } else if self.rest().starts_with("<error") {
+                let inner = self.error(depth + 1)?;
+                self.skip_ws();
+                (Box::new(inner), false)
+            } else {
+                self.pos = start;
+                (self.until('<')?.into(), true)
+            };
+        self.expect("</reason>")?;
+        Ok(reason)
+    }
+
+    /// Reads the rest of an `<error>` element after `</reason>`.
+    fn rest_of_error(&mut self,
+                     file: String,
+                     line: u32,
+                     reason: Box<dyn error::Error + marker::Send + marker::Sync>,
+                     text: bool)
+                     -> Result<Error> {
+        self.skip_ws();
+        let kind = if self.eat("<context>") {
+            let context = self.until('<')?;
+            self.expect("</context>")?;
+            ErrorKind::Context(context, reason)
+        } else if text && self.eat("<cancelled/>") {
+            ErrorKind::Cancelled(reason.to_string())
+        } else if text {
+            ErrorKind::String(reason.to_string())
+        } else {
+            ErrorKind::Wrapped(reason)
+        };
+
+        self.skip_ws();
+        let code = if self.eat("<code>") {
+            let code = self.until('<')?;
+            self.expect("</code>")?;
+            self.skip_ws();
+            Some(Cow::Owned(code))
+        } else {
+            None
+        };
+
+        let mut metadata = Metadata::new();
+        while self.eat("<meta key=\"") {
+            let key = self.until('"')?;
+            self.expect("\">")?;
+            let value = self.until('<')?;
+            self.expect("</meta>")?;
+            self.skip_ws();
+            let parsed = unquote(&value).unwrap_or_else(|| value.clone());
+            metadata.insert_entry(Cow::Owned(key), Arc::new(parsed), value, None);
+        }
+
+        if self.eat("<io>") {
+            self.skip_ws();
+            self.expect("<operation>")?;
+            let operation = self.until('<')?;
+            self.expect("</operation>")?;
+            self.skip_ws();
+            self.expect("<path>")?;
+            let path = self.until('<')?;
+            self.expect("</path>")?;
+            self.skip_ws();
+            let mut io = IoContext::new(operation, path);
+            if self.eat("<bytes>") {
+                let bytes = self.until('<')?;
+                io.bytes = Some(bytes.trim().parse().map_err(|_| self.fail("invalid number of bytes"))?);
+                self.expect("</bytes>")?;
+                self.skip_ws();
+            }
+            self.expect("</io>")?;
+            self.skip_ws();
+            metadata.insert_keyed(metadata::IO, io);
+        }
+
+        self.expect("</error>")?;
+
+        Ok(Error {
+            file: Cow::Owned(file),
+            line,
+            kind,
+            code,
+            metadata,
+        })
+    }
+}
+
+/// Replaces entity and character references in `s`.
+fn unescape(s: &str) -> Option<String> {
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(i) = rest.find('&') {
+        out.push_str(&rest[..i]);
+        rest = &rest[i + 1..];
+        let end = rest.find(';')?;
+        let c = match &rest[..end] {
+            "amp" => '&',
+            "lt" => '<',
+            "gt" => '>',
+            "quot" => '"',
+            "apos" => '\'',
+            r if r.starts_with("#x") => u32::from_str_radix(&r[2..], 16).ok().and_then(char::from_u32)?,
+            r if r.starts_with('#') => r[1..].parse().ok().and_then(char::from_u32)?,
+            _ => return None,
+        };
+        out.push(c);
+        rest = &rest[end + 1..];
+    }
+    out.push_str(rest);
+    Some(out)
+}
+
+/// Reverses the `Debug` output of a string, returning `None` if `s` is not one.
+///
+/// Values attached as strings are then parsed back as the original strings.
+///
+pub(crate) fn unquote(s: &str) -> Option<String> {
+    let inner = s.strip_prefix('"')?.strip_suffix('"')?;
+    let mut out = String::with_capacity(inner.len());
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => return None,
+            '\\' => out.push(match chars.next()? {
+                'n' => '\n',
+                'r' => '\r',
+                't' => '\t',
+                '0' => '\0',
+                '\\' => '\\',
+                '\'' => '\'',
+                '"' => '"',
+                'u' => {
+                    let rest = chars.as_str().strip_prefix('{')?;
+                    let end = rest.find('}')?;
+                    let c = u32::from_str_radix(&rest[..end], 16).ok().and_then(char::from_u32)?;
+                    chars = rest[end + 1..].chars();
+                    c
+                }
+                _ => return None,
+            }),
+            c => out.push(c),
+        }
+    }
+    Some(out)
+}
@@ -0,0 +1,96 @@
+#![cfg(feature = "proptest")]
+
+use proptest::prelude::*;
+
+use mm_errors::{Error, MultiError};
+use mm_errors::testing::strategies;
+
+proptest! {
+    #[test]
+    fn xml_round_trips(e in strategies::error_chain(1..=6)) {
+        let xml = e.to_string();
+        prop_assert_eq!(Error::from_xml(&xml).unwrap().to_string(), xml);
+    }
+
+    #[test]
+    fn json_round_trips(e in strategies::error_chain(1..=6)) {
+        let json = e.to_json();
+        prop_assert_eq!(Error::from_json(&json).unwrap().to_json(), json);
+    }
+
+    #[test]
+    fn json_round_trips_any_text(message in any::<String>(), context in any::<String>(), code in any::<String>()) {
+        let e = Error::context(Error::new(&message, "a.rs", 1), &context, "b.rs", 2).with_code(code);
+        let json = e.to_json();
+        prop_assert_eq!(Error::from_json(&json).unwrap().to_json(), json);
+    }
+
+    #[test]
+    fn json_round_trips_aggregates(errors in strategies::multi_error(0..=4, 1..=3)) {
+        let json = errors.to_json();
+        prop_assert_eq!(MultiError::from_json(&json).unwrap().to_json(), json);
+
+        let e = Error::wrap(errors, "c.rs", 1);
+        let json = e.to_json();
+        prop_assert_eq!(Error::from_json(&json).unwrap().to_json(), json);
+    }
+
+    #[test]
+    fn streamed_json_matches_to_json(errors in strategies::multi_error(0..=4, 1..=3)) {
+        let mut out = Vec::new();
+        errors.serialize_into(&mut out).unwrap();
+        prop_assert_eq!(String::from_utf8(out).unwrap(), errors.to_json());
+    }
+
+    #[test]
+    fn json_and_xml_agree(e in strategies::error_chain(1..=6)) {
+        prop_assert_eq!(Error::from_json(&e.to_json()).unwrap().to_string(), e.to_string());
+    }
+
+    #[cfg(feature = "cbor")]
+    #[test]
+    fn cbor_round_trips(e in strategies::error_chain(1..=6)) {
+        prop_assert_eq!(Error::from_cbor(&e.to_cbor()).unwrap().to_json(), e.to_json());
+    }
+
+    #[cfg(feature = "prost")]
+    #[test]
+    fn protobuf_round_trips(e in strategies::error_chain(1..=6)) {
+        use std::convert::TryFrom;
+
+        use prost::Message;
+
+        let bytes = mm_errors::proto::Error::from(&e).encode_to_vec();
+        let decoded = Error::try_from(mm_errors::proto::Error::decode(&*bytes).unwrap()).unwrap();
+        prop_assert_eq!(decoded.to_json(), e.to_json());
+    }
+
+    #[test]
+    fn chain_has_the_generated_depth(
+        (depth, e) in (1..=6usize).prop_flat_map(|d| (Just(d), strategies::error_chain(d..=d)))
+    ) {
+        prop_assert_eq!(e.chain().count(), depth);
+    }
+
+    #[test]
+    fn root_cause_is_the_last_error_of_the_chain(e in strategies::error_chain(1..=6)) {
+        let last = e.chain().last().unwrap();
+        prop_assert!(std::ptr::eq(last, e.root_cause()));
+    }
+
+    #[test]
+    fn clone_renders_the_same(e in strategies::error_chain(1..=6)) {
+        prop_assert_eq!(e.clone().to_string(), e.to_string());
+    }
+
+    #[test]
+    fn multi_error_renders_every_error(errors in strategies::multi_error(0..=4, 1..=3)) {
+        let xml = errors.to_string();
+        for e in &errors {
+            let attributes = format!(" schema=\"2\" version=\"{}\"", mm_errors::CRATE_VERSION);
+            let inner = e.to_string().replacen(&attributes, "", 1);
+            prop_assert!(xml.contains(&inner));
+        }
+        prop_assert_eq!(MultiError::from(errors.errors.clone()).to_string(), xml);
+    }
+}